@@ -0,0 +1,35 @@
+// Times how long the VM takes to run scripts that stress the dispatch loop.
+// Run with `cargo bench` and compare the numbers before and after a change.
+use cedar::VM;
+use std::{
+  fs,
+  path::PathBuf,
+  time::{Duration, Instant},
+};
+
+const RUNS: u32 = 10;
+
+fn main() {
+  for script in &["arithmetic", "calls"] {
+    let path = PathBuf::from("benches")
+      .join("scripts")
+      .join(format!("{}.cdr", script));
+    let source = fs::read_to_string(&path).expect("Benchmark script exists");
+    let mut times = Vec::new();
+    for _ in 0..RUNS {
+      let mut vm = VM::new();
+      let start = Instant::now();
+      vm.interpret(source.clone()).expect("Benchmark script runs");
+      times.push(start.elapsed());
+    }
+    times.sort();
+    let mean = times.iter().sum::<Duration>() / RUNS;
+    println!(
+      "{:<12} min {:>10.3?}  mean {:>10.3?}  max {:>10.3?}",
+      script,
+      times[0],
+      mean,
+      times[times.len() - 1]
+    );
+  }
+}
@@ -0,0 +1,330 @@
+//! `#[derive(CedarValue)]` implements `cedar::native::IntoValue` and
+//! `cedar::native::FromValue` for structs and enums so they can be passed to
+//! and returned from natives or read out of globals.
+//!
+//! - Structs with named fields become maps keyed by field name.
+//! - Tuple structs become lists, except for newtypes which are the value they
+//!   wrap, and unit structs are null.
+//! - Unit variants of an enum are strings with the variant's name. Other
+//!   variants are a map with the variant's name as its only key.
+//!
+//! Fields and variants can be given a different name in Cedar with
+//! `#[cedar(rename = "new-name")]`.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+  parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Fields, Generics, Ident,
+  LitStr, Result,
+};
+
+#[proc_macro_derive(CedarValue, attributes(cedar))]
+pub fn derive_cedar_value(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  expand(input)
+    .unwrap_or_else(Error::into_compile_error)
+    .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2> {
+  let name = &input.ident;
+  let (into_value, try_from_value) = match &input.data {
+    Data::Struct(data) => (
+      struct_into_value(&data.fields)?,
+      struct_try_from_value(&data.fields)?,
+    ),
+    Data::Enum(data) => {
+      let variants = data
+        .variants
+        .iter()
+        .map(|v| Ok((&v.ident, cedar_name(&v.ident, &v.attrs)?, &v.fields)))
+        .collect::<Result<Vec<_>>>()?;
+      (enum_into_value(&variants)?, enum_try_from_value(&variants)?)
+    }
+    Data::Union(_) => {
+      return Err(Error::new(
+        Span::call_site(),
+        "CedarValue can't be derived for unions",
+      ))
+    }
+  };
+
+  let into_generics = bounded(&input.generics, quote!(::cedar::native::IntoValue));
+  let (impl_generics, ty_generics, where_clause) = into_generics.split_for_impl();
+  let into_impl = quote! {
+    impl #impl_generics ::cedar::native::IntoValue for #name #ty_generics #where_clause {
+      fn to_value(self) -> ::cedar::value::Value {
+        #into_value
+      }
+    }
+  };
+
+  let from_generics = bounded(&input.generics, quote!(::cedar::native::FromValue));
+  let (impl_generics, ty_generics, where_clause) = from_generics.split_for_impl();
+  let expected = name.to_string();
+  let from_impl = quote! {
+    impl #impl_generics ::cedar::native::FromValue for #name #ty_generics #where_clause {
+      fn from_value(value: ::cedar::value::Value) -> ::std::option::Option<Self> {
+        <Self as ::cedar::native::FromValue>::try_from_value(value).ok()
+      }
+      fn expected() -> ::std::borrow::Cow<'static, str> {
+        ::std::borrow::Cow::Borrowed(#expected)
+      }
+      #[allow(unused_variables)]
+      fn try_from_value(
+        value: ::cedar::value::Value,
+      ) -> ::std::result::Result<Self, ::cedar::native::ConversionError> {
+        let found = value.type_name();
+        let mismatch = || {
+          ::cedar::native::ConversionError::new(
+            <Self as ::cedar::native::FromValue>::expected(),
+            found,
+          )
+        };
+        #try_from_value
+      }
+    }
+  };
+
+  Ok(quote! {
+    #into_impl
+    #from_impl
+  })
+}
+
+// Every type parameter has to convert for the type as a whole to
+fn bounded(generics: &Generics, bound: TokenStream2) -> Generics {
+  let mut generics = generics.clone();
+  let params = generics
+    .type_params()
+    .map(|param| param.ident.clone())
+    .collect::<Vec<_>>();
+  let where_clause = generics.make_where_clause();
+  for param in params {
+    where_clause.predicates.push(parse_quote!(#param: #bound));
+  }
+  generics
+}
+
+// The name a field or variant goes by in Cedar
+fn cedar_name(ident: &Ident, attrs: &[Attribute]) -> Result<String> {
+  let mut name = ident.to_string();
+  for attr in attrs.iter().filter(|a| a.path().is_ident("cedar")) {
+    attr.parse_nested_meta(|meta| {
+      if meta.path.is_ident("rename") {
+        name = meta.value()?.parse::<LitStr>()?.value();
+        Ok(())
+      } else {
+        Err(meta.error("unknown cedar attribute, expected `rename`"))
+      }
+    })?;
+  }
+  Ok(name)
+}
+
+fn struct_into_value(fields: &Fields) -> Result<TokenStream2> {
+  let (bindings, pattern) = destructure(fields);
+  let value = fields_into_value(fields, &bindings)?;
+  Ok(quote! {
+    let Self #pattern = self;
+    #value
+  })
+}
+
+fn struct_try_from_value(fields: &Fields) -> Result<TokenStream2> {
+  match fields {
+    Fields::Unit => Ok(quote! {
+      match value {
+        ::cedar::value::Value::Null => Ok(Self),
+        _ => Err(mismatch()),
+      }
+    }),
+    fields => {
+      let convert = fields_try_from_value(fields, quote!(Self))?;
+      Ok(quote! {
+        let wrap = |e: ::cedar::native::ConversionError| e;
+        #convert
+      })
+    }
+  }
+}
+
+fn enum_into_value(variants: &[(&Ident, String, &Fields)]) -> Result<TokenStream2> {
+  let arms = variants
+    .iter()
+    .map(|(ident, name, fields)| {
+      Ok(match fields {
+        Fields::Unit => quote! {
+          Self::#ident => ::cedar::value::Value::from(#name),
+        },
+        fields => {
+          let (bindings, pattern) = destructure(fields);
+          let value = fields_into_value(fields, &bindings)?;
+          quote! {
+            Self::#ident #pattern => {
+              let mut map = ::std::collections::BTreeMap::new();
+              map.insert(::std::string::String::from(#name), { #value });
+              ::cedar::value::Value::from(map)
+            }
+          }
+        }
+      })
+    })
+    .collect::<Result<Vec<_>>>()?;
+  Ok(quote! {
+    match self {
+      #(#arms)*
+    }
+  })
+}
+
+fn enum_try_from_value(variants: &[(&Ident, String, &Fields)]) -> Result<TokenStream2> {
+  let names = variants
+    .iter()
+    .map(|(_, name, _)| format!("{:?}", name))
+    .collect::<Vec<_>>()
+    .join(", ");
+  let expected = format!("one of {}", names);
+  let unit = variants
+    .iter()
+    .filter(|(_, _, fields)| matches!(fields, Fields::Unit))
+    .map(|(ident, name, _)| quote!(#name => Ok(Self::#ident),));
+  let tagged = variants
+    .iter()
+    .filter(|(_, _, fields)| !matches!(fields, Fields::Unit))
+    .map(|(ident, name, fields)| {
+      let convert = fields_try_from_value(fields, quote!(Self::#ident))?;
+      Ok(quote! {
+        #name => {
+          let value = value.clone();
+          let wrap = |e: ::cedar::native::ConversionError| e.field(#name);
+          #convert
+        }
+      })
+    })
+    .collect::<Result<Vec<_>>>()?;
+  Ok(quote! {
+    let unknown = |variant: &str| {
+      ::cedar::native::ConversionError::new(#expected, format!("{:?}", variant))
+    };
+    match value {
+      ::cedar::value::Value::String(variant) => match variant.as_str() {
+        #(#unit)*
+        variant => Err(unknown(variant)),
+      },
+      ::cedar::value::Value::Map(map) if map.len() == 1 => {
+        let (variant, value) = map.iter().next().expect("map has one entry");
+        match variant.as_str() {
+          #(#tagged)*
+          variant => Err(unknown(variant)),
+        }
+      }
+      _ => Err(mismatch()),
+    }
+  })
+}
+
+// Names to bind each field to and the pattern that binds them
+fn destructure(fields: &Fields) -> (Vec<Ident>, TokenStream2) {
+  match fields {
+    Fields::Named(named) => {
+      let bindings = named
+        .named
+        .iter()
+        .map(|f| f.ident.clone().expect("named fields have names"))
+        .collect::<Vec<_>>();
+      let pattern = quote!({ #(#bindings),* });
+      (bindings, pattern)
+    }
+    Fields::Unnamed(unnamed) => {
+      let bindings = (0..unnamed.unnamed.len())
+        .map(|i| format_ident!("field{}", i))
+        .collect::<Vec<_>>();
+      let pattern = quote!(( #(#bindings),* ));
+      (bindings, pattern)
+    }
+    Fields::Unit => (Vec::new(), TokenStream2::new()),
+  }
+}
+
+// Build the value for fields that have been bound to `bindings`
+fn fields_into_value(fields: &Fields, bindings: &[Ident]) -> Result<TokenStream2> {
+  Ok(match fields {
+    Fields::Named(named) => {
+      let keys = named
+        .named
+        .iter()
+        .map(|f| cedar_name(f.ident.as_ref().expect("named fields have names"), &f.attrs))
+        .collect::<Result<Vec<_>>>()?;
+      quote! {
+        let mut map = ::std::collections::BTreeMap::new();
+        #(
+          map.insert(
+            ::std::string::String::from(#keys),
+            ::cedar::native::IntoValue::to_value(#bindings),
+          );
+        )*
+        ::cedar::value::Value::from(map)
+      }
+    }
+    Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+      let binding = &bindings[0];
+      quote!(::cedar::native::IntoValue::to_value(#binding))
+    }
+    Fields::Unnamed(_) => quote! {
+      ::cedar::value::Value::from(vec![
+        #(::cedar::native::IntoValue::to_value(#bindings)),*
+      ])
+    },
+    Fields::Unit => quote!(::cedar::value::Value::Null),
+  })
+}
+
+// Build `constructor` out of `value`, which is in scope along with a
+// `mismatch` closure for when it's the wrong type and a `wrap` closure that
+// every error goes through
+fn fields_try_from_value(fields: &Fields, constructor: TokenStream2) -> Result<TokenStream2> {
+  Ok(match fields {
+    Fields::Named(named) => {
+      let idents = named
+        .named
+        .iter()
+        .map(|f| f.ident.clone().expect("named fields have names"));
+      let keys = named
+        .named
+        .iter()
+        .map(|f| cedar_name(f.ident.as_ref().expect("named fields have names"), &f.attrs))
+        .collect::<Result<Vec<_>>>()?;
+      quote! {
+        let map = value.into_map().ok_or_else(|| wrap(mismatch()))?;
+        Ok(#constructor {
+          #(#idents: ::cedar::native::field(&map, #keys).map_err(&wrap)?,)*
+        })
+      }
+    }
+    Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => quote! {
+      ::cedar::native::FromValue::try_from_value(value)
+        .map(#constructor)
+        .map_err(wrap)
+    },
+    Fields::Unnamed(unnamed) => {
+      let len = unnamed.unnamed.len();
+      let indices = 0..len;
+      quote! {
+        let list = value.into_list().ok_or_else(|| wrap(mismatch()))?;
+        if list.len() != #len {
+          return Err(wrap(mismatch()));
+        }
+        Ok(#constructor(
+          #(
+            ::cedar::native::FromValue::try_from_value(list[#indices].clone())
+              .map_err(|e| wrap(e.index(#indices)))?,
+          )*
+        ))
+      }
+    }
+    Fields::Unit => quote!(Ok(#constructor)),
+  })
+}
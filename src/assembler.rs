@@ -0,0 +1,390 @@
+use crate::{
+  chunk::{Chunk, OpCode, Operand},
+  value::{Function, Value},
+};
+use std::{borrow::Cow, collections::HashMap, fmt, str::FromStr};
+
+/// Turn a textual assembly listing, in the format produced by
+/// `Chunk::disassemble`, back into a chunk. Instruction offsets in the listing
+/// are informational only and ignored, so instructions can be added or removed
+/// by hand as long as jumps refer to labels rather than raw offsets.
+///
+/// ```text
+/// .chunk MAIN
+/// .const 0 string "Hello"
+/// .code
+/// 0000 [line 1] Constant 0 ; string "Hello"
+/// 0002 [line 1] Print
+/// 0003 [line 1] Null
+/// 0004 [line 1] Return
+/// .end
+/// ```
+pub fn assemble(source: &str) -> Result<Chunk, AssemblerError> {
+  let mut assembler = Assembler {
+    builders: Vec::new(),
+    chunk: None,
+  };
+  for (i, line) in source.lines().enumerate() {
+    assembler
+      .line(line)
+      .map_err(|message| AssemblerError::new(i + 1, message))?;
+  }
+  if !assembler.builders.is_empty() {
+    return Err(AssemblerError::new(
+      source.lines().count(),
+      "Expected '.end' before the end of input",
+    ));
+  }
+  assembler
+    .chunk
+    .ok_or_else(|| AssemblerError::new(0, "No '.chunk' found in input"))
+}
+
+struct Assembler {
+  builders: Vec<Builder>,
+  chunk: Option<Chunk>,
+}
+
+impl Assembler {
+  fn line(&mut self, line: &str) -> Result<(), Cow<'static, str>> {
+    let tokens = tokenize(line)?;
+    let mut tokens = tokens.iter();
+    let first = match tokens.next() {
+      Some(first) => first,
+      None => return Ok(()),
+    };
+    if self.chunk.is_some() {
+      return Err("Unexpected input after the end of the chunk".into());
+    }
+    match first.word() {
+      Some(".chunk") => {
+        if !self.builders.is_empty() {
+          return Err("'.chunk' can only be used at the start of the listing".into());
+        }
+        self.builders.push(Builder::new(None));
+        Ok(())
+      }
+      Some(".const") => {
+        let builder = self.current()?;
+        if builder.in_code {
+          return Err("Constants must be listed before '.code'".into());
+        }
+        let index = parse::<usize>(tokens.next())?;
+        if index != builder.chunk.constants.len() {
+          return Err(
+            format!(
+              "Expected constant {} but found {}",
+              builder.chunk.constants.len(),
+              index
+            )
+            .into(),
+          );
+        }
+        let value = match word(tokens.next())? {
+          "number" => Value::Number(parse(tokens.next())?),
+          "bool" => Value::Bool(parse(tokens.next())?),
+          "byte" => Value::Byte(parse(tokens.next())?),
+          "null" => Value::Null,
+          "string" => Value::from(string(tokens.next())?),
+          "function" => {
+            let name = string(tokens.next())?;
+            let arity = parse(tokens.next())?;
+            self.builders.push(Builder::new(Some((name, arity))));
+            return end_of_line(tokens.next());
+          }
+          kind => return Err(format!("Constants of type '{}' can not be assembled", kind).into()),
+        };
+        builder.chunk.constants.push(value);
+        end_of_line(tokens.next())
+      }
+      Some(".code") => {
+        self.current()?.in_code = true;
+        end_of_line(tokens.next())
+      }
+      Some(".end") => {
+        let builder = self
+          .builders
+          .pop()
+          .ok_or("'.end' without a matching '.chunk' or function")?;
+        let (function, chunk) = builder.finish()?;
+        match (self.builders.last_mut(), function) {
+          (Some(parent), Some((name, arity))) => {
+            parent.chunk.constants.push(Value::from(Function {
+              arity,
+              chunk,
+              name: name.into(),
+            }))
+          }
+          (None, None) => self.chunk = Some(chunk),
+          _ => unreachable!(),
+        }
+        end_of_line(tokens.next())
+      }
+      Some(label) if label.ends_with(':') => {
+        let builder = self.current()?;
+        let label = &label[..label.len() - 1];
+        if builder
+          .labels
+          .insert(label.to_string(), builder.chunk.code.len())
+          .is_some()
+        {
+          return Err(format!("Label '{}' is defined more than once", label).into());
+        }
+        end_of_line(tokens.next())
+      }
+      _ => {
+        let builder = self.current()?;
+        if !builder.in_code {
+          return Err("Instructions must come after '.code'".into());
+        }
+        // Skip the offset, we recalculate it ourselves
+        let mut token = Some(first);
+        if token.and_then(Token::word).map(is_number).unwrap_or(false) {
+          token = tokens.next();
+        }
+        if let Some("[line") = token.and_then(Token::word) {
+          let line = word(tokens.next())?;
+          builder.line = line
+            .strip_suffix(']')
+            .ok_or("Expected ']' after line number")?
+            .parse()
+            .map_err(|_| format!("Invalid line number '{}'", line))?;
+          token = tokens.next();
+        }
+        let mnemonic = word(token)?;
+        if mnemonic == ".byte" {
+          let byte = parse(tokens.next())?;
+          builder.emit(byte);
+          return end_of_line(tokens.next());
+        }
+        let op = OpCode::from_str(mnemonic).map_err(|e| e.to_string())?;
+        builder.emit(op.into());
+        for operand in op.operands() {
+          match (operand, tokens.next()) {
+            (Operand::Jump, Some(Token::Word(label))) if !is_number(label) => {
+              builder
+                .jumps
+                .push((builder.chunk.code.len(), op, label.to_string()));
+              builder.emit(0xff);
+              builder.emit(0xff);
+            }
+            (Operand::Jump, token) | (Operand::Global, token) => {
+              let operand: u16 = parse(token)?;
+              builder.emit((operand >> 8) as u8);
+              builder.emit((operand & 0xff) as u8);
+            }
+            (Operand::OpCode, Some(Token::Word(name))) if !is_number(name) => {
+              let op = OpCode::from_str(name).map_err(|e| e.to_string())?;
+              builder.emit(op.into());
+            }
+            (_, token) => {
+              let operand = parse(token)?;
+              builder.emit(operand);
+            }
+          }
+        }
+        // Comments about the operands start with ';' and are already gone
+        end_of_line(tokens.next())
+      }
+    }
+  }
+  fn current(&mut self) -> Result<&mut Builder, Cow<'static, str>> {
+    self
+      .builders
+      .last_mut()
+      .ok_or_else(|| "Expected '.chunk' before any other input".into())
+  }
+}
+
+struct Builder {
+  function: Option<(String, usize)>,
+  chunk: Chunk,
+  in_code: bool,
+  line: usize,
+  labels: HashMap<String, usize>,
+  // Offset of the jump operand, the jump instruction and the label to patch in
+  jumps: Vec<(usize, OpCode, String)>,
+}
+
+impl Builder {
+  fn new(function: Option<(String, usize)>) -> Self {
+    Self {
+      function,
+      chunk: Chunk::new(),
+      in_code: false,
+      line: 1,
+      labels: HashMap::new(),
+      jumps: Vec::new(),
+    }
+  }
+  fn emit(&mut self, byte: u8) {
+    self.chunk.write_byte(byte);
+    self.chunk.lines.push(self.line);
+  }
+  #[allow(clippy::type_complexity)]
+  fn finish(mut self) -> Result<(Option<(String, usize)>, Chunk), Cow<'static, str>> {
+    for (offset, op, label) in &self.jumps {
+      let target = *self
+        .labels
+        .get(label)
+        .ok_or_else(|| format!("Label '{}' is not defined", label))?;
+      let next = offset + 2;
+      let jump = if *op == OpCode::Loop {
+        next.checked_sub(target)
+      } else {
+        target.checked_sub(next)
+      }
+      .filter(|jump| *jump <= u16::MAX as usize)
+      .ok_or_else(|| format!("{} can not reach label '{}'", op, label))?;
+      self.chunk.code[*offset] = ((jump >> 8) & 0xff) as u8;
+      self.chunk.code[offset + 1] = (jump & 0xff) as u8;
+    }
+    Ok((self.function, self.chunk))
+  }
+}
+
+enum Token<'a> {
+  Word(&'a str),
+  String(String),
+}
+
+impl<'a> Token<'a> {
+  fn word(&self) -> Option<&'a str> {
+    match self {
+      Token::Word(w) => Some(w),
+      Token::String(_) => None,
+    }
+  }
+}
+
+/// Split a line into whitespace separated words and quoted strings, dropping
+/// any comment that starts with ';'
+fn tokenize(line: &str) -> Result<Vec<Token<'_>>, Cow<'static, str>> {
+  let mut tokens = Vec::new();
+  let mut chars = line.char_indices().peekable();
+  while let Some(&(start, c)) = chars.peek() {
+    if c.is_whitespace() {
+      chars.next();
+    } else if c == ';' {
+      break;
+    } else if c == '"' {
+      chars.next();
+      let mut string = String::new();
+      loop {
+        match chars.next() {
+          Some((_, '"')) => break,
+          Some((_, '\\')) => string.push(unescape(&mut chars)?),
+          Some((_, c)) => string.push(c),
+          None => return Err("Unterminated string".into()),
+        }
+      }
+      tokens.push(Token::String(string));
+    } else {
+      let mut end = line.len();
+      while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() || c == ';' {
+          end = i;
+          break;
+        }
+        chars.next();
+      }
+      tokens.push(Token::Word(&line[start..end]));
+    }
+  }
+  Ok(tokens)
+}
+
+fn unescape<I>(chars: &mut I) -> Result<char, Cow<'static, str>>
+where
+  I: Iterator<Item = (usize, char)>,
+{
+  Ok(match chars.next().map(|(_, c)| c) {
+    Some('n') => '\n',
+    Some('r') => '\r',
+    Some('t') => '\t',
+    Some('0') => '\0',
+    Some('\\') => '\\',
+    Some('"') => '"',
+    Some('\'') => '\'',
+    Some('u') => {
+      let mut hex = String::new();
+      if chars.next().map(|(_, c)| c) != Some('{') {
+        return Err("Expected '{' in unicode escape".into());
+      }
+      loop {
+        match chars.next().map(|(_, c)| c) {
+          Some('}') => break,
+          Some(c) => hex.push(c),
+          None => return Err("Unterminated unicode escape".into()),
+        }
+      }
+      u32::from_str_radix(&hex, 16)
+        .ok()
+        .and_then(std::char::from_u32)
+        .ok_or_else(|| format!("Invalid unicode escape '{}'", hex))?
+    }
+    Some(c) => return Err(format!("Unknown escape sequence '\\{}'", c).into()),
+    None => return Err("Unterminated string".into()),
+  })
+}
+
+fn is_number(word: &str) -> bool {
+  !word.is_empty() && word.chars().all(|c| c.is_ascii_digit())
+}
+
+fn word<'a>(token: Option<&Token<'a>>) -> Result<&'a str, Cow<'static, str>> {
+  token
+    .and_then(Token::word)
+    .ok_or_else(|| "Unexpected end of line".into())
+}
+
+fn string(token: Option<&Token>) -> Result<String, Cow<'static, str>> {
+  match token {
+    Some(Token::String(s)) => Ok(s.clone()),
+    _ => Err("Expected a quoted string".into()),
+  }
+}
+
+fn parse<T: FromStr>(token: Option<&Token>) -> Result<T, Cow<'static, str>> {
+  let word = word(token)?;
+  word
+    .parse()
+    .map_err(|_| format!("Invalid operand '{}'", word).into())
+}
+
+fn end_of_line(token: Option<&Token>) -> Result<(), Cow<'static, str>> {
+  match token {
+    None => Ok(()),
+    Some(_) => Err("Unexpected input at the end of the line".into()),
+  }
+}
+
+#[derive(Debug)]
+pub struct AssemblerError {
+  line: usize,
+  message: Cow<'static, str>,
+}
+
+impl AssemblerError {
+  fn new<M>(line: usize, message: M) -> Self
+  where
+    M: Into<Cow<'static, str>>,
+  {
+    Self {
+      line,
+      message: message.into(),
+    }
+  }
+}
+
+impl fmt::Display for AssemblerError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "[line {}] Error in assembly: {}",
+      self.line, self.message
+    )
+  }
+}
+
+impl std::error::Error for AssemblerError {}
@@ -0,0 +1,188 @@
+use crate::{
+  libstd::{self, Module},
+  native::{Context, IntoNativeFunc, IntoValue},
+  permissions::Permissions,
+  userdata::UserType,
+  value::Value,
+  vfs::FileSystem,
+  vm::{Limits, VM},
+  CedarError,
+};
+use std::io::{BufRead, Write};
+
+/// Sets up a VM that starts out with nothing in it. The embedder picks which
+/// parts of the standard library scripts get, adds their own natives and
+/// types, and can put limits on how much work a script is allowed to do.
+///
+/// ```
+/// # use cedar::{libstd::Module, VM};
+/// let mut vm = VM::builder()
+///   .module(Module::Io)
+///   .register_fn("double", |n: f64| n * 2.0)
+///   .max_instructions(10_000)
+///   .build()
+///   .unwrap();
+/// vm.interpret("let n = double(21);".into()).unwrap();
+/// assert_eq!(vm.get_global_as::<f64>("n").unwrap(), 42.0);
+/// ```
+pub struct VmBuilder {
+  vm: VM,
+  limits: Limits,
+  // The first thing that went wrong, reported by build
+  error: Option<CedarError>,
+}
+
+impl Default for VmBuilder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl VmBuilder {
+  pub fn new() -> Self {
+    Self {
+      vm: VM::sandboxed(),
+      limits: Limits::default(),
+      error: None,
+    }
+  }
+
+  fn record(mut self, result: Result<(), CedarError>) -> Self {
+    if let Err(e) = result {
+      self.error.get_or_insert(e);
+    }
+    self
+  }
+
+  /// Load one module of the standard library
+  pub fn module(mut self, module: Module) -> Self {
+    libstd::load_module(self.vm.globals_mut(), module);
+    self
+  }
+
+  /// Load several modules of the standard library, e.g. `Module::ALL`
+  pub fn modules(mut self, modules: &[Module]) -> Self {
+    for module in modules {
+      self = self.module(*module);
+    }
+    self
+  }
+
+  /// See `VM::register_fn`
+  pub fn register_fn<F, Args>(mut self, name: &str, f: F) -> Self
+  where
+    F: IntoNativeFunc<Args>,
+  {
+    let result = self.vm.register_fn(name, f);
+    self.record(result)
+  }
+
+  /// See `VM::register_native`
+  pub fn register_native<F>(mut self, name: &str, arity: usize, f: F) -> Self
+  where
+    F: Fn(&mut Context, Vec<Value>) -> Result<Value, CedarError> + 'static,
+  {
+    let result = self.vm.register_native(name, arity, f);
+    self.record(result)
+  }
+
+  /// See `VM::register_type`
+  pub fn register_type(mut self, user_type: UserType) -> Self {
+    self.vm.register_type(user_type);
+    self
+  }
+
+  /// Define a global variable before any script runs
+  pub fn global<T: IntoValue>(mut self, name: &str, value: T) -> Self {
+    let result = self.vm.set_global(name, value);
+    self.record(result)
+  }
+
+  pub fn optimize(mut self, optimize: bool) -> Self {
+    self.vm.set_optimize(optimize);
+    self
+  }
+
+  pub fn stdin<R: BufRead + 'static>(mut self, stdin: R) -> Self {
+    self.vm.set_stdin(stdin);
+    self
+  }
+
+  pub fn stdout<W: Write + 'static>(mut self, stdout: W) -> Self {
+    self.vm.set_stdout(stdout);
+    self
+  }
+
+  pub fn stderr<W: Write + 'static>(mut self, stderr: W) -> Self {
+    self.vm.set_stderr(stderr);
+    self
+  }
+
+  /// What natives are allowed to do outside of the VM. Nothing is allowed
+  /// unless this is given.
+  pub fn permissions(mut self, permissions: Permissions) -> Self {
+    self.vm.set_permissions(permissions);
+    self
+  }
+
+  /// The arguments given to the script, which `args` returns
+  pub fn args<I, S>(mut self, args: I) -> Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.vm.set_args(args);
+    self
+  }
+
+  /// The filesystem natives read and write files through, which is the
+  /// OS's unless this is given
+  pub fn fs<F: FileSystem + 'static>(mut self, fs: F) -> Self {
+    self.vm.set_fs(fs);
+    self
+  }
+
+  /// How deeply calls can nest before a script gets a stack overflow
+  pub fn max_call_depth(mut self, depth: usize) -> Self {
+    self.limits.max_call_depth = Some(depth);
+    self
+  }
+
+  /// How many values can be on the stack before a script gets a stack
+  /// overflow
+  pub fn max_stack(mut self, size: usize) -> Self {
+    self.limits.max_stack = Some(size);
+    self
+  }
+
+  /// How many instructions each call into the VM can execute before it's
+  /// stopped with an error, so that a script can't loop forever
+  pub fn max_instructions(mut self, count: u64) -> Self {
+    self.limits.max_instructions = Some(count);
+    self
+  }
+
+  /// How many bytes a string can hold before making it is an error
+  pub fn max_string_len(mut self, bytes: usize) -> Self {
+    self.limits.max_string_len = Some(bytes);
+    self
+  }
+
+  /// How many entries a list or map can hold before making it is an error
+  pub fn max_collection_len(mut self, length: usize) -> Self {
+    self.limits.max_collection_len = Some(length);
+    self
+  }
+
+  /// The VM, or the first error from setting it up
+  pub fn build(self) -> Result<VM, CedarError> {
+    match self.error {
+      Some(e) => Err(e),
+      None => {
+        let mut vm = self.vm;
+        vm.set_limits(self.limits);
+        Ok(vm)
+      }
+    }
+  }
+}
@@ -1,5 +1,5 @@
 use crate::{value::Value, CedarError};
-use std::fmt;
+use std::{convert::TryFrom, fmt, str::FromStr};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OpCode {
@@ -31,10 +31,80 @@ pub enum OpCode {
   Jump,
   Loop,
   Call,
+  Invoke,
+  Index,
+  BuildList,
+  // Superinstructions only emitted by the optimizer
+  IncrementLocal,
+  AddConstant,
+  PopJumpIfFalse,
+  CompareJumpIfFalse,
 }
-impl From<u8> for OpCode {
-  fn from(b: u8) -> Self {
-    match b {
+
+/// The kinds of operands that can follow an opcode in a chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+  /// A plain number such as a local slot or argument count
+  Byte,
+  /// An index into the chunk's constants
+  Constant,
+  /// Another opcode, used by superinstructions to pick an operator
+  OpCode,
+  /// A two byte slot in the VM's globals
+  Global,
+  /// A two byte offset to jump by
+  Jump,
+}
+
+impl Operand {
+  /// The number of bytes this operand takes up
+  pub fn size(self) -> usize {
+    match self {
+      Operand::Jump | Operand::Global => 2,
+      _ => 1,
+    }
+  }
+}
+
+impl OpCode {
+  /// The operands that follow this opcode in a chunk in order
+  pub fn operands(self) -> &'static [Operand] {
+    match self {
+      OpCode::Constant | OpCode::AddConstant => &[Operand::Constant],
+      OpCode::DefineGlobal | OpCode::GetGlobal | OpCode::SetGlobal => &[Operand::Global],
+      OpCode::GetLocal | OpCode::SetLocal | OpCode::Call | OpCode::BuildList => &[Operand::Byte],
+      OpCode::JumpIfFalse | OpCode::Jump | OpCode::Loop | OpCode::PopJumpIfFalse => {
+        &[Operand::Jump]
+      }
+      OpCode::IncrementLocal => &[Operand::Byte, Operand::Constant],
+      OpCode::CompareJumpIfFalse => &[Operand::OpCode, Operand::Jump],
+      OpCode::Invoke => &[Operand::Constant, Operand::Byte],
+      _ => &[],
+    }
+  }
+  /// The number of operand bytes that follow this opcode in a chunk
+  pub fn operand_len(self) -> usize {
+    self.operands().iter().map(|o| o.size()).sum()
+  }
+  pub fn is_jump(self) -> bool {
+    self.operands().last() == Some(&Operand::Jump)
+  }
+  pub fn is_comparison(self) -> bool {
+    matches!(
+      self,
+      OpCode::Equal
+        | OpCode::NotEqual
+        | OpCode::Greater
+        | OpCode::GreaterOrEqual
+        | OpCode::Less
+        | OpCode::LessOrEqual
+    )
+  }
+}
+impl TryFrom<u8> for OpCode {
+  type Error = ChunkError;
+  fn try_from(b: u8) -> Result<Self, Self::Error> {
+    Ok(match b {
       0 => OpCode::Return,
       1 => OpCode::Constant,
       2 => OpCode::Negate,
@@ -63,8 +133,15 @@ impl From<u8> for OpCode {
       25 => OpCode::Jump,
       26 => OpCode::Loop,
       27 => OpCode::Call,
-      _ => panic!("Invalid opcode: {}", b),
-    }
+      28 => OpCode::IncrementLocal,
+      29 => OpCode::AddConstant,
+      30 => OpCode::PopJumpIfFalse,
+      31 => OpCode::CompareJumpIfFalse,
+      32 => OpCode::Invoke,
+      33 => OpCode::Index,
+      34 => OpCode::BuildList,
+      _ => return Err(ChunkError::InvalidOpCode(b)),
+    })
   }
 }
 impl From<OpCode> for u8 {
@@ -98,6 +175,13 @@ impl From<OpCode> for u8 {
       OpCode::Jump => 25,
       OpCode::Loop => 26,
       OpCode::Call => 27,
+      OpCode::IncrementLocal => 28,
+      OpCode::AddConstant => 29,
+      OpCode::PopJumpIfFalse => 30,
+      OpCode::CompareJumpIfFalse => 31,
+      OpCode::Invoke => 32,
+      OpCode::Index => 33,
+      OpCode::BuildList => 34,
     }
   }
 }
@@ -112,9 +196,9 @@ impl fmt::Display for OpCode {
       OpCode::Subtract => "Subtract",
       OpCode::Multiply => "Multiply",
       OpCode::Divide => "Divide",
-      OpCode::Null => "null",
-      OpCode::True => "true",
-      OpCode::False => "false",
+      OpCode::Null => "Null",
+      OpCode::True => "True",
+      OpCode::False => "False",
       OpCode::Not => "Not",
       OpCode::Equal => "Equal",
       OpCode::NotEqual => "NotEqual",
@@ -133,11 +217,62 @@ impl fmt::Display for OpCode {
       OpCode::Jump => "Jump",
       OpCode::Loop => "Loop",
       OpCode::Call => "Call",
+      OpCode::IncrementLocal => "IncrementLocal",
+      OpCode::AddConstant => "AddConstant",
+      OpCode::PopJumpIfFalse => "PopJumpIfFalse",
+      OpCode::CompareJumpIfFalse => "CompareJumpIfFalse",
+      OpCode::Invoke => "Invoke",
+      OpCode::Index => "Index",
+      OpCode::BuildList => "BuildList",
     };
     write!(f, "{}", string)
   }
 }
 
+impl FromStr for OpCode {
+  type Err = ChunkError;
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Ok(match s {
+      "Return" => OpCode::Return,
+      "Constant" => OpCode::Constant,
+      "Negate" => OpCode::Negate,
+      "Add" => OpCode::Add,
+      "Subtract" => OpCode::Subtract,
+      "Multiply" => OpCode::Multiply,
+      "Divide" => OpCode::Divide,
+      "Null" => OpCode::Null,
+      "True" => OpCode::True,
+      "False" => OpCode::False,
+      "Not" => OpCode::Not,
+      "Equal" => OpCode::Equal,
+      "NotEqual" => OpCode::NotEqual,
+      "Greater" => OpCode::Greater,
+      "GreaterOrEqual" => OpCode::GreaterOrEqual,
+      "Less" => OpCode::Less,
+      "LessOrEqual" => OpCode::LessOrEqual,
+      "Print" => OpCode::Print,
+      "Pop" => OpCode::Pop,
+      "DefineGlobal" => OpCode::DefineGlobal,
+      "GetGlobal" => OpCode::GetGlobal,
+      "SetGlobal" => OpCode::SetGlobal,
+      "GetLocal" => OpCode::GetLocal,
+      "SetLocal" => OpCode::SetLocal,
+      "JumpIfFalse" => OpCode::JumpIfFalse,
+      "Jump" => OpCode::Jump,
+      "Loop" => OpCode::Loop,
+      "Call" => OpCode::Call,
+      "IncrementLocal" => OpCode::IncrementLocal,
+      "AddConstant" => OpCode::AddConstant,
+      "PopJumpIfFalse" => OpCode::PopJumpIfFalse,
+      "CompareJumpIfFalse" => OpCode::CompareJumpIfFalse,
+      "Invoke" => OpCode::Invoke,
+      "Index" => OpCode::Index,
+      "BuildList" => OpCode::BuildList,
+      _ => return Err(ChunkError::UnknownMnemonic(s.to_string())),
+    })
+  }
+}
+
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct Chunk {
   pub code: Vec<u8>,
@@ -177,32 +312,35 @@ impl Chunk {
       | OpCode::LessOrEqual
       | OpCode::Print
       | OpCode::Pop
+      | OpCode::Index
       | OpCode::Divide => {
         self.write_byte(byte.into());
         self.lines.push(line);
         Ok(())
       }
       OpCode::Constant => self.add_constant(value.expect("Constant should have a value"), line),
-      OpCode::DefineGlobal => {
-        self.add_global(value.expect("Global variable should have a value"), line)
-      }
-      OpCode::GetGlobal => self.add_get_global(
-        value.expect("Global variable ref should have a value"),
-        line,
-      ),
-      OpCode::SetGlobal => self.add_set_global(
-        value.expect("Global variable ref should have a value"),
-        line,
-      ),
       OpCode::GetLocal => {
         self.add_get_local(value.expect("Local variable ref should have a value"), line)
       }
       OpCode::SetLocal => {
         self.add_set_local(value.expect("Local variable ref should have a value"), line)
       }
-      OpCode::Call => self.add_call(value.expect("Call variable ref should have a value"), line),
+      OpCode::Call | OpCode::BuildList => self.add_with_count(
+        byte,
+        value.expect("Calls and lists should have a count"),
+        line,
+      ),
       // We handle this bit of code in the compiler itself
       OpCode::JumpIfFalse | OpCode::Jump | OpCode::Loop => Ok(()),
+      // Globals are resolved by the compiler and written with write_global
+      OpCode::DefineGlobal | OpCode::GetGlobal | OpCode::SetGlobal => Ok(()),
+      // Method calls carry two operands and are written with write_invoke
+      OpCode::Invoke => Ok(()),
+      // These are only ever created by the optimizer
+      OpCode::IncrementLocal
+      | OpCode::AddConstant
+      | OpCode::PopJumpIfFalse
+      | OpCode::CompareJumpIfFalse => Ok(()),
     }
   }
   pub fn write_byte(&mut self, byte: u8) {
@@ -211,7 +349,7 @@ impl Chunk {
 
   fn add_constant(&mut self, value: Value, line: usize) -> Result<(), CedarError> {
     self.constants.push(value);
-    if self.constants.len() > std::u8::MAX as usize {
+    if self.constants.len() > u8::MAX as usize {
       return Err(ChunkError::TooManyConst.into());
     }
     self.write_byte(OpCode::Constant.into());
@@ -222,8 +360,9 @@ impl Chunk {
     self.lines.push(line);
     Ok(())
   }
-  fn add_call(&mut self, value: Value, line: usize) -> Result<(), CedarError> {
-    self.write_byte(OpCode::Call.into());
+  // An instruction followed by how many values on the stack it uses
+  fn add_with_count(&mut self, op: OpCode, value: Value, line: usize) -> Result<(), CedarError> {
+    self.write_byte(op.into());
     self.write_byte(value.into_byte());
     // TODO: Make this work for indexing better
     // push twice to keep length for indexing the same
@@ -232,73 +371,37 @@ impl Chunk {
     Ok(())
   }
 
-  fn add_global(&mut self, value: Value, line: usize) -> Result<(), CedarError> {
-    self.constants.push(value);
-    if self.constants.len() > std::u8::MAX as usize {
-      return Err(ChunkError::TooManyConst.into());
-    }
-    self.write_byte(OpCode::DefineGlobal.into());
-    self.write_byte((self.constants.len() - 1) as u8);
-    // TODO: Make this work for indexing better
-    // push twice to keep length for indexing the same
+  /// Write an instruction that refers to the global in the given slot
+  pub fn write_global(&mut self, op: OpCode, slot: u16, line: usize) {
+    self.write_byte(op.into());
+    self.write_byte((slot >> 8) as u8);
+    self.write_byte((slot & 0xff) as u8);
     self.lines.push(line);
     self.lines.push(line);
-    Ok(())
-  }
-
-  fn add_get_global(&mut self, value: Value, line: usize) -> Result<(), CedarError> {
-    match self
-      .constants
-      .iter()
-      .enumerate()
-      .find_map(|(i, c)| if *c == value { Some(i) } else { None })
-    {
-      None => {
-        self.constants.push(value);
-        if self.constants.len() > std::u8::MAX as usize {
-          return Err(ChunkError::TooManyConst.into());
-        }
-        self.write_byte(OpCode::GetGlobal.into());
-        self.write_byte((self.constants.len() - 1) as u8);
-      }
-      Some(b) => {
-        self.write_byte(OpCode::GetGlobal.into());
-        self.write_byte(b as u8);
-      }
-    }
-    // TODO: Make this work for indexing better
-    // push twice to keep length for indexing the same
     self.lines.push(line);
-    self.lines.push(line);
-    Ok(())
   }
 
-  fn add_set_global(&mut self, value: Value, line: usize) -> Result<(), CedarError> {
-    match self
-      .constants
-      .iter()
-      .enumerate()
-      .find_map(|(i, c)| if *c == value { Some(i) } else { None })
-    {
-      None => {
-        self.constants.push(value);
-        if self.constants.len() > std::u8::MAX as usize {
-          return Err(ChunkError::TooManyConst.into());
-        }
-        self.write_byte(OpCode::SetGlobal.into());
-        self.write_byte((self.constants.len() - 1) as u8);
-      }
-      Some(b) => {
-        self.write_byte(OpCode::SetGlobal.into());
-        self.write_byte(b as u8);
-      }
+  /// Write a call to the method with the given name on the value below the
+  /// arguments
+  pub fn write_invoke(
+    &mut self,
+    name: Value,
+    arg_count: u8,
+    line: usize,
+  ) -> Result<(), CedarError> {
+    self.constants.push(name);
+    if self.constants.len() > u8::MAX as usize {
+      return Err(ChunkError::TooManyConst.into());
     }
-    // TODO: Make this work for indexing better
-    // push twice to keep length for indexing the same
+    self.write_byte(OpCode::Invoke.into());
+    self.write_byte((self.constants.len() - 1) as u8);
+    self.write_byte(arg_count);
+    self.lines.push(line);
     self.lines.push(line);
     self.lines.push(line);
     Ok(())
   }
+
   fn add_get_local(&mut self, value: Value, line: usize) -> Result<(), CedarError> {
     self.write_byte(OpCode::GetLocal.into());
     let value = value.into_byte();
@@ -307,7 +410,7 @@ impl Chunk {
     self.lines.push(line);
     self.lines.push(line);
     // We checked for truncation here
-    self.write_byte(value as u8);
+    self.write_byte(value);
     Ok(())
   }
   fn add_set_local(&mut self, value: Value, line: usize) -> Result<(), CedarError> {
@@ -318,74 +421,158 @@ impl Chunk {
     self.lines.push(line);
     self.lines.push(line);
     // We checked for truncation here
-    self.write_byte(value as u8);
+    self.write_byte(value);
     Ok(())
   }
-  #[allow(dead_code)]
-  pub fn disassemble(&self, name: &str) {
-    println!("== {} ==", name);
-    let mut iterator = self.code.iter().enumerate();
-    while let Some((i, instruction)) = iterator.next() {
-      let op = OpCode::from(*instruction);
-      match op {
-        OpCode::Return
-        | OpCode::Negate
-        | OpCode::Add
-        | OpCode::Subtract
-        | OpCode::Multiply
-        | OpCode::Divide
-        | OpCode::Not
-        | OpCode::Null
-        | OpCode::False
-        | OpCode::Equal
-        | OpCode::NotEqual
-        | OpCode::Greater
-        | OpCode::GreaterOrEqual
-        | OpCode::Less
-        | OpCode::LessOrEqual
-        | OpCode::Print
-        | OpCode::Pop
-        | OpCode::True => println!("{:04} {:4}", i, op),
-        OpCode::GetGlobal
-        | OpCode::SetGlobal
-        | OpCode::DefineGlobal
-        | OpCode::GetLocal
-        | OpCode::SetLocal
-        | OpCode::Constant => {
-          print!("{:04} {:4} ", i, op);
-          if let Some((_, location)) = iterator.next() {
-            println!("{}", &self.constants[*location as usize]);
-          }
+  /// Render the chunk as a textual assembly listing. Every nested function
+  /// constant is listed in full, jump targets get labels and each instruction
+  /// records its offset and source line. The output can be turned back into
+  /// the same chunk with `assembler::assemble`.
+  pub fn disassemble(&self, name: &str) -> String {
+    let mut out = format!(".chunk {}\n", name);
+    self.disassemble_body(&mut out, 0);
+    out.push_str(".end\n");
+    out
+  }
+  fn disassemble_body(&self, out: &mut String, depth: usize) {
+    let indent = "  ".repeat(depth);
+    for (i, constant) in self.constants.iter().enumerate() {
+      if let Value::Function(function) = constant {
+        out.push_str(&format!(
+          "{}.const {} function {:?} {}\n",
+          indent, i, function.name, function.arity
+        ));
+        function.chunk.disassemble_body(out, depth + 1);
+        out.push_str(&format!("{}.end\n", indent));
+      } else {
+        out.push_str(&format!("{}.const {} {}\n", indent, i, literal(constant)));
+      }
+    }
+    out.push_str(&format!("{}.code\n", indent));
+    let targets = self.jump_targets();
+    let mut offset = 0;
+    while offset < self.code.len() {
+      if targets.contains(&offset) {
+        out.push_str(&format!("{}L{:04}:\n", indent, offset));
+      }
+      let line = self.lines.get(offset).copied().unwrap_or(0);
+      let byte = self.code[offset];
+      let prefix = format!("{}{:04} [line {}]", indent, offset, line);
+      let op = match OpCode::try_from(byte) {
+        Ok(op) if offset + op.operand_len() < self.code.len() => op,
+        // Either not an instruction or one missing its operands so we list the
+        // raw byte to keep the listing faithful to the chunk
+        _ => {
+          out.push_str(&format!("{} .byte {}\n", prefix, byte));
+          offset += 1;
+          continue;
         }
-        OpCode::Call => {
-          print!("{:04} {:4} ", i, op);
-          if let Some((_, location)) = iterator.next() {
-            println!("{}", location);
+      };
+      let mut text = format!("{} {}", prefix, op);
+      let mut comment = None;
+      let mut position = offset + 1;
+      for operand in op.operands() {
+        let byte = self.code[position];
+        match operand {
+          Operand::Byte => text.push_str(&format!(" {}", byte)),
+          Operand::Global => text.push_str(&format!(" {}", self.read_u16(position))),
+          Operand::Constant => {
+            text.push_str(&format!(" {}", byte));
+            comment = self.constants.get(byte as usize).map(|c| match c {
+              Value::Function(function) => function.to_string(),
+              constant => literal(constant),
+            });
           }
+          Operand::OpCode => match OpCode::try_from(byte) {
+            Ok(op) => text.push_str(&format!(" {}", op)),
+            Err(_) => text.push_str(&format!(" {}", byte)),
+          },
+          Operand::Jump => match self.jump_target(offset) {
+            Some(target) if targets.contains(&target) => text.push_str(&format!(" L{:04}", target)),
+            _ => text.push_str(&format!(" {}", self.read_u16(position))),
+          },
         }
-        OpCode::Jump | OpCode::Loop | OpCode::JumpIfFalse => {
-          if let (Some((_, location)), Some((_, location2))) = (iterator.next(), iterator.next()) {
-            println!(
-              "{:04} {:4} {}",
-              i,
-              op,
-              ((*location as u16) << 8) | *location2 as u16
-            );
-          }
+        position += operand.size();
+      }
+      match comment {
+        Some(comment) => out.push_str(&format!("{} ; {}\n", text, comment)),
+        None => out.push_str(&format!("{}\n", text)),
+      }
+      offset += 1 + op.operand_len();
+    }
+  }
+  /// Where the jump instruction at `offset` will continue executing from. This
+  /// is None if it isn't a complete jump instruction or would jump to before
+  /// the start of the chunk.
+  pub fn jump_target(&self, offset: usize) -> Option<usize> {
+    let op = OpCode::try_from(*self.code.get(offset)?).ok()?;
+    let next = offset + 1 + op.operand_len();
+    if !op.is_jump() || next > self.code.len() {
+      return None;
+    }
+    let jump = self.read_u16(next - 2) as usize;
+    match op {
+      OpCode::Loop => next.checked_sub(jump),
+      _ => Some(next + jump),
+    }
+  }
+  fn read_u16(&self, offset: usize) -> u16 {
+    ((self.code[offset] as u16) << 8) | self.code[offset + 1] as u16
+  }
+  /// Every offset in the chunk that a jump lands on the start of an
+  /// instruction at
+  fn jump_targets(&self) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut jumps = Vec::new();
+    let mut offset = 0;
+    while offset < self.code.len() {
+      starts.push(offset);
+      match OpCode::try_from(self.code[offset]) {
+        Ok(op) if offset + op.operand_len() < self.code.len() => {
+          jumps.extend(self.jump_target(offset));
+          offset += 1 + op.operand_len();
         }
+        _ => offset += 1,
       }
     }
+    let mut targets: Vec<usize> = jumps
+      .into_iter()
+      .filter(|target| starts.binary_search(target).is_ok())
+      .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    targets
+  }
+}
+
+/// Format a constant the way the assembler expects to read it
+fn literal(value: &Value) -> String {
+  match value {
+    Value::Number(n) => format!("number {}", n),
+    Value::Bool(b) => format!("bool {}", b),
+    Value::Byte(b) => format!("byte {}", b),
+    Value::Null => "null".into(),
+    Value::String(s) => format!("string {:?}", s),
+    Value::Function(f) => format!("function {:?} {}", f.name, f.arity),
+    Value::NativeFn(f) => format!("native {}", f),
+    Value::List(_) | Value::Map(_) | Value::UserData(_) => {
+      format!("{} {}", value.type_name(), value)
+    }
   }
 }
 
 #[derive(Debug)]
 pub enum ChunkError {
   TooManyConst,
+  InvalidOpCode(u8),
+  UnknownMnemonic(String),
 }
 impl fmt::Display for ChunkError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
       ChunkError::TooManyConst => write!(f, "Too many constants used in code"),
+      ChunkError::InvalidOpCode(b) => write!(f, "Invalid opcode: {}", b),
+      ChunkError::UnknownMnemonic(m) => write!(f, "Unknown instruction: {}", m),
     }
   }
 }
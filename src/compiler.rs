@@ -1,14 +1,17 @@
 use crate::{
   chunk::{Chunk, OpCode},
+  globals::Globals,
+  ops::{self, OpResult},
   scanner::{Scanner, Token, TokenType},
   value::{Function, Value},
   CedarError,
 };
 use std::{borrow::Cow, fmt, iter::Peekable, mem, vec};
 
-const U8_COUNT: isize = std::u8::MAX as isize + 1;
+const U8_COUNT: isize = u8::MAX as isize + 1;
 
-pub fn compile(source: String) -> Result<Function, CedarError> {
+/// Compile a script, resolving any globals it uses to slots in `globals`
+pub fn compile(source: String, globals: &mut Globals) -> Result<Function, CedarError> {
   let mut tokens = Scanner::new(source).scan()?;
 
   // This means we are parsing lines from the repl and need to add an EOF token
@@ -19,7 +22,11 @@ pub fn compile(source: String) -> Result<Function, CedarError> {
       lexeme: "".into(),
     });
   }
-  Ok(TokenIter::new(tokens).compile()?)
+  let mut iter = TokenIter::new(tokens, mem::take(globals));
+  let function = iter.compile();
+  // Hand the globals back even if compilation failed so the REPL keeps them
+  *globals = mem::take(&mut iter.globals);
+  function
 }
 
 pub struct TokenIter {
@@ -28,39 +35,39 @@ pub struct TokenIter {
   current: Option<Token>,
   function: Function,
   fn_type: FunctionType,
-  rules: [ParseRule; 39],
+  rules: [ParseRule; 41],
   locals: Vec<Local>, // we use U8_COUNT as our hard limit for locals in scope
   scope_depth: isize,
+  // The literal value the most recently emitted code evaluates to, if any
+  constant: Option<Constant>,
+  globals: Globals,
 }
 
 impl fmt::Debug for TokenIter {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    self.chunk_immutable().disassemble("MAIN");
     write!(
       f,
-      "Iter: {:#?}\nPrevious: {:#?}\nCurrent: {:#?}\n",
-      self.iter, self.previous, self.current
+      "{}Iter: {:#?}\nPrevious: {:#?}\nCurrent: {:#?}\n",
+      self.chunk_immutable().disassemble("MAIN"),
+      self.iter,
+      self.previous,
+      self.current
     )
   }
 }
 
 impl TokenIter {
-  fn new(tokens: Vec<Token>) -> TokenIter {
+  fn new(tokens: Vec<Token>, globals: Globals) -> TokenIter {
     Self {
       iter: tokens.into_iter().peekable(),
       previous: None,
       current: None,
       function: Function::new(),
       fn_type: FunctionType::Script,
-      locals: vec![Local::new(
-        Token {
-          ty: TokenType::Fn,
-          line: 0,
-          lexeme: "".into(),
-        },
-        0, // depth
-      )],
+      locals: vec![Local::reserved()],
       scope_depth: 0,
+      constant: None,
+      globals,
       rules: [
         // LeftParen
         ParseRule::new(
@@ -74,10 +81,18 @@ impl TokenIter {
         ParseRule::new(None, None, Precedence::None),
         // RightBrace
         ParseRule::new(None, None, Precedence::None),
+        // LeftBracket
+        ParseRule::new(
+          Some(TokenIter::list),
+          Some(TokenIter::index),
+          Precedence::Call,
+        ),
+        // RightBracket
+        ParseRule::new(None, None, Precedence::None),
         // Comma
         ParseRule::new(None, None, Precedence::None),
         // Dot
-        ParseRule::new(None, None, Precedence::None),
+        ParseRule::new(None, Some(TokenIter::dot), Precedence::Call),
         // Minus
         ParseRule::new(
           Some(TokenIter::unary),
@@ -157,21 +172,20 @@ impl TokenIter {
   fn chunk(&mut self) -> &mut Chunk {
     &mut self.function.chunk
   }
-  fn compile(mut self) -> Result<Function, CedarError> {
-    let mut failed = false;
+  fn compile(&mut self) -> Result<Function, CedarError> {
+    let mut errors = Vec::new();
     self.advance();
     while !self.match_token(TokenType::EOF)? {
       if let Err(e) = self.declaration() {
-        failed = true;
-        eprintln!("{}", e);
+        errors.push(e);
         self.synchronize()?;
       }
     }
-    if failed {
-      Err(CompilerError::failed().into())
+    if !errors.is_empty() {
+      Err(CompilerError::Failed(errors).into())
     } else {
       self.end_compiler()?;
-      Ok(self.function)
+      Ok(mem::take(&mut self.function))
     }
   }
   fn advance(&mut self) {
@@ -245,31 +259,69 @@ impl TokenIter {
     }
     Ok(())
   }
-  fn parse_variable(&mut self) -> Result<Option<Value>, CedarError> {
+  fn parse_variable(&mut self) -> Result<Option<u16>, CedarError> {
     self.consume(TokenType::Identifier, "Expect variable name.")?;
     self.declare_variable()?;
     if self.scope_depth > 0 {
-      // Locals are not looked up by name at runtime return None
+      // Locals live on the stack and don't need a slot
       Ok(None)
     } else {
-      // Globals are looked up by name at runtime return a value
-      Ok(Some(Value::String(
-        self
-          .previous
-          .clone()
-          .ok_or_else(|| CompilerError::ice("No previous value in let_declaration"))?
-          .lexeme,
-      )))
+      // Globals are stored in the slot given to their name
+      let name = self
+        .previous
+        .clone()
+        .ok_or_else(|| CompilerError::ice("No previous value in let_declaration"))?;
+      self.resolve_global(&name).map(Some)
     }
   }
+  fn line(&self) -> Result<usize, CedarError> {
+    Ok(
+      self
+        .previous
+        .as_ref()
+        .map(|p| p.line)
+        .ok_or_else(|| CompilerError::ice("No previous value when emitting code"))?,
+    )
+  }
   fn emit_byte(&mut self, byte: OpCode, value: Option<Value>) -> Result<(), CedarError> {
-    let line = self
-      .previous
-      .as_ref()
-      .map(|p| p.line)
-      .ok_or_else(|| CompilerError::ice("No previous value when calling emit_byte"))?;
+    let line = self.line()?;
+    self.constant = None;
     self.chunk().write_chunk(byte, value, line)
   }
+  fn emit_global(&mut self, op: OpCode, slot: u16) -> Result<(), CedarError> {
+    let line = self.line()?;
+    self.constant = None;
+    self.chunk().write_global(op, slot, line);
+    Ok(())
+  }
+  /// Emit the code to load a literal value, remembering it so that it can be
+  /// folded into any operator that uses it
+  fn emit_literal(&mut self, value: Value) -> Result<(), CedarError> {
+    let mark = self.mark();
+    match value {
+      Value::Bool(true) => self.emit_byte(OpCode::True, None)?,
+      Value::Bool(false) => self.emit_byte(OpCode::False, None)?,
+      Value::Null => self.emit_byte(OpCode::Null, None)?,
+      ref value => self.emit_constant(Some(value.clone()))?,
+    }
+    self.constant = Some(Constant { mark, value });
+    Ok(())
+  }
+  fn mark(&mut self) -> Mark {
+    let chunk = self.chunk();
+    Mark {
+      code: chunk.code.len(),
+      constants: chunk.constants.len(),
+    }
+  }
+  /// Throw away everything emitted since the mark was taken
+  fn rewind(&mut self, mark: Mark) {
+    let chunk = self.chunk();
+    chunk.code.truncate(mark.code);
+    chunk.lines.truncate(mark.code);
+    chunk.constants.truncate(mark.constants);
+    self.constant = None;
+  }
   fn emit_return(&mut self) -> Result<(), CedarError> {
     self.emit_byte(OpCode::Null, None)?;
     self.emit_byte(OpCode::Return, None)
@@ -285,8 +337,9 @@ impl TokenIter {
       .previous
       .as_ref()
       .map(|c| -> Result<Value, CedarError> { Ok(Value::Number(c.lexeme.parse()?)) })
-      .transpose()?;
-    self.emit_constant(number)
+      .transpose()?
+      .ok_or_else(|| CompilerError::ice("No previous value in number"))?;
+    self.emit_literal(number)
   }
   fn string(&mut self, _: bool) -> Result<(), CedarError> {
     let mut string = self
@@ -301,13 +354,13 @@ impl TokenIter {
     } else {
       *string.to_mut() = string[1..string.len() - 1].into();
     }
-    self.emit_constant(Some(Value::String(string)))
+    self.emit_literal(Value::from(string.into_owned()))
   }
   fn literal(&mut self, _: bool) -> Result<(), CedarError> {
     match self.previous.as_ref().map(|t| t.ty) {
-      Some(TokenType::False) => self.emit_byte(OpCode::False, None),
-      Some(TokenType::True) => self.emit_byte(OpCode::True, None),
-      Some(TokenType::Null) => self.emit_byte(OpCode::Null, None),
+      Some(TokenType::False) => self.emit_literal(Value::Bool(false)),
+      Some(TokenType::True) => self.emit_literal(Value::Bool(true)),
+      Some(TokenType::Null) => self.emit_literal(Value::Null),
       _ => unreachable!(),
     }
   }
@@ -323,7 +376,7 @@ impl TokenIter {
       Some(prefix) => {
         prefix(self, can_assign)?;
       }
-      None => return Err(CompilerError::new(&token, "Expected expression").into()),
+      None => return Err(CompilerError::new(token, "Expected expression").into()),
     }
     while {
       match self.current.as_ref() {
@@ -341,12 +394,12 @@ impl TokenIter {
         Some(infix) => {
           infix(self, can_assign)?;
         }
-        None => return Err(CompilerError::new(&token, "Expected infix function").into()),
+        None => return Err(CompilerError::new(token, "Expected infix function").into()),
       }
     }
 
     if can_assign && self.match_token(TokenType::Equal)? {
-      Err(CompilerError::new(&self.previous.as_ref().unwrap(), "Expected infix function").into())
+      Err(CompilerError::new(self.previous.as_ref().unwrap(), "Expected infix function").into())
     } else {
       Ok(())
     }
@@ -407,13 +460,14 @@ impl TokenIter {
     }
     self.add_local(name)
   }
-  fn define_variable(&mut self, global: Option<Value>) -> Result<(), CedarError> {
+  fn define_variable(&mut self, global: Option<u16>) -> Result<(), CedarError> {
     if self.scope_depth > 0 {
       self.mark_initialized();
       return Ok(());
     }
 
-    self.emit_byte(OpCode::DefineGlobal, global)
+    let slot = global.ok_or_else(|| CompilerError::ice("No slot for global variable"))?;
+    self.emit_global(OpCode::DefineGlobal, slot)
   }
   fn mark_initialized(&mut self) {
     if self.scope_depth != 0 {
@@ -464,7 +518,7 @@ impl TokenIter {
   fn named_variable(&mut self, can_assign: bool) -> Result<(), CedarError> {
     let get_op;
     let set_op;
-    let arg;
+    let local;
     let name = self.previous.clone().unwrap();
     let depth = self.resolve_local(&name.lexeme)?;
     match depth {
@@ -474,21 +528,34 @@ impl TokenIter {
         if depth > U8_COUNT {
           return Err(CompilerError::new(&name, "Too many levels of scoping in function").into());
         }
-        arg = Some(Value::Byte(depth as u8));
+        local = Some(depth as u8);
       }
       Depth::Uninitialized => {
         get_op = OpCode::GetGlobal;
         set_op = OpCode::SetGlobal;
-        arg = Some(Value::String(name.lexeme));
+        local = None;
       }
     }
-    if can_assign && self.match_token(TokenType::Equal)? {
+    let op = if can_assign && self.match_token(TokenType::Equal)? {
       self.expression()?;
-      self.emit_byte(set_op, arg)
+      set_op
     } else {
-      self.emit_byte(get_op, arg)
+      get_op
+    };
+    match local {
+      Some(slot) => self.emit_byte(op, Some(Value::Byte(slot))),
+      None => {
+        let slot = self.resolve_global(&name)?;
+        self.emit_global(op, slot)
+      }
     }
   }
+  fn resolve_global(&mut self, name: &Token) -> Result<u16, CedarError> {
+    self
+      .globals
+      .resolve(&name.lexeme)
+      .ok_or_else(|| CompilerError::new(name, "Too many global variables").into())
+  }
   fn resolve_local(&mut self, name: &str) -> Result<Depth, CedarError> {
     for (pos, local) in self.locals.iter().rev().enumerate() {
       if local.name.lexeme == name {
@@ -525,6 +592,9 @@ impl TokenIter {
   }
   fn if_statement(&mut self) -> Result<(), CedarError> {
     self.expression()?;
+    if let Some(condition) = self.constant.take() {
+      return self.constant_if_statement(condition);
+    }
     let then_jump = self.emit_jump(OpCode::JumpIfFalse)?;
     self.emit_byte(OpCode::Pop, None)?;
     self.statement()?;
@@ -536,6 +606,28 @@ impl TokenIter {
     }
     self.patch_jump(else_jump)
   }
+  /// Only emit the branch of an if statement that will actually run when the
+  /// condition is known at compile time. The other branch is still compiled
+  /// so that it gets checked for errors but its code is thrown away.
+  fn constant_if_statement(&mut self, condition: Constant) -> Result<(), CedarError> {
+    // JumpIfFalse only jumps for false so every other value takes the branch
+    let taken = condition.value != Value::Bool(false);
+    self.rewind(condition.mark);
+    let mark = self.mark();
+    self.statement()?;
+    if !taken {
+      self.rewind(mark);
+    }
+    if self.match_token(TokenType::Else)? {
+      let mark = self.mark();
+      self.statement()?;
+      if taken {
+        self.rewind(mark);
+      }
+    }
+    self.constant = None;
+    Ok(())
+  }
   fn return_statement(&mut self) -> Result<(), CedarError> {
     if self.fn_type == FunctionType::Script {
       return Err(CompilerError::error("Cannot return from top level code").into());
@@ -595,36 +687,36 @@ impl TokenIter {
     self.end_scope()
   }
   fn emit_loop(&mut self, loop_start: usize) -> Result<(), CedarError> {
+    let line = self.line()?;
+    self.constant = None;
     let chunk = self.chunk();
     chunk.write_byte(OpCode::Loop.into());
     let offset = chunk.code.len() - loop_start + 2;
-    if offset > std::u16::MAX as usize {
+    if offset > u16::MAX as usize {
       return Err(CompilerError::error("Loop body too large").into());
     }
     chunk.write_byte(((offset >> 8) & 0xff) as u8);
     chunk.write_byte((offset & 0xff) as u8);
-    // For dissasembler
-    chunk.lines.push(0xff);
-    chunk.lines.push(0xff);
-    chunk.lines.push(0xff);
+    chunk.lines.extend(&[line; 3]);
     Ok(())
   }
   fn emit_jump(&mut self, jump: OpCode) -> Result<usize, CedarError> {
+    let line = self.line()?;
+    self.constant = None;
     let chunk = self.chunk();
     chunk.write_byte(jump.into());
     chunk.write_byte(0xff);
     chunk.write_byte(0xff);
-    // For dissasembler
-    chunk.lines.push(0xff);
-    chunk.lines.push(0xff);
-    chunk.lines.push(0xff);
+    chunk.lines.extend(&[line; 3]);
     Ok(chunk.code.len() - 2)
   }
   fn patch_jump(&mut self, offset: usize) -> Result<(), CedarError> {
+    // Code after this point is a jump target and can't be folded away
+    self.constant = None;
     let chunk = self.chunk();
     let jump = chunk.code.len() - offset - 2;
 
-    if jump > std::u16::MAX as usize {
+    if jump > u16::MAX as usize {
       return Err(CompilerError::error("Too much code to jump over.").into());
     }
 
@@ -669,9 +761,9 @@ impl TokenIter {
     let scope_depth = self.scope_depth;
     self.scope_depth = 0;
     self.fn_type = ty;
-    self.locals = Vec::new();
     let mut function = Function::new();
-    let mut locals = Vec::new();
+    // Slot 0 is reserved for the function being called
+    let mut locals = vec![Local::reserved()];
     mem::swap(&mut self.function, &mut function);
     mem::swap(&mut self.locals, &mut locals);
 
@@ -708,7 +800,7 @@ impl TokenIter {
     mem::swap(&mut self.locals, &mut locals);
     self.fn_type = current_ty;
     self.scope_depth = scope_depth;
-    self.emit_byte(OpCode::Constant, Some(Value::Function(function)))
+    self.emit_byte(OpCode::Constant, Some(Value::from(function)))
   }
   fn expression(&mut self) -> Result<(), CedarError> {
     self.parse_precedence(Precedence::Assignment)
@@ -724,11 +816,19 @@ impl TokenIter {
       .map(|p| p.ty)
       .ok_or_else(|| CompilerError::ice("No previous value in unary expression"))?;
     self.parse_precedence(Precedence::Unary)?;
-    match ty {
-      TokenType::Minus => self.emit_byte(OpCode::Negate, None),
-      TokenType::Bang => self.emit_byte(OpCode::Not, None),
+    let (op, fold): (OpCode, fn(Value) -> OpResult) = match ty {
+      TokenType::Minus => (OpCode::Negate, ops::negate),
+      TokenType::Bang => (OpCode::Not, ops::not),
       _ => unreachable!(),
+    };
+    if let Some(operand) = self.constant.take() {
+      // If the operation fails we leave it to fail at runtime instead
+      if let Ok(value) = fold(operand.value.clone()) {
+        self.rewind(operand.mark);
+        return self.emit_literal(value);
+      }
     }
+    self.emit_byte(op, None)
   }
   fn binary(&mut self, _: bool) -> Result<(), CedarError> {
     let operator_ty = self
@@ -750,25 +850,94 @@ impl TokenIter {
       Precedence::Call => Precedence::Primary,
       Precedence::Primary => Precedence::Primary,
     };
+    // The left operand was the last thing emitted before we got here
+    let left = self.constant.take();
     self.parse_precedence(precedence)?;
-    match operator_ty {
-      TokenType::Plus => self.emit_byte(OpCode::Add, None),
-      TokenType::Minus => self.emit_byte(OpCode::Subtract, None),
-      TokenType::Star => self.emit_byte(OpCode::Multiply, None),
-      TokenType::Slash => self.emit_byte(OpCode::Divide, None),
-      TokenType::BangEqual => self.emit_byte(OpCode::NotEqual, None),
-      TokenType::EqualEqual => self.emit_byte(OpCode::Equal, None),
-      TokenType::Greater => self.emit_byte(OpCode::Greater, None),
-      TokenType::GreaterEqual => self.emit_byte(OpCode::GreaterOrEqual, None),
-      TokenType::Less => self.emit_byte(OpCode::Less, None),
-      TokenType::LessEqual => self.emit_byte(OpCode::LessOrEqual, None),
+    let right = self.constant.take();
+    let (op, fold): (OpCode, fn(Value, Value) -> OpResult) = match operator_ty {
+      TokenType::Plus => (OpCode::Add, ops::add),
+      TokenType::Minus => (OpCode::Subtract, ops::subtract),
+      TokenType::Star => (OpCode::Multiply, ops::multiply),
+      TokenType::Slash => (OpCode::Divide, ops::divide),
+      TokenType::BangEqual => (OpCode::NotEqual, ops::not_equal),
+      TokenType::EqualEqual => (OpCode::Equal, ops::equal),
+      TokenType::Greater => (OpCode::Greater, ops::greater),
+      TokenType::GreaterEqual => (OpCode::GreaterOrEqual, ops::greater_or_equal),
+      TokenType::Less => (OpCode::Less, ops::less),
+      TokenType::LessEqual => (OpCode::LessOrEqual, ops::less_or_equal),
       _ => unreachable!(),
+    };
+    if let (Some(left), Some(right)) = (left, right) {
+      // If the operation fails we leave it to fail at runtime instead
+      if let Ok(value) = fold(left.value, right.value) {
+        self.rewind(left.mark);
+        return self.emit_literal(value);
+      }
     }
+    self.emit_byte(op, None)
   }
   fn call(&mut self, _: bool) -> Result<(), CedarError> {
     let arg_count = self.argument_list()?;
     self.emit_byte(OpCode::Call, Some(Value::Byte(arg_count)))
   }
+  fn list(&mut self, _: bool) -> Result<(), CedarError> {
+    let mut count = 0;
+    if !self.check(TokenType::RightBracket)? {
+      while {
+        self.expression()?;
+        count += 1;
+        if count == 255 {
+          return Err(CompilerError::error("Cannot have more than 255 elements in a list").into());
+        }
+        self.match_token(TokenType::Comma)?
+      } {}
+    }
+    self.consume(TokenType::RightBracket, "Expect ']' after list elements")?;
+    self.emit_byte(OpCode::BuildList, Some(Value::Byte(count)))
+  }
+  fn index(&mut self, _: bool) -> Result<(), CedarError> {
+    let target = self.constant.take();
+    self.expression()?;
+    let index = self.constant.take();
+    self.consume(TokenType::RightBracket, "Expect ']' after index")?;
+    if let (Some(target), Some(index)) = (target, index) {
+      // If the operation fails we leave it to fail at runtime instead
+      if let Ok(value) = ops::index(target.value, index.value) {
+        self.rewind(target.mark);
+        return self.emit_literal(value);
+      }
+    }
+    self.emit_byte(OpCode::Index, None)
+  }
+  fn dot(&mut self, _: bool) -> Result<(), CedarError> {
+    self.consume(TokenType::Identifier, "Expect method name after '.'")?;
+    let name = self
+      .previous
+      .as_ref()
+      .ok_or_else(|| CompilerError::ice("No previous value in method call"))?
+      .lexeme
+      .to_string();
+    self.consume(TokenType::LeftParen, "Expect '(' after method name")?;
+    let arg_count = self.argument_list()?;
+    let line = self.line()?;
+    self.constant = None;
+    self
+      .chunk()
+      .write_invoke(Value::from(name), arg_count, line)
+  }
+}
+
+// Where the chunk ended at some point during compilation
+#[derive(Debug, Clone, Copy)]
+struct Mark {
+  code: usize,
+  constants: usize,
+}
+
+#[derive(Debug)]
+struct Constant {
+  mark: Mark,
+  value: Value,
 }
 
 #[derive(Debug)]
@@ -784,6 +953,16 @@ impl Local {
       depth: Depth::Initialized(depth),
     }
   }
+  fn reserved() -> Self {
+    Self::new(
+      Token {
+        ty: TokenType::Fn,
+        line: 0,
+        lexeme: "".into(),
+      },
+      0, // depth
+    )
+  }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -835,8 +1014,11 @@ pub enum FunctionType {
 
 #[derive(Debug)]
 pub enum CompilerError {
-  Message { message: Cow<'static, str> },
-  Failed,
+  Message {
+    message: Cow<'static, str>,
+  },
+  /// Compilation failed with every error that was found
+  Failed(Vec<CedarError>),
 }
 
 impl CompilerError {
@@ -867,9 +1049,6 @@ impl CompilerError {
       message: format!("[error] Error: {}", message.into()).into(),
     }
   }
-  fn failed() -> Self {
-    CompilerError::Failed
-  }
   fn ice<M>(message: M) -> Self
   where
     M: Into<Cow<'static, str>>,
@@ -884,7 +1063,7 @@ impl fmt::Display for CompilerError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
       CompilerError::Message { message } => write!(f, "{}", message),
-      CompilerError::Failed => write!(f, ""),
+      CompilerError::Failed(_) => write!(f, ""),
     }
   }
 }
@@ -0,0 +1,132 @@
+use crate::{
+  native::{Context, IntoNativeFunc, NativeFuncHolder},
+  value::Value,
+  CedarError,
+};
+use std::{borrow::Cow, collections::HashMap};
+
+/// The global variables of a VM. The compiler resolves every global it sees
+/// to a numeric slot so that at runtime looking one up is just indexing into
+/// a Vec. The names are kept around so the REPL can keep using globals from
+/// earlier lines and so errors can say which variable went wrong.
+#[derive(Debug, Default)]
+pub struct Globals {
+  names: Vec<Cow<'static, str>>,
+  slots: HashMap<Cow<'static, str>, u16>,
+  // None until the global has been defined
+  values: Vec<Option<Value>>,
+}
+
+impl Globals {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The slot for the global with this name, giving it a new one if it has
+  /// never been seen before. Returns None if every slot is already in use.
+  pub fn resolve(&mut self, name: &str) -> Option<u16> {
+    if let Some(slot) = self.slots.get(name) {
+      return Some(*slot);
+    }
+    if self.names.len() > u16::MAX as usize {
+      return None;
+    }
+    let slot = self.names.len() as u16;
+    let name: Cow<'static, str> = name.to_string().into();
+    self.names.push(name.clone());
+    self.slots.insert(name, slot);
+    self.values.push(None);
+    Some(slot)
+  }
+
+  /// The slot for the global with this name if it has one
+  pub fn slot(&self, name: &str) -> Option<u16> {
+    self.slots.get(name).copied()
+  }
+
+  /// The name of the global in this slot
+  pub fn name(&self, slot: u16) -> Option<&str> {
+    self.names.get(slot as usize).map(|n| n.as_ref())
+  }
+
+  /// The value of the global in this slot if it's been defined
+  pub fn get(&self, slot: u16) -> Option<&Value> {
+    self.values.get(slot as usize).and_then(Option::as_ref)
+  }
+
+  /// Give the global in this slot a value, returning false if the slot
+  /// doesn't exist
+  pub fn define(&mut self, slot: u16, value: Value) -> bool {
+    match self.values.get_mut(slot as usize) {
+      Some(global) => {
+        *global = Some(value);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// Change the value of an already defined global, returning false if it
+  /// hasn't been defined yet
+  pub fn set(&mut self, slot: u16, value: Value) -> bool {
+    match self.values.get_mut(slot as usize) {
+      Some(Some(global)) => {
+        *global = value;
+        true
+      }
+      _ => false,
+    }
+  }
+
+  /// Define a global by name, used to bind natives before any code is compiled
+  pub fn insert<N>(&mut self, name: N, value: Value) -> Option<u16>
+  where
+    N: AsRef<str>,
+  {
+    let slot = self.resolve(name.as_ref())?;
+    self.define(slot, value);
+    Some(slot)
+  }
+
+  /// Bind a Rust function or closure to a global as a native function
+  pub fn register_fn<N, F, Args>(&mut self, name: N, f: F) -> Option<u16>
+  where
+    N: Into<Cow<'static, str>>,
+    F: IntoNativeFunc<Args>,
+  {
+    let name = name.into();
+    let native = NativeFuncHolder::new(name.clone(), f);
+    self.insert(name, Value::NativeFn(native))
+  }
+
+  /// Bind a native that is given the VM's context and its arguments as they
+  /// are. This is for natives that need to call back into Cedar or raise
+  /// their own errors.
+  pub fn register_native<N, F>(&mut self, name: N, arity: usize, f: F) -> Option<u16>
+  where
+    N: Into<Cow<'static, str>>,
+    F: Fn(&mut Context, Vec<Value>) -> Result<Value, CedarError> + 'static,
+  {
+    let name = name.into();
+    let native = NativeFuncHolder::with_context(name.clone(), arity, f);
+    self.insert(name, Value::NativeFn(native))
+  }
+
+  /// The number of slots handed out so far
+  pub fn len(&self) -> usize {
+    self.names.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.names.is_empty()
+  }
+
+  /// Every defined global along with its name
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+    self
+      .names
+      .iter()
+      .zip(&self.values)
+      .filter_map(|(name, value)| Some((name.as_ref(), value.as_ref()?)))
+  }
+}
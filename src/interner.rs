@@ -0,0 +1,58 @@
+use crate::value::{Function, Value};
+use std::{borrow::Borrow, collections::HashSet, rc::Rc};
+
+/// Makes sure that every string constant with the same contents shares the
+/// same storage, so that loading one is just bumping a reference count and
+/// comparing two of them is usually just comparing pointers
+#[derive(Debug, Default)]
+pub struct Interner {
+  strings: HashSet<Interned>,
+}
+
+impl Interner {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The shared copy of this string, adding it if it's the first time we've
+  /// seen it
+  pub fn intern(&mut self, string: &str) -> Rc<String> {
+    if let Some(interned) = self.strings.get(string) {
+      return interned.0.clone();
+    }
+    let interned = Rc::new(string.to_string());
+    self.strings.insert(Interned(interned.clone()));
+    interned
+  }
+
+  /// Swap every string constant in a function, and any function nested in
+  /// its constants, for the shared copy
+  pub fn intern_constants(&mut self, function: &mut Function) {
+    for constant in &mut function.chunk.constants {
+      match constant {
+        Value::String(string) => *string = self.intern(string),
+        Value::Function(function) => self.intern_constants(Rc::make_mut(function)),
+        _ => {}
+      }
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.strings.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.strings.is_empty()
+  }
+}
+
+// Lets the set be searched with a plain &str. Rc<String> hashes exactly like
+// the str it holds so this agrees with the derived Hash.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Interned(Rc<String>);
+
+impl Borrow<str> for Interned {
+  fn borrow(&self) -> &str {
+    &self.0
+  }
+}
@@ -1,16 +1,34 @@
+pub mod assembler;
+pub mod builder;
 pub mod chunk;
 pub mod compiler;
+pub mod globals;
+pub mod interner;
 pub mod libstd;
 pub mod native;
+pub mod ops;
+pub mod optimizer;
+pub mod output;
+pub mod permissions;
 pub mod scanner;
+#[cfg(feature = "serde")]
+pub mod serde_value;
+pub mod userdata;
 pub mod value;
+pub mod verifier;
+pub mod vfs;
 pub mod vm;
 
+pub use assembler::AssemblerError;
+pub use builder::VmBuilder;
+#[cfg(feature = "derive")]
+pub use cedar_derive::CedarValue;
 pub use chunk::ChunkError;
 pub use compiler::CompilerError;
 use scanner::ScannerError;
-use std::{fmt, io, num::ParseFloatError};
-pub use vm::{InterpreterResult, VM};
+use std::{borrow::Cow, fmt, io, num::ParseFloatError};
+pub use verifier::VerifierError;
+pub use vm::{InterpreterResult, Limits, VM};
 
 #[derive(Debug)]
 pub enum CedarError {
@@ -20,6 +38,13 @@ pub enum CedarError {
   CompilerError(CompilerError),
   ParseFloatError(ParseFloatError),
   ChunkError(ChunkError),
+  VerifierError(VerifierError),
+  AssemblerError(AssemblerError),
+  /// Misuse of the embedding API, like calling a function that doesn't exist
+  HostError(Cow<'static, str>),
+  /// A script called `exit` with this code. It isn't a failure as such, but
+  /// unwinds like one so that nothing after the call runs.
+  Exit(i32),
 }
 impl From<io::Error> for CedarError {
   fn from(e: io::Error) -> CedarError {
@@ -51,6 +76,16 @@ impl From<ChunkError> for CedarError {
     CedarError::ChunkError(e)
   }
 }
+impl From<VerifierError> for CedarError {
+  fn from(e: VerifierError) -> CedarError {
+    CedarError::VerifierError(e)
+  }
+}
+impl From<AssemblerError> for CedarError {
+  fn from(e: AssemblerError) -> CedarError {
+    CedarError::AssemblerError(e)
+  }
+}
 
 impl fmt::Display for CedarError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -61,6 +96,10 @@ impl fmt::Display for CedarError {
       CedarError::CompilerError(e) => write!(f, "{}", e),
       CedarError::ParseFloatError(e) => write!(f, "{}", e),
       CedarError::ChunkError(e) => write!(f, "{}", e),
+      CedarError::VerifierError(e) => write!(f, "{}", e),
+      CedarError::AssemblerError(e) => write!(f, "{}", e),
+      CedarError::HostError(e) => write!(f, "[host] Error: {}", e),
+      CedarError::Exit(code) => write!(f, "Script exited with code {}", code),
     }
   }
 }
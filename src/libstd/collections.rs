@@ -0,0 +1,48 @@
+//! Lists and maps are values like strings, so the natives that change one
+//! hand back a changed copy and leave the one they were given alone.
+use crate::{native::Context, value::Value, CedarError};
+use std::{collections::BTreeMap, rc::Rc};
+
+/// The keys of a map in order
+pub fn keys(map: Rc<BTreeMap<String, Value>>) -> Vec<String> {
+  map.keys().cloned().collect()
+}
+
+/// The values of a map in the order of their keys
+pub fn values(map: Rc<BTreeMap<String, Value>>) -> Vec<Value> {
+  map.values().cloned().collect()
+}
+
+/// Whether a list holds a value or a map has a key
+pub fn contains(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let found = match &args[0] {
+    Value::List(list) => list.contains(&args[1]),
+    Value::Map(map) => map.contains_key(cx.arg::<Rc<String>>(&args, 1)?.as_str()),
+    value => return Err(cx.error(format!("Can't search a {}", value.type_name()))),
+  };
+  Ok(Value::Bool(found))
+}
+
+/// The list with a value added to the end
+pub fn push(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let mut list: Rc<Vec<Value>> = cx.arg(&args, 0)?;
+  cx.check_collection_length(list.len().checked_add(1))?;
+  Rc::make_mut(&mut list).push(args[1].clone());
+  Ok(Value::List(list))
+}
+
+/// The map with a key set to a value, replacing any value it already had
+pub fn insert(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let mut map: Rc<BTreeMap<String, Value>> = cx.arg(&args, 0)?;
+  let key: String = cx.arg(&args, 1)?;
+  if !map.contains_key(&key) {
+    cx.check_collection_length(map.len().checked_add(1))?;
+  }
+  Rc::make_mut(&mut map).insert(key, args[2].clone());
+  Ok(Value::Map(map))
+}
+
+/// A map with nothing in it
+pub fn new_map() -> BTreeMap<String, Value> {
+  BTreeMap::new()
+}
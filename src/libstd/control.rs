@@ -0,0 +1,14 @@
+use crate::{native::Context, value::Value, CedarError, InterpreterResult};
+
+/// Call `body` with no arguments and return what it returns. If it raises an
+/// error, `handler` is called with the error's message instead and what it
+/// returns is used.
+pub fn try_call(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let message = match cx.call(&args[0], &[]) {
+    Err(CedarError::InterpreterResult(InterpreterResult::RuntimeError(message, _))) => message,
+    Err(CedarError::HostError(message)) => message,
+    result => return result,
+  };
+  let message = cx.string(message.into_owned());
+  cx.call(&args[1], &[message])
+}
@@ -0,0 +1,59 @@
+use crate::{
+  native::{Context, IntoValue},
+  permissions::Permission,
+  value::Value,
+  CedarError,
+};
+use std::{env, path::Path};
+
+/// The arguments given to the script as a list of strings
+pub fn args(cx: &mut Context, _: Vec<Value>) -> Result<Value, CedarError> {
+  Ok(cx.args().to_vec().to_value())
+}
+
+/// The value of an environment variable, or null if it isn't set
+pub fn env_get(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let name: String = cx.arg(&args, 0)?;
+  cx.require(Permission::Env)?;
+  Ok(match env::var(&name) {
+    Ok(value) => cx.string(value),
+    Err(_) => Value::Null,
+  })
+}
+
+/// Set an environment variable for this process and any it runs
+pub fn env_set(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let name: String = cx.arg(&args, 0)?;
+  let value: String = cx.arg(&args, 1)?;
+  cx.require(Permission::Env)?;
+  if name.is_empty() || name.contains(&['=', '\0'][..]) || value.contains('\0') {
+    return Err(cx.error(format!(
+      "Can't set an environment variable named {:?}",
+      name
+    )));
+  }
+  env::set_var(name, value);
+  Ok(Value::Null)
+}
+
+/// The directory the script is running in
+pub fn cwd(cx: &mut Context, _: Vec<Value>) -> Result<Value, CedarError> {
+  cx.require(Permission::Read(Path::new(".")))?;
+  let cwd = env::current_dir()
+    .map_err(|e| cx.error(format!("Could not get the current directory: {}", e)))?;
+  Ok(cx.string(cwd.to_string_lossy()))
+}
+
+/// The name of the machine the script is running on
+pub fn hostname(cx: &mut Context, _: Vec<Value>) -> Result<Value, CedarError> {
+  cx.require(Permission::Env)?;
+  let hostname = gethostname::gethostname();
+  Ok(cx.string(hostname.to_string_lossy()))
+}
+
+/// Stop the script, with `code` becoming the exit code of `cedarc`. Exit
+/// codes only go up to 255.
+pub fn exit(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let code: u8 = cx.arg(&args, 0)?;
+  Err(CedarError::Exit(code.into()))
+}
@@ -0,0 +1,291 @@
+use super::{io_error, path_arg};
+use crate::{
+  native::{Context, IntoValue},
+  permissions::Permission,
+  value::Value,
+  CedarError,
+};
+use std::{
+  collections::BTreeMap,
+  io,
+  path::{Path, PathBuf},
+  time::UNIX_EPOCH,
+};
+
+// Deeper than any real directory tree, so that walking a filesystem that
+// loops some other way than through links still stops
+const MAX_WALK_DEPTH: usize = 256;
+
+pub fn exists(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  cx.require(Permission::Read(&path))?;
+  Ok(Value::Bool(cx.fs().metadata(&path).is_ok()))
+}
+
+pub fn is_dir(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  cx.require(Permission::Read(&path))?;
+  let is_dir = cx.fs().metadata(&path).map(|m| m.is_dir).unwrap_or(false);
+  Ok(Value::Bool(is_dir))
+}
+
+/// The names of everything in a directory in sorted order
+pub fn list_dir(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  cx.require(Permission::Read(&path))?;
+  let mut names = cx
+    .fs()
+    .read_dir(&path)
+    .map_err(|e| io_error(cx, "list", &path, e))?;
+  names.sort();
+  Ok(names.to_value())
+}
+
+/// Create a directory and any missing directories above it like `mkdir -p`
+pub fn mkdir(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  cx.require(Permission::Write(&path))?;
+  cx.fs()
+    .create_dir_all(&path)
+    .map_err(|e| io_error(cx, "create", &path, e))?;
+  Ok(Value::Null)
+}
+
+/// Remove a file or a directory with nothing in it
+pub fn remove(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  cx.require(Permission::Write(&path))?;
+  let fs = cx.fs();
+  fs.metadata(&path)
+    .and_then(|metadata| {
+      if metadata.is_dir {
+        fs.remove_dir(&path)
+      } else {
+        fs.remove_file(&path)
+      }
+    })
+    .map_err(|e| io_error(cx, "remove", &path, e))?;
+  Ok(Value::Null)
+}
+
+/// Remove a file, or a directory along with everything in it
+pub fn remove_all(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  cx.require(Permission::Write(&path))?;
+  let fs = cx.fs();
+  fs.metadata(&path)
+    .and_then(|metadata| {
+      if metadata.is_dir {
+        fs.remove_dir_all(&path)
+      } else {
+        fs.remove_file(&path)
+      }
+    })
+    .map_err(|e| io_error(cx, "remove", &path, e))?;
+  Ok(Value::Null)
+}
+
+pub fn rename(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let from = path_arg(cx, &args, 0)?;
+  let to = path_arg(cx, &args, 1)?;
+  cx.require(Permission::Write(&from))?;
+  cx.require(Permission::Write(&to))?;
+  cx.fs()
+    .rename(&from, &to)
+    .map_err(|e| io_error(cx, "rename", &from, e))?;
+  Ok(Value::Null)
+}
+
+pub fn copy(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let from = path_arg(cx, &args, 0)?;
+  let to = path_arg(cx, &args, 1)?;
+  cx.require(Permission::Read(&from))?;
+  cx.require(Permission::Write(&to))?;
+  cx.fs()
+    .copy(&from, &to)
+    .map_err(|e| io_error(cx, "copy", &from, e))?;
+  Ok(Value::Null)
+}
+
+pub fn append_file(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  let content: String = cx.arg(&args, 1)?;
+  cx.require(Permission::Write(&path))?;
+  cx.fs()
+    .append(&path, content.as_bytes())
+    .map_err(|e| io_error(cx, "append to", &path, e))?;
+  Ok(Value::Null)
+}
+
+/// A map with the `size` of a file in bytes, when it was `modified` in
+/// seconds since the Unix epoch and whether it `is-dir`
+pub fn metadata(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  cx.require(Permission::Read(&path))?;
+  let metadata = cx
+    .fs()
+    .metadata(&path)
+    .map_err(|e| io_error(cx, "read metadata of", &path, e))?;
+  let modified = metadata
+    .modified
+    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+    .map(|since| since.as_secs_f64());
+  let mut map = BTreeMap::new();
+  map.insert("size".to_string(), Value::Number(metadata.len as f64));
+  map.insert("modified".to_string(), modified.to_value());
+  map.insert("is-dir".to_string(), Value::Bool(metadata.is_dir));
+  Ok(Value::from(map))
+}
+
+/// Every file inside of a directory and the directories in it, in sorted
+/// order. Links to files are listed but links to directories aren't followed,
+/// so a walk never leaves the directory it started in.
+pub fn walk(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  let mut files = Vec::new();
+  walk_dir(cx, &path, 0, &mut files)?;
+  Ok(paths(files))
+}
+
+/// Every file matching a pattern where `*` matches any part of a name, `?`
+/// matches one character of a name and `**` matches any number of
+/// directories, e.g. `src/**/*.cdr`
+pub fn glob(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let pattern: String = cx.arg(&args, 0)?;
+  let segments = pattern.split('/').collect::<Vec<_>>();
+  // Only the directory before the first wildcard needs to be walked
+  let literal = segments
+    .iter()
+    .take_while(|segment| !segment.contains(&['*', '?'][..]))
+    .count();
+  let base = match segments[..literal].join("/") {
+    base if base.is_empty() && pattern.starts_with('/') => PathBuf::from("/"),
+    base => PathBuf::from(base),
+  };
+  cx.require(Permission::Read(&base))?;
+
+  if literal == segments.len() {
+    let is_file = cx.fs().metadata(&base).map(|m| !m.is_dir).unwrap_or(false);
+    return Ok(paths(if is_file { vec![base] } else { Vec::new() }));
+  }
+  let root = if base.as_os_str().is_empty() {
+    Path::new(".")
+  } else {
+    &base
+  };
+  // Nothing can match inside of a directory that isn't there
+  if !cx.fs().metadata(root).map(|m| m.is_dir).unwrap_or(false) {
+    return Ok(paths(Vec::new()));
+  }
+  let mut files = Vec::new();
+  walk_dir(cx, root, 0, &mut files)?;
+  let matching = files
+    .into_iter()
+    .filter_map(|file| {
+      let relative = file.strip_prefix(root).ok()?;
+      let names = relative
+        .iter()
+        .map(|name| name.to_string_lossy())
+        .collect::<Vec<_>>();
+      let names = names.iter().map(|name| name.as_ref()).collect::<Vec<_>>();
+      if glob_match(&segments[literal..], &names) {
+        Some(base.join(relative))
+      } else {
+        None
+      }
+    })
+    .collect();
+  Ok(paths(matching))
+}
+
+// Every directory entered is checked against the permissions, since a
+// directory that's allowed can have one inside of it that isn't
+fn walk_dir(
+  cx: &Context,
+  dir: &Path,
+  depth: usize,
+  files: &mut Vec<PathBuf>,
+) -> Result<(), CedarError> {
+  cx.require(Permission::Read(dir))?;
+  if depth > MAX_WALK_DEPTH {
+    return Err(io_error(
+      cx,
+      "walk",
+      dir,
+      io::Error::other("Directories are nested too deeply"),
+    ));
+  }
+  let fs = cx.fs();
+  let mut names = fs.read_dir(dir).map_err(|e| io_error(cx, "walk", dir, e))?;
+  names.sort();
+  for name in names {
+    let path = dir.join(name);
+    let metadata = fs
+      .symlink_metadata(&path)
+      .map_err(|e| io_error(cx, "walk", &path, e))?;
+    if metadata.is_dir {
+      walk_dir(cx, &path, depth + 1, files)?;
+    } else if !metadata.is_symlink || fs.metadata(&path).map_or(true, |m| !m.is_dir) {
+      files.push(path);
+    }
+  }
+  Ok(())
+}
+
+fn paths(paths: Vec<PathBuf>) -> Value {
+  paths
+    .into_iter()
+    .map(|path| path.to_string_lossy().into_owned())
+    .collect::<Vec<_>>()
+    .to_value()
+}
+
+fn glob_match(pattern: &[&str], names: &[&str]) -> bool {
+  wildcard_match(
+    pattern,
+    names,
+    |segment| *segment == "**",
+    |segment, name| {
+      let segment = segment.chars().collect::<Vec<_>>();
+      let name = name.chars().collect::<Vec<_>>();
+      name_match(&segment, &name)
+    },
+  )
+}
+
+fn name_match(pattern: &[char], name: &[char]) -> bool {
+  wildcard_match(pattern, name, |p| *p == '*', |p, c| *p == '?' || p == c)
+}
+
+// Match a pattern where a star stands for any number of items. Rather than
+// trying every way of splitting the input between the stars, which takes
+// exponential time, only the most recent star is ever backtracked to. That
+// is enough because a later star can match anything an earlier one could
+// have, so this takes at most pattern length times input length steps.
+fn wildcard_match<P, T>(
+  pattern: &[P],
+  input: &[T],
+  is_star: impl Fn(&P) -> bool,
+  matches: impl Fn(&P, &T) -> bool,
+) -> bool {
+  let (mut p, mut i) = (0, 0);
+  // Where to carry on from after the last star and how much it has matched
+  let mut star = None;
+  while i < input.len() {
+    if p < pattern.len() && is_star(&pattern[p]) {
+      star = Some((p + 1, i));
+      p += 1;
+    } else if p < pattern.len() && matches(&pattern[p], &input[i]) {
+      p += 1;
+      i += 1;
+    } else if let Some((after, matched)) = star {
+      // Have the star take one more item and try again from just after it
+      star = Some((after, matched + 1));
+      p = after;
+      i = matched + 1;
+    } else {
+      return false;
+    }
+  }
+  pattern[p..].iter().all(is_star)
+}
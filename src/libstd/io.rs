@@ -1,8 +1,106 @@
-use std::{borrow::Cow, fs, path::PathBuf};
+use super::{io_error, path_arg};
+use crate::{
+  globals::Globals,
+  native::Context,
+  permissions::Permission,
+  userdata::{UserData, UserRef, UserType},
+  value::Value,
+  CedarError,
+};
+use std::rc::Rc;
 
-pub fn read_file(path: Cow<'static, str>) -> Cow<'static, str> {
-  fs::read_to_string(&PathBuf::from(&*path)).unwrap().into()
+pub fn read_file(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  cx.require(Permission::Read(&path))?;
+  cx.fs()
+    .read_to_string(&path)
+    .map(|content| cx.string(content))
+    .map_err(|e| io_error(cx, "read", &path, e))
 }
-pub fn write_file(path: Cow<'static, str>, content: Cow<'static, str>) {
-  fs::write(&PathBuf::from(&*path), &*content).unwrap();
+
+pub fn write_file(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let path = path_arg(cx, &args, 0)?;
+  let content: String = cx.arg(&args, 1)?;
+  cx.require(Permission::Write(&path))?;
+  cx.fs()
+    .write(&path, content.as_bytes())
+    .map(|_| Value::Null)
+    .map_err(|e| io_error(cx, "write", &path, e))
+}
+
+// The next line of stdin without its line ending, or None at the end of it
+fn next_line(cx: &mut Context) -> Result<Option<String>, CedarError> {
+  // Anything printed before asking for input, like a prompt, has to be seen
+  // before the script waits on it
+  cx.stdout().flush()?;
+  let mut line = String::new();
+  let read = cx
+    .stdin()
+    .read_line(&mut line)
+    .map_err(|e| cx.error(format!("Could not read stdin: {}", e)))?;
+  if read == 0 {
+    return Ok(None);
+  }
+  if line.ends_with('\n') {
+    line.pop();
+    if line.ends_with('\r') {
+      line.pop();
+    }
+  }
+  Ok(Some(line))
+}
+
+pub fn read_line(cx: &mut Context, _args: Vec<Value>) -> Result<Value, CedarError> {
+  Ok(match next_line(cx)? {
+    Some(line) => cx.string(line),
+    None => Value::Null,
+  })
+}
+
+pub fn read_stdin(cx: &mut Context, _args: Vec<Value>) -> Result<Value, CedarError> {
+  cx.stdout().flush()?;
+  let mut input = String::new();
+  cx.stdin()
+    .read_to_string(&mut input)
+    .map_err(|e| cx.error(format!("Could not read stdin: {}", e)))?;
+  Ok(cx.string(input))
+}
+
+/// The lines of stdin, read one at a time as the script asks for them
+pub struct Lines {
+  finished: bool,
+}
+
+/// Bind `stdin-lines` along with the `next` method of the value it makes:
+///
+/// ```text
+/// let lines = stdin-lines();
+/// for let line = lines.next(); line != null; line = lines.next() {
+///   print upper(line);
+/// }
+/// ```
+pub fn register_lines(globals: &mut Globals) {
+  let lines_type = Rc::new(
+    UserType::new::<Lines>("Lines").native_method("next", 1, |cx, args| {
+      let lines: UserRef<Lines> = cx.arg(&args, 0)?;
+      // Once stdin has ended it stays ended, even for a terminal that
+      // would go on reading after an EOF
+      if lines.borrow().finished {
+        return Ok(Value::Null);
+      }
+      match next_line(cx)? {
+        Some(line) => Ok(cx.string(line)),
+        None => {
+          lines.borrow_mut().finished = true;
+          Ok(Value::Null)
+        }
+      }
+    }),
+  );
+  globals.register_fn("stdin-lines", move || {
+    Value::UserData(Rc::new(UserData::new(
+      lines_type.clone(),
+      Lines { finished: false },
+    )))
+  });
 }
@@ -0,0 +1,396 @@
+//! JSON objects become maps, arrays become lists and everything else becomes
+//! the value of the same name. Maps keep their keys sorted, so stringifying
+//! the same value always gives the same text.
+use crate::{native::Context, ops, value::Value, CedarError};
+use std::{borrow::Cow, collections::BTreeMap, fmt::Write, rc::Rc};
+
+// Far deeper than any real document, so a malicious one can't overflow the
+// stack
+const MAX_DEPTH: usize = 512;
+
+pub fn parse(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let text: Rc<String> = cx.arg(&args, 0)?;
+  let mut parser = Parser {
+    text: &text,
+    pos: 0,
+    depth: 0,
+  };
+  parser
+    .document()
+    .map_err(|message| cx.error(parser.describe(message)))
+}
+
+/// The value as JSON, indented by the given number of spaces or all on one
+/// line if it's null or 0
+pub fn stringify(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let indent: Option<usize> = cx.arg(&args, 1)?;
+  let mut out = String::new();
+  let max = cx.max_string_len();
+  write_value(&mut out, &args[0], indent.unwrap_or(0), 0, max).map_err(|e| cx.error(e))?;
+  Ok(cx.string(out))
+}
+
+struct Parser<'a> {
+  text: &'a str,
+  pos: usize,
+  depth: usize,
+}
+
+type Parsed<T> = Result<T, String>;
+
+impl Parser<'_> {
+  fn document(&mut self) -> Parsed<Value> {
+    let value = self.value()?;
+    self.whitespace();
+    match self.peek() {
+      None => Ok(value),
+      Some(c) => Err(format!("Unexpected {:?} after the end of the JSON", c)),
+    }
+  }
+
+  // Where the parser is in the text, counting lines and characters from 1
+  fn describe(&self, message: String) -> String {
+    let before = &self.text[..self.pos];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    format!(
+      "Invalid JSON at line {}, column {}: {}",
+      line, column, message
+    )
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.text[self.pos..].chars().next()
+  }
+
+  fn bump(&mut self) -> Option<char> {
+    let c = self.peek()?;
+    self.pos += c.len_utf8();
+    Some(c)
+  }
+
+  fn whitespace(&mut self) {
+    while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+      self.pos += 1;
+    }
+  }
+
+  fn expect(&mut self, expected: char) -> Parsed<()> {
+    match self.peek() {
+      Some(c) if c == expected => {
+        self.pos += 1;
+        Ok(())
+      }
+      Some(c) => Err(format!("Expected {:?} but found {:?}", expected, c)),
+      None => Err(format!("Expected {:?} but the text ended", expected)),
+    }
+  }
+
+  fn value(&mut self) -> Parsed<Value> {
+    self.whitespace();
+    match self.peek() {
+      Some('{') => self.nested(Self::object),
+      Some('[') => self.nested(Self::array),
+      Some('"') => self.string().map(Value::from),
+      Some('-' | '0'..='9') => self.number(),
+      Some('t') => self.literal("true", Value::Bool(true)),
+      Some('f') => self.literal("false", Value::Bool(false)),
+      Some('n') => self.literal("null", Value::Null),
+      Some(c) => Err(format!("Unexpected {:?}", c)),
+      None => Err("Expected a value but the text ended".into()),
+    }
+  }
+
+  fn nested(&mut self, parse: fn(&mut Self) -> Parsed<Value>) -> Parsed<Value> {
+    if self.depth == MAX_DEPTH {
+      return Err("Nested too deeply".into());
+    }
+    self.depth += 1;
+    let value = parse(self);
+    self.depth -= 1;
+    value
+  }
+
+  fn literal(&mut self, word: &str, value: Value) -> Parsed<Value> {
+    if self.text[self.pos..].starts_with(word) {
+      self.pos += word.len();
+      Ok(value)
+    } else {
+      Err(format!("Expected {}", word))
+    }
+  }
+
+  fn object(&mut self) -> Parsed<Value> {
+    self.expect('{')?;
+    let mut map = BTreeMap::new();
+    self.whitespace();
+    if self.peek() == Some('}') {
+      self.pos += 1;
+      return Ok(Value::from(map));
+    }
+    loop {
+      self.whitespace();
+      if self.peek() != Some('"') {
+        return Err("Expected a string for the key of an object".into());
+      }
+      let key = self.string()?;
+      self.whitespace();
+      self.expect(':')?;
+      let value = self.value()?;
+      map.insert(key, value);
+      self.whitespace();
+      match self.peek() {
+        Some(',') => self.pos += 1,
+        Some('}') => {
+          self.pos += 1;
+          return Ok(Value::from(map));
+        }
+        _ => return Err("Expected ',' or '}' after a value in an object".into()),
+      }
+    }
+  }
+
+  fn array(&mut self) -> Parsed<Value> {
+    self.expect('[')?;
+    let mut list = Vec::new();
+    self.whitespace();
+    if self.peek() == Some(']') {
+      self.pos += 1;
+      return Ok(Value::from(list));
+    }
+    loop {
+      list.push(self.value()?);
+      self.whitespace();
+      match self.peek() {
+        Some(',') => self.pos += 1,
+        Some(']') => {
+          self.pos += 1;
+          return Ok(Value::from(list));
+        }
+        _ => return Err("Expected ',' or ']' after a value in an array".into()),
+      }
+    }
+  }
+
+  fn number(&mut self) -> Parsed<Value> {
+    let start = self.pos;
+    if self.peek() == Some('-') {
+      self.pos += 1;
+    }
+    match self.peek() {
+      Some('0') => self.pos += 1,
+      Some('1'..='9') => self.digits(),
+      _ => return Err("Expected a digit".into()),
+    }
+    if self.peek() == Some('.') {
+      self.pos += 1;
+      self.required_digits()?;
+    }
+    if let Some('e' | 'E') = self.peek() {
+      self.pos += 1;
+      if let Some('+' | '-') = self.peek() {
+        self.pos += 1;
+      }
+      self.required_digits()?;
+    }
+    let n = self.text[start..self.pos]
+      .parse::<f64>()
+      .map_err(|e| e.to_string())?;
+    Ok(Value::Number(n))
+  }
+
+  fn digits(&mut self) {
+    while let Some('0'..='9') = self.peek() {
+      self.pos += 1;
+    }
+  }
+
+  fn required_digits(&mut self) -> Parsed<()> {
+    match self.peek() {
+      Some('0'..='9') => {
+        self.digits();
+        Ok(())
+      }
+      _ => Err("Expected a digit".into()),
+    }
+  }
+
+  fn string(&mut self) -> Parsed<String> {
+    self.expect('"')?;
+    let mut s = String::new();
+    loop {
+      match self.bump() {
+        Some('"') => return Ok(s),
+        Some('\\') => s.push(self.escape()?),
+        Some(c) if (c as u32) < 0x20 => {
+          self.pos -= 1;
+          return Err("Control characters must be escaped in strings".into());
+        }
+        Some(c) => s.push(c),
+        None => return Err("Unterminated string".into()),
+      }
+    }
+  }
+
+  fn escape(&mut self) -> Parsed<char> {
+    Ok(match self.bump() {
+      Some('"') => '"',
+      Some('\\') => '\\',
+      Some('/') => '/',
+      Some('b') => '\u{8}',
+      Some('f') => '\u{c}',
+      Some('n') => '\n',
+      Some('r') => '\r',
+      Some('t') => '\t',
+      Some('u') => {
+        let high = self.hex()?;
+        if (0xD800..0xDC00).contains(&high) {
+          // A character outside of the basic plane is written as two
+          // escapes, a surrogate pair
+          if !self.text[self.pos..].starts_with("\\u") {
+            return Err("Expected the second half of a surrogate pair".into());
+          }
+          self.pos += 2;
+          let low = self.hex()?;
+          if !(0xDC00..0xE000).contains(&low) {
+            return Err("Invalid second half of a surrogate pair".into());
+          }
+          let c = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+          char::from_u32(c).ok_or("Invalid unicode escape")?
+        } else {
+          char::from_u32(high).ok_or("Unpaired surrogate in a unicode escape")?
+        }
+      }
+      Some(c) => {
+        self.pos -= c.len_utf8();
+        return Err(format!("Invalid escape '\\{}'", c));
+      }
+      None => return Err("Unterminated string".into()),
+    })
+  }
+
+  fn hex(&mut self) -> Parsed<u32> {
+    let digits = self.text[self.pos..]
+      .get(..4)
+      .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+      .ok_or("Expected four hex digits in a unicode escape")?;
+    self.pos += 4;
+    Ok(u32::from_str_radix(digits, 16).expect("digits were checked to be hex"))
+  }
+}
+
+fn write_value(
+  out: &mut String,
+  value: &Value,
+  indent: usize,
+  level: usize,
+  max: usize,
+) -> Result<(), String> {
+  match value {
+    Value::Null => out.push_str("null"),
+    Value::Bool(b) => write!(out, "{}", b).expect("writing to a string can't fail"),
+    Value::Byte(b) => write!(out, "{}", b).expect("writing to a string can't fail"),
+    Value::Number(n) if n.is_finite() => {
+      write!(out, "{}", n).expect("writing to a string can't fail")
+    }
+    Value::Number(n) => return Err(format!("Can't convert {} to JSON", n)),
+    Value::String(s) => write_string(out, s),
+    Value::List(list) => {
+      write_nested(
+        out,
+        '[',
+        ']',
+        list.iter(),
+        indent,
+        level,
+        max,
+        |out, value| write_value(out, value, indent, level + 1, max),
+      )?;
+    }
+    Value::Map(map) => {
+      write_nested(
+        out,
+        '{',
+        '}',
+        map.iter(),
+        indent,
+        level,
+        max,
+        |out, (key, value)| {
+          write_string(out, key);
+          out.push_str(if indent > 0 { ": " } else { ":" });
+          write_value(out, value, indent, level + 1, max)
+        },
+      )?;
+    }
+    Value::Function(_) | Value::NativeFn(_) | Value::UserData(_) => {
+      return Err(format!(
+        "Can't convert {} to JSON because it's a {}",
+        value,
+        value.type_name()
+      ))
+    }
+  }
+  Ok(())
+}
+
+// Write the entries of a list or map between brackets, one per line if
+// indenting. The text is checked against the longest string allowed as it
+// grows, since a list holding the same list many times over can be tiny in
+// memory but enormous written out.
+#[allow(clippy::too_many_arguments)]
+fn write_nested<T>(
+  out: &mut String,
+  open: char,
+  close: char,
+  entries: impl ExactSizeIterator<Item = T>,
+  indent: usize,
+  level: usize,
+  max: usize,
+  mut write_entry: impl FnMut(&mut String, T) -> Result<(), String>,
+) -> Result<(), String> {
+  out.push(open);
+  let empty = entries.len() == 0;
+  for (i, entry) in entries.enumerate() {
+    if i > 0 {
+      out.push(',');
+    }
+    if indent > 0 {
+      check_length(out, indent.checked_mul(level + 1), max)?;
+      out.push('\n');
+      out.push_str(&" ".repeat(indent * (level + 1)));
+    }
+    write_entry(out, entry)?;
+    check_length(out, Some(0), max)?;
+  }
+  if indent > 0 && !empty {
+    check_length(out, indent.checked_mul(level), max)?;
+    out.push('\n');
+    out.push_str(&" ".repeat(indent * level));
+  }
+  out.push(close);
+  Ok(())
+}
+
+fn check_length(out: &str, extra: Option<usize>, max: usize) -> Result<(), String> {
+  let length = extra.and_then(|extra| out.len().checked_add(extra));
+  ops::check_length(length, max).map_err(Cow::into_owned)
+}
+
+fn write_string(out: &mut String, s: &str) {
+  out.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      c if (c as u32) < 0x20 => {
+        write!(out, "\\u{:04x}", c as u32).expect("writing to a string can't fail")
+      }
+      c => out.push(c),
+    }
+  }
+  out.push('"');
+}
@@ -0,0 +1,164 @@
+use crate::{globals::Globals, native::Context, value::Value, CedarError};
+use std::{
+  cell::Cell,
+  rc::Rc,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+pub fn floor(n: f64) -> f64 {
+  n.floor()
+}
+pub fn ceil(n: f64) -> f64 {
+  n.ceil()
+}
+/// Round to the nearest integer, with halves rounded away from zero
+pub fn round(n: f64) -> f64 {
+  n.round()
+}
+pub fn trunc(n: f64) -> f64 {
+  n.trunc()
+}
+pub fn abs(n: f64) -> f64 {
+  n.abs()
+}
+pub fn sqrt(n: f64) -> f64 {
+  n.sqrt()
+}
+pub fn pow(base: f64, exponent: f64) -> f64 {
+  base.powf(exponent)
+}
+pub fn exp(n: f64) -> f64 {
+  n.exp()
+}
+/// The natural logarithm
+pub fn log(n: f64) -> f64 {
+  n.ln()
+}
+pub fn log2(n: f64) -> f64 {
+  n.log2()
+}
+pub fn log10(n: f64) -> f64 {
+  n.log10()
+}
+pub fn sin(n: f64) -> f64 {
+  n.sin()
+}
+pub fn cos(n: f64) -> f64 {
+  n.cos()
+}
+pub fn tan(n: f64) -> f64 {
+  n.tan()
+}
+pub fn asin(n: f64) -> f64 {
+  n.asin()
+}
+pub fn acos(n: f64) -> f64 {
+  n.acos()
+}
+pub fn atan(n: f64) -> f64 {
+  n.atan()
+}
+pub fn atan2(y: f64, x: f64) -> f64 {
+  y.atan2(x)
+}
+pub fn min(a: f64, b: f64) -> f64 {
+  a.min(b)
+}
+pub fn max(a: f64, b: f64) -> f64 {
+  a.max(b)
+}
+pub fn is_integer(n: f64) -> bool {
+  n.is_finite() && n.fract() == 0.0
+}
+pub fn is_nan(n: f64) -> bool {
+  n.is_nan()
+}
+pub fn is_finite(n: f64) -> bool {
+  n.is_finite()
+}
+
+pub fn clamp(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let n: f64 = cx.arg(&args, 0)?;
+  let min: f64 = cx.arg(&args, 1)?;
+  let max: f64 = cx.arg(&args, 2)?;
+  if min > max || min.is_nan() || max.is_nan() {
+    return Err(cx.error(format!(
+      "Can't clamp between {} and {}",
+      Value::Number(min),
+      Value::Number(max)
+    )));
+  }
+  Ok(Value::Number(n.clamp(min, max)))
+}
+
+/// The number written out with `precision` digits after the decimal point
+pub fn format_number(n: f64, precision: u8) -> String {
+  format!("{:.*}", precision as usize, n)
+}
+
+/// Bind `random`, `random-int` and `seed-random`, which share one generator
+/// so that seeding it makes every random number after it reproducible
+pub fn register_random(globals: &mut Globals) {
+  let rng = Rc::new(Rng::new(initial_seed()));
+
+  let state = rng.clone();
+  globals.register_fn("seed-random", move |seed: u64| state.seed(seed));
+
+  let state = rng.clone();
+  globals.register_fn("random", move || state.float());
+
+  globals.register_native("random-int", 2, move |cx, args| {
+    let min: i64 = cx.arg(&args, 0)?;
+    let max: i64 = cx.arg(&args, 1)?;
+    if min > max {
+      return Err(cx.error(format!(
+        "Can't pick a random integer from {} to {}",
+        min, max
+      )));
+    }
+    // Scale a 64 bit number down to the size of the range
+    let range = (max as i128 - min as i128 + 1) as u128;
+    let offset = (rng.next() as u128 * range) >> 64;
+    Ok(Value::Number((min as i128 + offset as i128) as f64))
+  });
+}
+
+fn initial_seed() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|time| time.as_nanos() as u64)
+    .unwrap_or(0)
+}
+
+// SplitMix64, which is small and fast with good enough randomness for
+// scripts. It's not suitable for anything to do with security.
+struct Rng {
+  state: Cell<u64>,
+}
+
+impl Rng {
+  fn new(seed: u64) -> Self {
+    Self {
+      state: Cell::new(seed),
+    }
+  }
+
+  fn seed(&self, seed: u64) {
+    self.state.set(seed);
+  }
+
+  fn next(&self) -> u64 {
+    let state = self.state.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+    self.state.set(state);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+  }
+
+  // A number from 0 up to but not including 1 using the 53 bits a float can
+  // hold exactly
+  fn float(&self) -> f64 {
+    (self.next() >> 11) as f64 / (1u64 << 53) as f64
+  }
+}
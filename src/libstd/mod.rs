@@ -1,26 +1,184 @@
-use crate::{native::NativeFuncHolder, value::Value};
-use std::{borrow::Cow, collections::HashMap, rc::Rc};
+use crate::{globals::Globals, native::Context, value::Value, CedarError};
+use std::path::{Path, PathBuf};
 
+pub mod collections;
+pub mod control;
+pub mod env;
+pub mod fs;
 pub mod io;
+pub mod json;
+pub mod math;
+pub mod process;
+pub mod string;
 
-use self::io::*;
-
-pub fn load() -> HashMap<Cow<'static, str>, Value> {
-  let mut std = HashMap::new();
-  let read: fn(Cow<'static, str>) -> Cow<'static, str> = read_file;
-  std.insert(
-    "read-file".into(),
-    Value::NativeFn(NativeFuncHolder {
-      inner: Rc::new(read),
-    }),
-  );
-  let write: fn(Cow<'static, str>, Cow<'static, str>) -> () = write_file;
-  std.insert(
-    "write-file".into(),
-    Value::NativeFn(NativeFuncHolder {
-      inner: Rc::new(write),
-    }),
-  );
+/// A part of the standard library that can be loaded into a VM on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Module {
+  /// Reading and writing files and reading stdin
+  Io,
+  /// Working with files and directories
+  Fs,
+  /// Searching, slicing and changing strings
+  String,
+  /// Rounding, trigonometry, logarithms and random numbers
+  Math,
+  /// Reading and writing JSON
+  Json,
+  /// Building and searching lists and maps
+  Collections,
+  /// Running other programs
+  Process,
+  /// Catching errors with `try`
+  Control,
+  /// Arguments, environment variables and exiting
+  Env,
+}
+
+impl Module {
+  /// Every module in the standard library
+  pub const ALL: &'static [Module] = &[
+    Module::Io,
+    Module::Fs,
+    Module::String,
+    Module::Math,
+    Module::Json,
+    Module::Collections,
+    Module::Process,
+    Module::Control,
+    Module::Env,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Module::Io => "io",
+      Module::Fs => "fs",
+      Module::String => "string",
+      Module::Math => "math",
+      Module::Json => "json",
+      Module::Collections => "collections",
+      Module::Process => "process",
+      Module::Control => "control",
+      Module::Env => "env",
+    }
+  }
+}
+
+/// Bind the natives of one module to global slots
+pub fn load_module(globals: &mut Globals, module: Module) {
+  match module {
+    Module::Io => {
+      globals.register_native("read-file", 1, io::read_file);
+      globals.register_native("write-file", 2, io::write_file);
+      globals.register_native("read-line", 0, io::read_line);
+      globals.register_native("read-stdin", 0, io::read_stdin);
+      io::register_lines(globals);
+    }
+    Module::Fs => {
+      globals.register_native("exists", 1, fs::exists);
+      globals.register_native("is-dir", 1, fs::is_dir);
+      globals.register_native("list-dir", 1, fs::list_dir);
+      globals.register_native("mkdir", 1, fs::mkdir);
+      globals.register_native("remove", 1, fs::remove);
+      globals.register_native("remove-all", 1, fs::remove_all);
+      globals.register_native("rename", 2, fs::rename);
+      globals.register_native("copy", 2, fs::copy);
+      globals.register_native("append-file", 2, fs::append_file);
+      globals.register_native("metadata", 1, fs::metadata);
+      globals.register_native("walk", 1, fs::walk);
+      globals.register_native("glob", 1, fs::glob);
+    }
+    Module::String => {
+      globals.register_native("length", 1, string::length);
+      globals.register_native("substring", 3, string::substring);
+      globals.register_native("split", 2, string::split);
+      globals.register_native("join", 2, string::join);
+      globals.register_fn("trim", string::trim);
+      globals.register_fn("trim-start", string::trim_start);
+      globals.register_fn("trim-end", string::trim_end);
+      globals.register_native("replace", 3, string::replace);
+      globals.register_fn("find", string::find);
+      globals.register_fn("starts-with", string::starts_with);
+      globals.register_fn("ends-with", string::ends_with);
+      globals.register_fn("upper", string::upper);
+      globals.register_fn("lower", string::lower);
+      globals.register_native("repeat", 2, string::repeat);
+      globals.register_native("pad-start", 3, string::pad_start);
+      globals.register_native("pad-end", 3, string::pad_end);
+      globals.register_native("chars", 1, string::chars);
+      globals.register_fn("parse-number", string::parse_number);
+    }
+    Module::Math => {
+      globals.insert("pi", Value::Number(std::f64::consts::PI));
+      globals.insert("e", Value::Number(std::f64::consts::E));
+      globals.register_fn("floor", math::floor);
+      globals.register_fn("ceil", math::ceil);
+      globals.register_fn("round", math::round);
+      globals.register_fn("trunc", math::trunc);
+      globals.register_fn("abs", math::abs);
+      globals.register_fn("sqrt", math::sqrt);
+      globals.register_fn("pow", math::pow);
+      globals.register_fn("exp", math::exp);
+      globals.register_fn("log", math::log);
+      globals.register_fn("log2", math::log2);
+      globals.register_fn("log10", math::log10);
+      globals.register_fn("sin", math::sin);
+      globals.register_fn("cos", math::cos);
+      globals.register_fn("tan", math::tan);
+      globals.register_fn("asin", math::asin);
+      globals.register_fn("acos", math::acos);
+      globals.register_fn("atan", math::atan);
+      globals.register_fn("atan2", math::atan2);
+      globals.register_fn("min", math::min);
+      globals.register_fn("max", math::max);
+      globals.register_native("clamp", 3, math::clamp);
+      globals.register_fn("is-integer", math::is_integer);
+      globals.register_fn("is-nan", math::is_nan);
+      globals.register_fn("is-finite", math::is_finite);
+      globals.register_fn("format-number", math::format_number);
+      math::register_random(globals);
+    }
+    Module::Json => {
+      globals.register_native("json-parse", 1, json::parse);
+      globals.register_native("json-stringify", 2, json::stringify);
+    }
+    Module::Collections => {
+      globals.register_fn("keys", collections::keys);
+      globals.register_fn("values", collections::values);
+      globals.register_native("contains", 2, collections::contains);
+      globals.register_native("push", 2, collections::push);
+      globals.register_native("insert", 3, collections::insert);
+      globals.register_fn("new-map", collections::new_map);
+    }
+    Module::Process => process::register(globals),
+    Module::Control => {
+      globals.register_native("try", 2, control::try_call);
+    }
+    Module::Env => {
+      globals.register_native("args", 0, env::args);
+      globals.register_native("env-get", 1, env::env_get);
+      globals.register_native("env-set", 2, env::env_set);
+      globals.register_native("cwd", 0, env::cwd);
+      globals.register_native("hostname", 0, env::hostname);
+      globals.register_native("exit", 1, env::exit);
+    }
+  }
+}
 
+/// Bind every native in the standard library to a global slot
+pub fn load() -> Globals {
+  let mut std = Globals::new();
+  for module in Module::ALL {
+    load_module(&mut std, *module);
+  }
   std
 }
+
+// A path passed to a native
+pub(crate) fn path_arg(cx: &Context, args: &[Value], index: usize) -> Result<PathBuf, CedarError> {
+  cx.arg::<String>(args, index).map(PathBuf::from)
+}
+
+// The error for a native that failed to do something to a file
+pub(crate) fn io_error(cx: &Context, action: &str, path: &Path, e: std::io::Error) -> CedarError {
+  cx.error(format!("Could not {} '{}': {}", action, path.display(), e))
+}
@@ -0,0 +1,250 @@
+//! Running other programs. A script builds a `Command` with `command`, sets
+//! it up with methods that can be chained, and runs it:
+//!
+//! ```text
+//! let out = command("git").arg("status").arg("--short").cwd("src").run();
+//! print out.stdout();
+//! ```
+//!
+//! Processes run against the real filesystem even when the VM has been given
+//! a different one, so every run needs the `Run` permission.
+use crate::{
+  globals::Globals,
+  native::Context,
+  permissions::Permission,
+  userdata::{UserData, UserRef, UserType},
+  value::Value,
+  CedarError,
+};
+use std::{
+  io::{Read, Write},
+  path::PathBuf,
+  process::{self, Stdio},
+  rc::Rc,
+  sync::mpsc,
+  thread,
+};
+
+/// A program to run along with how to run it
+pub struct Command {
+  program: String,
+  args: Vec<String>,
+  cwd: Option<PathBuf>,
+  env: Vec<(String, String)>,
+  stdin: Option<String>,
+  output: OutputMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputMode {
+  /// Keep stdout and stderr for the script to look at
+  Capture,
+  /// Write stdout and stderr to the VM's sinks as they arrive as well as
+  /// keeping them
+  Stream,
+  /// Give the process the stdio of the host's process
+  Inherit,
+}
+
+/// What a process left behind once it exited
+pub struct Output {
+  status: Option<i32>,
+  stdout: String,
+  stderr: String,
+}
+
+/// Bind `command` along with the methods of the values it makes
+pub fn register(globals: &mut Globals) {
+  let output_type = Rc::new(
+    UserType::new::<Output>("Output")
+      .method("status", |out: UserRef<Output>| out.borrow().status)
+      .method("success", |out: UserRef<Output>| {
+        out.borrow().status == Some(0)
+      })
+      .method("stdout", |out: UserRef<Output>| out.borrow().stdout.clone())
+      .method("stderr", |out: UserRef<Output>| out.borrow().stderr.clone()),
+  );
+  let run_output = output_type.clone();
+
+  let command_type = Rc::new(
+    UserType::new::<Command>("Command")
+      .native_method("arg", 2, |cx, args| {
+        let arg = cx.arg(&args, 1)?;
+        command(cx, &args)?.borrow_mut().args.push(arg);
+        Ok(args[0].clone())
+      })
+      .native_method("args", 2, |cx, args| {
+        let list: Vec<String> = cx.arg(&args, 1)?;
+        command(cx, &args)?.borrow_mut().args.extend(list);
+        Ok(args[0].clone())
+      })
+      .native_method("cwd", 2, |cx, args| {
+        let cwd: String = cx.arg(&args, 1)?;
+        command(cx, &args)?.borrow_mut().cwd = Some(cwd.into());
+        Ok(args[0].clone())
+      })
+      .native_method("env", 3, |cx, args| {
+        let key = cx.arg(&args, 1)?;
+        let value = cx.arg(&args, 2)?;
+        command(cx, &args)?.borrow_mut().env.push((key, value));
+        Ok(args[0].clone())
+      })
+      .native_method("stdin", 2, |cx, args| {
+        let input = cx.arg(&args, 1)?;
+        command(cx, &args)?.borrow_mut().stdin = Some(input);
+        Ok(args[0].clone())
+      })
+      .native_method("stream", 1, |cx, args| {
+        command(cx, &args)?.borrow_mut().output = OutputMode::Stream;
+        Ok(args[0].clone())
+      })
+      .native_method("inherit", 1, |cx, args| {
+        command(cx, &args)?.borrow_mut().output = OutputMode::Inherit;
+        Ok(args[0].clone())
+      })
+      // Run and raise an error if the process didn't exit successfully
+      .native_method("run", 1, move |cx, args| {
+        let cmd = command(cx, &args)?;
+        let cmd = cmd.borrow();
+        let output = execute(cx, &cmd)?;
+        if output.status != Some(0) {
+          return Err(cx.error(failure(&cmd, &output)));
+        }
+        Ok(userdata(&run_output, output))
+      })
+      // Run and hand back the output however the process exited
+      .native_method("output", 1, move |cx, args| {
+        let cmd = command(cx, &args)?;
+        let output = execute(cx, &cmd.borrow())?;
+        Ok(userdata(&output_type, output))
+      }),
+  );
+
+  globals.register_fn("command", move |program: String| {
+    userdata(
+      &command_type,
+      Command {
+        program,
+        args: Vec::new(),
+        cwd: None,
+        env: Vec::new(),
+        stdin: None,
+        output: OutputMode::Capture,
+      },
+    )
+  });
+}
+
+// The command a method was called on
+fn command(cx: &Context, args: &[Value]) -> Result<UserRef<Command>, CedarError> {
+  cx.arg(args, 0)
+}
+
+fn userdata<T: 'static>(user_type: &Rc<UserType>, value: T) -> Value {
+  Value::UserData(Rc::new(UserData::new(user_type.clone(), value)))
+}
+
+fn failure(command: &Command, output: &Output) -> String {
+  let mut message = match output.status {
+    Some(status) => format!(
+      "Command '{}' exited with status {}",
+      command.program, status
+    ),
+    None => format!("Command '{}' was killed by a signal", command.program),
+  };
+  let stderr = output.stderr.trim();
+  if !stderr.is_empty() {
+    message.push_str(": ");
+    message.push_str(stderr);
+  }
+  message
+}
+
+enum Chunk {
+  Stdout(Vec<u8>),
+  Stderr(Vec<u8>),
+}
+
+fn execute(cx: &mut Context, command: &Command) -> Result<Output, CedarError> {
+  cx.require(Permission::Run)?;
+  let mut process = process::Command::new(&command.program);
+  process.args(&command.args);
+  if let Some(cwd) = &command.cwd {
+    process.current_dir(cwd);
+  }
+  process.envs(command.env.iter().map(|(k, v)| (k, v)));
+  let inherit = command.output == OutputMode::Inherit;
+  process.stdin(match (&command.stdin, inherit) {
+    (Some(_), _) => Stdio::piped(),
+    (None, true) => Stdio::inherit(),
+    (None, false) => Stdio::null(),
+  });
+  if inherit {
+    // Anything the script printed has to come out before the process does
+    cx.stdout().flush()?;
+    cx.stderr().flush()?;
+    process.stdout(Stdio::inherit()).stderr(Stdio::inherit());
+  } else {
+    process.stdout(Stdio::piped()).stderr(Stdio::piped());
+  }
+
+  let mut child = process
+    .spawn()
+    .map_err(|e| cx.error(format!("Could not run '{}': {}", command.program, e)))?;
+  // Every pipe is handled on its own thread so that a process that fills
+  // one of them while waiting on another can't deadlock
+  if let (Some(mut pipe), Some(input)) = (child.stdin.take(), command.stdin.clone()) {
+    thread::spawn(move || pipe.write_all(input.as_bytes()));
+  }
+  let (sender, chunks) = mpsc::channel();
+  if let Some(pipe) = child.stdout.take() {
+    forward(pipe, sender.clone(), Chunk::Stdout);
+  }
+  if let Some(pipe) = child.stderr.take() {
+    forward(pipe, sender, Chunk::Stderr);
+  } else {
+    drop(sender);
+  }
+
+  let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+  let stream = command.output == OutputMode::Stream;
+  for chunk in chunks {
+    match chunk {
+      Chunk::Stdout(bytes) => {
+        if stream {
+          cx.stdout().write_all(&bytes)?;
+          cx.stdout().flush()?;
+        }
+        stdout.extend(bytes);
+      }
+      Chunk::Stderr(bytes) => {
+        if stream {
+          cx.stderr().write_all(&bytes)?;
+          cx.stderr().flush()?;
+        }
+        stderr.extend(bytes);
+      }
+    }
+  }
+  let status = child.wait()?;
+  Ok(Output {
+    status: status.code(),
+    stdout: String::from_utf8_lossy(&stdout).into_owned(),
+    stderr: String::from_utf8_lossy(&stderr).into_owned(),
+  })
+}
+
+// Send everything read from a pipe to the thread running the script
+fn forward<R>(mut pipe: R, sender: mpsc::Sender<Chunk>, chunk: fn(Vec<u8>) -> Chunk)
+where
+  R: Read + Send + 'static,
+{
+  thread::spawn(move || {
+    let mut buffer = [0; 8192];
+    while let Ok(read @ 1..) = pipe.read(&mut buffer) {
+      if sender.send(chunk(buffer[..read].to_vec())).is_err() {
+        break;
+      }
+    }
+  });
+}
@@ -0,0 +1,167 @@
+//! Strings are indexed by character, meaning a Unicode scalar value, rather
+//! than by byte so that no operation can split a character in half.
+use crate::{
+  native::{Context, IntoValue},
+  value::Value,
+  CedarError,
+};
+use std::rc::Rc;
+
+/// The number of characters in a string, or the number of entries in a list
+/// or map
+pub fn length(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let length = match &args[0] {
+    Value::String(s) => s.chars().count(),
+    Value::List(list) => list.len(),
+    Value::Map(map) => map.len(),
+    value => return Err(cx.error(format!("Can't get the length of a {}", value.type_name()))),
+  };
+  Ok(Value::Number(length as f64))
+}
+
+/// The characters from `start` up to but not including `end`
+pub fn substring(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let s: Rc<String> = cx.arg(&args, 0)?;
+  let start: usize = cx.arg(&args, 1)?;
+  let end: usize = cx.arg(&args, 2)?;
+  let length = s.chars().count();
+  if let Some(index) = [start, end].iter().find(|&&i| i > length) {
+    return Err(cx.error(format!(
+      "Index {} is out of range for a string of length {}",
+      index, length
+    )));
+  }
+  if start > end {
+    return Err(cx.error(format!("Start index {} is after end index {}", start, end)));
+  }
+  Ok(cx.string(s.chars().skip(start).take(end - start).collect::<String>()))
+}
+
+/// Split on every occurrence of `separator`, or into characters if it's
+/// empty
+pub fn split(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let s: Rc<String> = cx.arg(&args, 0)?;
+  let separator: Rc<String> = cx.arg(&args, 1)?;
+  if separator.is_empty() {
+    return chars(cx, args);
+  }
+  cx.check_collection_length(Some(s.matches(separator.as_str()).count() + 1))?;
+  Ok(s.split(separator.as_str()).collect::<Vec<_>>().to_value())
+}
+
+pub fn join(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let strings: Vec<Rc<String>> = cx.arg(&args, 0)?;
+  let separator: Rc<String> = cx.arg(&args, 1)?;
+  let separators = separator.len().checked_mul(strings.len().saturating_sub(1));
+  let length = strings
+    .iter()
+    .try_fold(0usize, |length, s| length.checked_add(s.len()))
+    .and_then(|length| length.checked_add(separators?));
+  cx.check_string_length(length)?;
+  let strings = strings.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+  Ok(cx.string(strings.join(&separator)))
+}
+
+pub fn trim(s: Rc<String>) -> String {
+  s.trim().into()
+}
+
+pub fn trim_start(s: Rc<String>) -> String {
+  s.trim_start().into()
+}
+
+pub fn trim_end(s: Rc<String>) -> String {
+  s.trim_end().into()
+}
+
+/// Replace every occurrence of `from`
+pub fn replace(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let s: Rc<String> = cx.arg(&args, 0)?;
+  let from: Rc<String> = cx.arg(&args, 1)?;
+  let to: Rc<String> = cx.arg(&args, 2)?;
+  if to.len() > from.len() {
+    let count = s.matches(from.as_str()).count();
+    let growth = (to.len() - from.len()).checked_mul(count);
+    cx.check_string_length(growth.and_then(|growth| growth.checked_add(s.len())))?;
+  }
+  Ok(cx.string(s.replace(from.as_str(), &to)))
+}
+
+/// The index of the character where `needle` first appears, or null
+pub fn find(s: Rc<String>, needle: Rc<String>) -> Option<usize> {
+  s.find(needle.as_str())
+    .map(|byte| s[..byte].chars().count())
+}
+
+pub fn starts_with(s: Rc<String>, prefix: Rc<String>) -> bool {
+  s.starts_with(prefix.as_str())
+}
+
+pub fn ends_with(s: Rc<String>, suffix: Rc<String>) -> bool {
+  s.ends_with(suffix.as_str())
+}
+
+pub fn upper(s: Rc<String>) -> String {
+  s.to_uppercase()
+}
+
+pub fn lower(s: Rc<String>) -> String {
+  s.to_lowercase()
+}
+
+/// The string `count` times over
+pub fn repeat(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let s: Rc<String> = cx.arg(&args, 0)?;
+  let count: usize = cx.arg(&args, 1)?;
+  cx.check_string_length(s.len().checked_mul(count))?;
+  Ok(cx.string(s.repeat(count)))
+}
+
+/// Add `fill` to the start until the string is `width` characters long
+pub fn pad_start(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let (s, padding) = padding(cx, &args)?;
+  Ok(cx.string(padding + &s))
+}
+
+/// Add `fill` to the end until the string is `width` characters long
+pub fn pad_end(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let (s, padding) = padding(cx, &args)?;
+  Ok(cx.string(s.to_string() + &padding))
+}
+
+// The string being padded and enough of `fill` repeated to make it `width`
+// characters long, cutting the last repeat short if it doesn't fit
+fn padding(cx: &Context, args: &[Value]) -> Result<(Rc<String>, String), CedarError> {
+  let s: Rc<String> = cx.arg(args, 0)?;
+  let width: usize = cx.arg(args, 1)?;
+  let fill: Rc<String> = cx.arg(args, 2)?;
+  let missing = width.saturating_sub(s.chars().count());
+  let fill_chars = fill.chars().count();
+  if fill_chars > 0 {
+    let partial: usize = fill
+      .chars()
+      .take(missing % fill_chars)
+      .map(char::len_utf8)
+      .sum();
+    let length = (missing / fill_chars)
+      .checked_mul(fill.len())
+      .and_then(|n| n.checked_add(partial))
+      .and_then(|n| n.checked_add(s.len()));
+    cx.check_string_length(length)?;
+  }
+  let padding = fill.chars().cycle().take(missing).collect();
+  Ok((s, padding))
+}
+
+/// Every character as a string of its own
+pub fn chars(cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+  let s: Rc<String> = cx.arg(&args, 0)?;
+  cx.check_collection_length(Some(s.chars().count()))?;
+  Ok(s.chars().map(String::from).collect::<Vec<_>>().to_value())
+}
+
+/// The number written in a string, ignoring whitespace around it, or null
+/// if it isn't one
+pub fn parse_number(s: Rc<String>) -> Option<f64> {
+  s.trim().parse::<f64>().ok().filter(|n| n.is_finite())
+}
@@ -1,21 +1,59 @@
-use cedar::{CedarError, CompilerError, InterpreterResult, VM};
+use cedar::{
+  native::{FromValue, IntoValue},
+  permissions::Permissions,
+  value::Value,
+  CedarError, CompilerError, InterpreterResult, VM,
+};
 use rustyline::{error::ReadlineError, Editor};
-use std::{env, fs, path::PathBuf, process::exit};
+use std::{
+  env, fs,
+  io::{self, Read},
+  path::PathBuf,
+  process::exit,
+};
+
+const USAGE: &str = "Usage: cedarc [-O] [--allow-read[=<paths>]] [--allow-write[=<paths>]] \
+                     [--allow-env] [--allow-run] [script | - | -e <code>] [args...]";
+
+struct Options {
+  optimize: bool,
+  permissions: Permissions,
+  script: Option<Script>,
+  // Everything after the script, which is handed to it
+  args: Vec<String>,
+}
+
+// Where the source of the script to run comes from
+enum Script {
+  File(PathBuf),
+  // `cedarc -`, for piping a script in
+  Stdin,
+  // `cedarc -e 'code'`
+  Eval(String),
+}
+
 fn main() {
-  let mut args = env::args();
-  let res = if args.len() > 2 {
-    println!("Usage: cedar [script]");
-    exit(64);
-  } else if let Some(arg) = args.nth(1) {
-    run_file(arg.into())
-  } else {
-    repl()
+  let options = match parse_args(env::args().skip(1)) {
+    Some(options) => options,
+    None => {
+      println!("{}", USAGE);
+      exit(64);
+    }
+  };
+  let mut vm = VM::new();
+  vm.set_optimize(options.optimize);
+  vm.set_permissions(options.permissions);
+  vm.set_args(options.args);
+  let res = match options.script {
+    Some(script) => run_script(&mut vm, script),
+    None => repl(&mut vm).map(|_| 0),
   };
 
-  if let Err(e) = res {
-    match e {
+  match res {
+    Ok(code) => exit(code),
+    Err(e) => match e {
       CedarError::CompilerError(c) => match c {
-        CompilerError::Failed => exit(64),
+        CompilerError::Failed(_) => exit(64),
         _ => unreachable!(),
       },
       CedarError::InterpreterResult(i) => match i {
@@ -28,21 +66,119 @@ fn main() {
           exit(70);
         }
       },
+      CedarError::Exit(code) => exit(code),
+      // Something the script handed back to the host was wrong, such as
+      // what main returned
+      CedarError::HostError(_) => {
+        eprintln!("{}", e);
+        exit(70);
+      }
       _ => {
         eprintln!("{}", e);
         exit(64);
       }
+    },
+  }
+}
+
+// Scripts get no access to files, the environment or other processes unless
+// they're granted it with a flag. Flags that take paths allow everything when
+// given none, e.g. `--allow-read` versus `--allow-read=data,config.toml`.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<Options> {
+  let mut options = Options {
+    optimize: false,
+    permissions: Permissions::none(),
+    script: None,
+    args: Vec::new(),
+  };
+  while let Some(arg) = args.next() {
+    if options.script.is_some() {
+      options.args.push(arg);
+      continue;
     }
+    let (flag, paths) = match arg.find('=') {
+      Some(i) => (&arg[..i], Some(path_list(&arg[i + 1..])?)),
+      None => (arg.as_str(), None),
+    };
+    let permissions = options.permissions;
+    options.permissions = match (flag, paths) {
+      ("-O", None) => {
+        options.optimize = true;
+        permissions
+      }
+      ("--allow-read", None) => permissions.allow_read(),
+      ("--allow-read", Some(paths)) => permissions.allow_read_paths(paths),
+      ("--allow-write", None) => permissions.allow_write(),
+      ("--allow-write", Some(paths)) => permissions.allow_write_paths(paths),
+      ("--allow-env", None) => permissions.allow_env(),
+      ("--allow-run", None) => permissions.allow_run(),
+      ("-", None) => {
+        options.script = Some(Script::Stdin);
+        permissions
+      }
+      ("-e", None) => {
+        options.script = Some(Script::Eval(args.next()?));
+        permissions
+      }
+      (flag, _) if flag.starts_with('-') => return None,
+      _ => {
+        options.script = Some(Script::File(arg.clone().into()));
+        permissions
+      }
+    };
   }
+  Some(options)
 }
 
-fn run_file(path: PathBuf) -> Result<(), CedarError> {
-  let mut vm = VM::new();
-  run(&mut vm, fs::read_to_string(&path)?)
+// The paths given to a flag like `--allow-read=a,b`. An empty path would
+// resolve to the current directory, so none of them can be empty.
+fn path_list(list: &str) -> Option<Vec<PathBuf>> {
+  list
+    .split(',')
+    .map(|path| match path {
+      "" => None,
+      path => Some(PathBuf::from(path)),
+    })
+    .collect()
 }
 
-fn repl() -> Result<(), CedarError> {
-  let mut vm = VM::new();
+// Run a script and give back the exit code for it
+fn run_script(vm: &mut VM, script: Script) -> Result<i32, CedarError> {
+  let source = match script {
+    Script::File(path) => fs::read_to_string(&path)?,
+    // The script uses up stdin, so the stdin natives only see its end
+    Script::Stdin => {
+      let mut source = String::new();
+      io::stdin().read_to_string(&mut source)?;
+      source
+    }
+    Script::Eval(code) => code,
+  };
+  run(vm, source)?;
+  call_main(vm)
+}
+
+// A script can define `fn main(args)`, which is called once the rest of the
+// script has run. It returns the exit code, with null meaning success. Exit
+// codes only have 8 bits, so anything wider is an error rather than wrapping
+// around to some other code, possibly 0.
+fn call_main(vm: &mut VM) -> Result<i32, CedarError> {
+  let main = match vm.get_global("main") {
+    Some(Value::Function(main)) => main,
+    _ => return Ok(0),
+  };
+  let args = if main.arity == 0 {
+    Vec::new()
+  } else {
+    vec![vm.args().to_vec().to_value()]
+  };
+  let code = vm.invoke(Value::Function(main), &args)?;
+  Option::<u8>::try_from_value(code)
+    .map(|code| code.map_or(0, i32::from))
+    .map_err(|e| CedarError::HostError(e.describe("The value main returned").into()))
+}
+
+fn repl(vm: &mut VM) -> Result<(), CedarError> {
   let mut rl = Editor::<()>::new();
   loop {
     let readline = rl.readline(">> ");
@@ -51,8 +187,10 @@ fn repl() -> Result<(), CedarError> {
         "exit" | "quit" | "q" => break Ok(()),
         _ => {
           rl.add_history_entry(line.as_str());
-          if let Err(e) = run(&mut vm, line) {
-            eprintln!("{}", e);
+          match run(vm, line) {
+            Ok(()) => {}
+            Err(CedarError::Exit(code)) => exit(code),
+            Err(e) => eprintln!("{}", e),
           }
         }
       },
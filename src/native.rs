@@ -1,43 +1,358 @@
-use crate::value::Value;
+use crate::{permissions::Permission, value::Value, vfs::FileSystem, vm::VM, CedarError};
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::marker::PhantomData;
 use std::rc::Rc;
 
-pub trait NativeType: Sized {
+/// Rust types that can be handed to a script as a value
+pub trait IntoValue {
   fn to_value(self) -> Value;
-  fn from_value(v: Value) -> Option<Self>;
 }
 
+/// Rust types that can be taken out of a script's value
+pub trait FromValue: Sized {
+  fn from_value(value: Value) -> Option<Self>;
+  /// What a value has to be to convert to this type, for error messages
+  fn expected() -> Cow<'static, str>;
+  /// Convert a value, saying which part of it was the wrong type if it
+  /// can't be. Types made up of other values override this so that errors
+  /// point at the field or element that failed.
+  fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+    let found = value.type_name();
+    Self::from_value(value).ok_or_else(|| ConversionError::new(Self::expected(), found))
+  }
+}
+
+/// Why a value couldn't be converted to a Rust type
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionError {
+  // From the outermost value in to the part that failed
+  path: Vec<Segment>,
+  pub expected: Cow<'static, str>,
+  pub found: Cow<'static, str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+  Field(Cow<'static, str>),
+  Index(usize),
+}
+
+impl ConversionError {
+  pub fn new<E, F>(expected: E, found: F) -> Self
+  where
+    E: Into<Cow<'static, str>>,
+    F: Into<Cow<'static, str>>,
+  {
+    Self {
+      path: Vec::new(),
+      expected: expected.into(),
+      found: found.into(),
+    }
+  }
+
+  /// The same error as part of the field with the given name
+  pub fn field<N>(mut self, name: N) -> Self
+  where
+    N: Into<Cow<'static, str>>,
+  {
+    self.path.insert(0, Segment::Field(name.into()));
+    self
+  }
+
+  /// The same error as part of the element at the given index
+  pub fn index(mut self, index: usize) -> Self {
+    self.path.insert(0, Segment::Index(index));
+    self
+  }
+
+  /// Where the error happened, such as `servers[1].port`, or an empty string
+  /// if it was the value itself
+  pub fn path(&self) -> String {
+    let mut path = String::new();
+    for segment in &self.path {
+      match segment {
+        Segment::Field(name) => {
+          if !path.is_empty() {
+            path.push('.');
+          }
+          path.push_str(name);
+        }
+        Segment::Index(index) => path.push_str(&format!("[{}]", index)),
+      }
+    }
+    path
+  }
+
+  /// Describe the error for a value that was called `subject`
+  pub fn describe(&self, subject: &str) -> String {
+    if self.path.is_empty() {
+      format!(
+        "{} should be {} but got {}",
+        subject, self.expected, self.found
+      )
+    } else {
+      format!(
+        "{} at {} should be {} but got {}",
+        subject,
+        self.path(),
+        self.expected,
+        self.found
+      )
+    }
+  }
+}
+
+/// Types that go both ways between Rust and Cedar
+pub trait NativeType: IntoValue + FromValue {}
+impl<T: IntoValue + FromValue> NativeType for T {}
+
 pub trait NativeFunc {
-  fn call(&self, args: Vec<Value>) -> Option<Value>;
+  fn call(&self, cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError>;
+  /// How many arguments the function takes
+  fn arity(&self) -> usize;
+}
+
+/// Anything that can be turned into a native function. This is implemented
+/// for functions and closures taking up to 12 arguments that can all be
+/// converted from Cedar values. `Args` is only there so that the impls for
+/// different numbers of arguments don't overlap.
+pub trait IntoNativeFunc<Args> {
+  fn into_native_func(self) -> Box<dyn NativeFunc>;
 }
 
-macro_rules! helper {
-  ($x:ident $y:ident) => {
-    $x
-  };
+/// The handle a native function is given to the VM that called it. Through
+/// it a native can call back into Cedar functions it was passed, allocate
+/// values and raise errors that point at the line of the script that called
+/// it.
+pub struct Context<'a> {
+  vm: &'a mut VM,
+  name: &'a str,
+}
+
+impl<'a> Context<'a> {
+  pub(crate) fn new(vm: &'a mut VM, name: &'a str) -> Self {
+    Self { vm, name }
+  }
+
+  /// The name the native being called was registered under
+  pub fn name(&self) -> &str {
+    self.name
+  }
+
+  /// Call a Cedar function or another native with the given arguments.
+  /// Errors raised by the callee should be returned from the native so that
+  /// they reach the script.
+  pub fn call(&mut self, callee: &Value, args: &[Value]) -> Result<Value, CedarError> {
+    self.vm.invoke(callee.clone(), args)
+  }
+
+  /// Call a function defined as a global by name
+  pub fn call_function(&mut self, name: &str, args: &[Value]) -> Result<Value, CedarError> {
+    self.vm.call_function(name, args)
+  }
+
+  /// The value of a global variable if it's been defined
+  pub fn get_global(&self, name: &str) -> Option<Value> {
+    self.vm.get_global(name)
+  }
+
+  /// Define a global variable, or change its value if it already exists
+  pub fn set_global<T>(&mut self, name: &str, value: T) -> Result<(), CedarError>
+  where
+    T: IntoValue,
+  {
+    self.vm.set_global(name, value)
+  }
+
+  /// Where the VM's stdin natives read from
+  pub fn stdin(&mut self) -> &mut dyn std::io::BufRead {
+    self.vm.stdin()
+  }
+
+  /// Where the VM's `print` writes to
+  pub fn stdout(&mut self) -> &mut dyn std::io::Write {
+    self.vm.stdout()
+  }
+
+  pub fn stderr(&mut self) -> &mut dyn std::io::Write {
+    self.vm.stderr()
+  }
+
+  /// Allocate a string value
+  pub fn string<S>(&mut self, s: S) -> Value
+  where
+    S: Into<String>,
+  {
+    Value::from(s.into())
+  }
+
+  /// A string value shared with every other use of the same string, which
+  /// is cheaper for strings that are handed out over and over like keys
+  pub fn intern(&mut self, s: &str) -> Value {
+    Value::String(self.vm.intern(s))
+  }
+
+  /// Wrap a Rust object of a type registered with `VM::register_type` so it
+  /// can be handed to the script
+  pub fn userdata<T: std::any::Any>(&self, value: T) -> Result<Value, CedarError> {
+    self.vm.userdata(value)
+  }
+
+  /// Convert one of the arguments to a Rust type, raising the same error a
+  /// typed native would if it's the wrong type
+  pub fn arg<T>(&self, args: &[Value], index: usize) -> Result<T, CedarError>
+  where
+    T: FromValue,
+  {
+    self.convert(args.get(index).cloned(), index)
+  }
+
+  /// The arguments given to the script
+  pub fn args(&self) -> &[String] {
+    self.vm.args()
+  }
+
+  /// The filesystem of the VM, which every native working with files should
+  /// use rather than `std::fs`
+  pub fn fs(&self) -> &dyn FileSystem {
+    self.vm.fs()
+  }
+
+  /// Check that the VM's permissions allow something outside of the VM,
+  /// raising a permission denied error if they don't. Every native that
+  /// touches files, the environment or other processes goes through this.
+  pub fn require(&self, permission: Permission) -> Result<(), CedarError> {
+    if self.vm.permissions().allows(permission, self.vm.fs()) {
+      Ok(())
+    } else {
+      Err(self.error(format!(
+        "Permission denied: '{}' needs {}",
+        self.name, permission
+      )))
+    }
+  }
+
+  /// The most bytes a string the native makes can hold
+  pub fn max_string_len(&self) -> usize {
+    self.vm.max_string_len()
+  }
+
+  /// Check that a string of `bytes` bytes the native is about to make fits
+  /// in the VM's limits, where None means working out its length overflowed
+  pub fn check_string_length(&self, bytes: Option<usize>) -> Result<(), CedarError> {
+    self.vm.check_string_length(bytes)
+  }
+
+  /// Check that a list or map of `length` entries the native is about to
+  /// make fits in the VM's limits
+  pub fn check_collection_length(&self, length: Option<usize>) -> Result<(), CedarError> {
+    self.vm.check_collection_length(length)
+  }
+
+  /// An error at the line of the script that called the native, to be
+  /// returned from it
+  pub fn error<M>(&self, message: M) -> CedarError
+  where
+    M: Into<Cow<'static, str>>,
+  {
+    self.vm.error(message)
+  }
+
+  fn convert<T>(&self, value: Option<Value>, index: usize) -> Result<T, CedarError>
+  where
+    T: FromValue,
+  {
+    let value = value.ok_or_else(|| ConversionError::new(T::expected(), "nothing"));
+    value.and_then(T::try_from_value).map_err(|e| {
+      self.error(e.describe(&format!(
+        "Argument {} of native function '{}'",
+        index + 1,
+        self.name
+      )))
+    })
+  }
+}
+
+/// Convert the field of a map with the given name, treating a missing field
+/// as null. Used by `#[derive(CedarValue)]`.
+#[doc(hidden)]
+pub fn field<T>(map: &BTreeMap<String, Value>, name: &'static str) -> Result<T, ConversionError>
+where
+  T: FromValue,
+{
+  let value = map.get(name).cloned().unwrap_or(Value::Null);
+  T::try_from_value(value).map_err(|e| e.field(name))
+}
+
+// Holds onto a Rust function along with the signature it was registered with
+struct Wrapper<F, Args> {
+  inner: F,
+  args: PhantomData<fn() -> Args>,
 }
 
 macro_rules! gen_impls {
     ($($($T:ident),* -> $R:ident;)*) => {
         $(
-            impl<$($T,)* $R> NativeFunc for fn($($T),*) -> $R
+            impl<Func, $($T,)* $R> IntoNativeFunc<($($T,)*)> for Func
             where
-                $($T: NativeType,)*
-                $R: NativeType,
+                Func: Fn($($T),*) -> $R + 'static,
+                $($T: FromValue + 'static,)*
+                $R: IntoValue + 'static,
             {
-                fn call(&self, args: Vec<Value>) -> Option<Value> {
+                fn into_native_func(self) -> Box<dyn NativeFunc> {
+                    Box::new(Wrapper::<Func, ($($T,)*)> {
+                        inner: self,
+                        args: PhantomData,
+                    })
+                }
+            }
+
+            impl<Func, $($T,)* $R> NativeFunc for Wrapper<Func, ($($T,)*)>
+            where
+                Func: Fn($($T),*) -> $R,
+                $($T: FromValue,)*
+                $R: IntoValue,
+            {
+                #[allow(unused_mut, unused_variables, unused_assignments, non_snake_case)]
+                fn call(&self, cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
                     let mut args = args.into_iter();
-                    Some(NativeType::to_value(
-                        self($(NativeType::from_value(helper!(args $T).next()?)?),*)
-                    ))
+                    let mut index = 0;
+                    $(
+                        let $T: $T = cx.convert(args.next(), index)?;
+                        index += 1;
+                    )*
+                    Ok((self.inner)($($T),*).to_value())
+                }
+                fn arity(&self) -> usize {
+                    <[&str]>::len(&[$(stringify!($T)),*])
                 }
             }
         )*
     }
 }
 
+// A native that works with the raw arguments and the VM's context
+struct WithContext<F> {
+  inner: F,
+  arity: usize,
+}
+
+impl<F> NativeFunc for WithContext<F>
+where
+  F: Fn(&mut Context, Vec<Value>) -> Result<Value, CedarError>,
+{
+  fn call(&self, cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+    (self.inner)(cx, args)
+  }
+  fn arity(&self) -> usize {
+    self.arity
+  }
+}
+
 gen_impls! {
+    -> R;
     A -> R;
     A, B -> R;
     A, B, C -> R;
@@ -45,40 +360,195 @@ gen_impls! {
     A, B, C, D, E -> R;
     A, B, C, D, E, F -> R;
     A, B, C, D, E, F, G -> R;
-    // etc.
+    A, B, C, D, E, F, G, H -> R;
+    A, B, C, D, E, F, G, H, I -> R;
+    A, B, C, D, E, F, G, H, I, J -> R;
+    A, B, C, D, E, F, G, H, I, J, K -> R;
+    A, B, C, D, E, F, G, H, I, J, K, L -> R;
 }
 
-impl NativeType for f64 {
+impl IntoValue for f64 {
   fn to_value(self) -> Value {
     Value::Number(self)
   }
+}
+impl FromValue for f64 {
   fn from_value(value: Value) -> Option<Self> {
     value.into_num()
   }
+  fn expected() -> Cow<'static, str> {
+    "number".into()
+  }
 }
 
-impl NativeType for bool {
+impl IntoValue for f32 {
+  fn to_value(self) -> Value {
+    Value::Number(self.into())
+  }
+}
+// Infinity and NaN carry over, but a finite number too big for an f32 would
+// silently turn into infinity
+impl FromValue for f32 {
+  fn from_value(value: Value) -> Option<Self> {
+    let n = value.into_num()?;
+    let f = n as f32;
+    if n.is_finite() && f.is_infinite() {
+      None
+    } else {
+      Some(f)
+    }
+  }
+  fn expected() -> Cow<'static, str> {
+    format!("number from {:e} to {:e}", f32::MIN, f32::MAX).into()
+  }
+}
+
+// Numbers are all floats in Cedar so integers only convert from numbers with
+// nothing after the decimal point that fit in the integer type
+macro_rules! integer_impls {
+    ($($T:ty)*) => {
+        $(
+            impl IntoValue for $T {
+                fn to_value(self) -> Value {
+                    Value::Number(self as f64)
+                }
+            }
+            impl FromValue for $T {
+                fn from_value(value: Value) -> Option<Self> {
+                    let n = value.into_num()?;
+                    // MAX rounds up to the next power of two as a float for
+                    // the wider types, which is the first number out of range
+                    // for them, so compare before casting as casts saturate
+                    let (min, max) = (<$T>::MIN as f64, <$T>::MAX as f64);
+                    if n.fract() != 0.0 || n < min || n >= max + 1.0 {
+                        return None;
+                    }
+                    Some(n as $T)
+                }
+                fn expected() -> Cow<'static, str> {
+                    format!("integer from {} to {}", <$T>::MIN, <$T>::MAX).into()
+                }
+            }
+        )*
+    }
+}
+
+integer_impls! { i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize }
+
+impl IntoValue for bool {
   fn to_value(self) -> Value {
     Value::Bool(self)
   }
+}
+impl FromValue for bool {
   fn from_value(value: Value) -> Option<Self> {
     value.into_bool()
   }
+  fn expected() -> Cow<'static, str> {
+    "bool".into()
+  }
+}
+
+impl IntoValue for String {
+  fn to_value(self) -> Value {
+    Value::from(self)
+  }
+}
+impl FromValue for String {
+  fn from_value(value: Value) -> Option<Self> {
+    value
+      .into_string()
+      .map(|s| Rc::try_unwrap(s).unwrap_or_else(|s| (*s).clone()))
+  }
+  fn expected() -> Cow<'static, str> {
+    "string".into()
+  }
 }
 
-impl NativeType for Cow<'static, str> {
+// Borrowed strings can only go into a script since there's nothing for them
+// to borrow from on the way out
+impl IntoValue for &str {
+  fn to_value(self) -> Value {
+    Value::from(self)
+  }
+}
+
+impl IntoValue for Rc<String> {
   fn to_value(self) -> Value {
     Value::String(self)
   }
+}
+impl FromValue for Rc<String> {
   fn from_value(value: Value) -> Option<Self> {
     value.into_string()
   }
+  fn expected() -> Cow<'static, str> {
+    "string".into()
+  }
+}
+
+impl IntoValue for Rc<Vec<Value>> {
+  fn to_value(self) -> Value {
+    Value::List(self)
+  }
+}
+impl FromValue for Rc<Vec<Value>> {
+  fn from_value(value: Value) -> Option<Self> {
+    value.into_list()
+  }
+  fn expected() -> Cow<'static, str> {
+    "list".into()
+  }
 }
 
-impl NativeType for () {
+impl IntoValue for Rc<BTreeMap<String, Value>> {
+  fn to_value(self) -> Value {
+    Value::Map(self)
+  }
+}
+impl FromValue for Rc<BTreeMap<String, Value>> {
+  fn from_value(value: Value) -> Option<Self> {
+    value.into_map()
+  }
+  fn expected() -> Cow<'static, str> {
+    "map".into()
+  }
+}
+
+impl IntoValue for Cow<'static, str> {
+  fn to_value(self) -> Value {
+    Value::from(self.into_owned())
+  }
+}
+impl FromValue for Cow<'static, str> {
+  fn from_value(value: Value) -> Option<Self> {
+    String::from_value(value).map(Cow::Owned)
+  }
+  fn expected() -> Cow<'static, str> {
+    "string".into()
+  }
+}
+
+impl IntoValue for Value {
+  fn to_value(self) -> Value {
+    self
+  }
+}
+impl FromValue for Value {
+  fn from_value(value: Value) -> Option<Self> {
+    Some(value)
+  }
+  fn expected() -> Cow<'static, str> {
+    "any value".into()
+  }
+}
+
+impl IntoValue for () {
   fn to_value(self) -> Value {
     Value::Null
   }
+}
+impl FromValue for () {
   fn from_value(value: Value) -> Option<Self> {
     if let Value::Null = value {
       Some(())
@@ -86,32 +556,245 @@ impl NativeType for () {
       None
     }
   }
+  fn expected() -> Cow<'static, str> {
+    "null".into()
+  }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+  fn to_value(self) -> Value {
+    self.map_or(Value::Null, T::to_value)
+  }
+}
+impl<T: FromValue> FromValue for Option<T> {
+  fn from_value(value: Value) -> Option<Self> {
+    match value {
+      Value::Null => Some(None),
+      value => T::from_value(value).map(Some),
+    }
+  }
+  fn expected() -> Cow<'static, str> {
+    format!("{} or null", T::expected()).into()
+  }
+  fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+    let found = value.type_name();
+    match value {
+      Value::Null => Ok(None),
+      value => T::try_from_value(value).map(Some).map_err(|e| {
+        if e.path.is_empty() {
+          ConversionError::new(Self::expected(), found)
+        } else {
+          e
+        }
+      }),
+    }
+  }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+  fn to_value(self) -> Value {
+    Value::from(self.into_iter().map(T::to_value).collect::<Vec<_>>())
+  }
+}
+impl<T: FromValue> FromValue for Vec<T> {
+  fn from_value(value: Value) -> Option<Self> {
+    let list = value.into_list()?;
+    list.iter().cloned().map(T::from_value).collect()
+  }
+  fn expected() -> Cow<'static, str> {
+    format!("list of {}", T::expected()).into()
+  }
+  fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+    let found = value.type_name();
+    let list = value
+      .into_list()
+      .ok_or_else(|| ConversionError::new(Self::expected(), found))?;
+    list
+      .iter()
+      .enumerate()
+      .map(|(i, v)| T::try_from_value(v.clone()).map_err(|e| e.index(i)))
+      .collect()
+  }
+}
+
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+  fn to_value(self) -> Value {
+    Value::from(
+      self
+        .into_iter()
+        .map(|(k, v)| (k, v.to_value()))
+        .collect::<BTreeMap<_, _>>(),
+    )
+  }
+}
+impl<T: FromValue> FromValue for HashMap<String, T> {
+  fn from_value(value: Value) -> Option<Self> {
+    let map = value.into_map()?;
+    map
+      .iter()
+      .map(|(k, v)| Some((k.clone(), T::from_value(v.clone())?)))
+      .collect()
+  }
+  fn expected() -> Cow<'static, str> {
+    format!("map of {}", T::expected()).into()
+  }
+  fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+    let found = value.type_name();
+    let map = value
+      .into_map()
+      .ok_or_else(|| ConversionError::new(Self::expected(), found))?;
+    map
+      .iter()
+      .map(|(k, v)| {
+        let v = T::try_from_value(v.clone()).map_err(|e| e.field(k.clone()))?;
+        Ok((k.clone(), v))
+      })
+      .collect()
+  }
+}
+
+impl<T: IntoValue> IntoValue for BTreeMap<String, T> {
+  fn to_value(self) -> Value {
+    Value::from(
+      self
+        .into_iter()
+        .map(|(k, v)| (k, v.to_value()))
+        .collect::<BTreeMap<_, _>>(),
+    )
+  }
+}
+impl<T: FromValue> FromValue for BTreeMap<String, T> {
+  fn from_value(value: Value) -> Option<Self> {
+    let map = value.into_map()?;
+    map
+      .iter()
+      .map(|(k, v)| Some((k.clone(), T::from_value(v.clone())?)))
+      .collect()
+  }
+  fn expected() -> Cow<'static, str> {
+    format!("map of {}", T::expected()).into()
+  }
+  fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+    let found = value.type_name();
+    let map = value
+      .into_map()
+      .ok_or_else(|| ConversionError::new(Self::expected(), found))?;
+    map
+      .iter()
+      .map(|(k, v)| {
+        let v = T::try_from_value(v.clone()).map_err(|e| e.field(k.clone()))?;
+        Ok((k.clone(), v))
+      })
+      .collect()
+  }
+}
+
+// Tuples are lists with exactly as many elements as the tuple
+macro_rules! tuple_impls {
+    ($($($T:ident),*;)*) => {
+        $(
+            impl<$($T: IntoValue),*> IntoValue for ($($T,)*) {
+                #[allow(non_snake_case)]
+                fn to_value(self) -> Value {
+                    let ($($T,)*) = self;
+                    Value::from(vec![$($T.to_value()),*])
+                }
+            }
+            impl<$($T: FromValue),*> FromValue for ($($T,)*) {
+                #[allow(non_snake_case)]
+                fn from_value(value: Value) -> Option<Self> {
+                    let list = value.into_list()?;
+                    match list.as_slice() {
+                        [$($T),*] => Some(($($T::from_value($T.clone())?,)*)),
+                        _ => None,
+                    }
+                }
+                fn expected() -> Cow<'static, str> {
+                    let types: &[Cow<'static, str>] = &[$($T::expected()),*];
+                    format!("list of [{}]", types.join(", ")).into()
+                }
+                #[allow(non_snake_case, unused_assignments)]
+                fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+                    let found = value.type_name();
+                    let list = value.into_list();
+                    match list.as_ref().map(|l| l.as_slice()) {
+                        Some([$($T),*]) => {
+                            let mut index = 0;
+                            $(
+                                let $T = $T::try_from_value($T.clone()).map_err(|e| e.index(index))?;
+                                index += 1;
+                            )*
+                            Ok(($($T,)*))
+                        }
+                        _ => Err(ConversionError::new(Self::expected(), found)),
+                    }
+                }
+            }
+        )*
+    }
+}
+
+tuple_impls! {
+    A;
+    A, B;
+    A, B, C;
+    A, B, C, D;
+    A, B, C, D, E;
+    A, B, C, D, E, F;
+    A, B, C, D, E, F, G;
+    A, B, C, D, E, F, G, H;
 }
 
-#[derive(Clone)]
 pub struct NativeFuncHolder {
-  pub inner: Rc<dyn NativeFunc>,
+  pub name: Cow<'static, str>,
+  pub arity: usize,
+  pub inner: Box<dyn NativeFunc>,
 }
 
 impl NativeFuncHolder {
-  pub fn call(&self, args: Vec<Value>) -> Option<Value> {
-    self.inner.call(args)
+  pub fn new<N, F, Args>(name: N, inner: F) -> Rc<Self>
+  where
+    N: Into<Cow<'static, str>>,
+    F: IntoNativeFunc<Args>,
+  {
+    let inner = inner.into_native_func();
+    Rc::new(Self {
+      name: name.into(),
+      arity: inner.arity(),
+      inner,
+    })
+  }
+  /// A native that takes `arity` arguments as they are and is given the
+  /// context of the VM calling it
+  pub fn with_context<N, F>(name: N, arity: usize, inner: F) -> Rc<Self>
+  where
+    N: Into<Cow<'static, str>>,
+    F: Fn(&mut Context, Vec<Value>) -> Result<Value, CedarError> + 'static,
+  {
+    Rc::new(Self {
+      name: name.into(),
+      arity,
+      inner: Box::new(WithContext { inner, arity }),
+    })
+  }
+  pub fn call(&self, cx: &mut Context, args: Vec<Value>) -> Result<Value, CedarError> {
+    self.inner.call(cx, args)
   }
 }
 
 impl PartialEq for NativeFuncHolder {
   fn eq(&self, other: &Self) -> bool {
-    Rc::ptr_eq(&self.inner, &other.inner)
+    std::ptr::eq(self, other)
   }
 }
 
 impl fmt::Debug for NativeFuncHolder {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "NativeFuncHolder")
+    write!(f, "NativeFuncHolder({})", self.name)
   }
 }
 impl fmt::Display for NativeFuncHolder {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "NativeFuncHolder")
+    write!(f, "<native {}>", self.name)
   }
 }
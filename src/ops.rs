@@ -0,0 +1,210 @@
+// The semantics of Cedar's operators. These are shared by the VM when it
+// executes an instruction and by the compiler when it folds an expression on
+// literals, so a folded expression always produces exactly the value (or the
+// error) that running it would have.
+use crate::{chunk::OpCode, value::Value};
+use std::{borrow::Cow, f64, rc::Rc};
+
+pub type OpResult = Result<Value, Cow<'static, str>>;
+
+/// The most bytes a string can hold. Strings are capped well below what
+/// would exhaust the host's memory, so that a script can't take the host
+/// down by asking for a huge one.
+pub const MAX_STRING_LENGTH: usize = 1 << 28;
+
+/// Check that a string of `bytes` bytes is no longer than `max`, where None
+/// means working out its length overflowed. Everything that builds a string
+/// from others goes through this.
+pub fn check_length(bytes: Option<usize>, max: usize) -> Result<(), Cow<'static, str>> {
+  match bytes {
+    Some(bytes) if bytes <= max => Ok(()),
+    _ => Err(format!("Can't make a string longer than {} bytes", max).into()),
+  }
+}
+
+pub fn negate(a: Value) -> OpResult {
+  let n = a.into_num().ok_or("Operand must be a number")?;
+  Ok(Value::Number(-n))
+}
+
+pub fn not(a: Value) -> OpResult {
+  let boolean = a.into_bool().ok_or("Operand must be a boolean")?;
+  Ok(Value::Bool(!boolean))
+}
+
+pub fn add(a: Value, b: Value) -> OpResult {
+  add_within(a, b, MAX_STRING_LENGTH)
+}
+
+/// Add two values, where a string that would end up longer than
+/// `max_length` bytes is an error
+pub fn add_within(a: Value, b: Value, max_length: usize) -> OpResult {
+  let concat = |a, b: &str| concat(a, b, max_length);
+  match (b, a) {
+    (Value::Number(b), Value::Number(a)) => Ok(Value::Number(a + b)),
+    (Value::String(b), Value::String(a)) => concat(a, &b),
+    (Value::Number(b), Value::String(a)) => concat(a, &b.to_string()),
+    (Value::Bool(b), Value::String(a)) => concat(a, &b.to_string()),
+    (Value::Null, Value::String(a)) => concat(a, "null"),
+    (_, Value::Number(_)) => Err("Second operand is not a number".into()),
+    (Value::Number(_), _) => Err("First operand is not a number".into()),
+    (_, _) => Err(
+      "Addition operator can only be used with 2 number values or a String and another value"
+        .into(),
+    ),
+  }
+}
+
+fn concat(mut a: Rc<String>, b: &str, max_length: usize) -> OpResult {
+  check_length(a.len().checked_add(b.len()), max_length)?;
+  Rc::make_mut(&mut a).push_str(b);
+  Ok(Value::String(a))
+}
+
+pub fn subtract(a: Value, b: Value) -> OpResult {
+  let (a, b) = numbers(a, b)?;
+  Ok(Value::Number(a - b))
+}
+
+pub fn multiply(a: Value, b: Value) -> OpResult {
+  let (a, b) = numbers(a, b)?;
+  Ok(Value::Number(a * b))
+}
+
+pub fn divide(a: Value, b: Value) -> OpResult {
+  let (a, b) = numbers(a, b)?;
+  Ok(Value::Number(a / b))
+}
+
+fn numbers(a: Value, b: Value) -> Result<(f64, f64), Cow<'static, str>> {
+  let b = b.into_num().ok_or("Operand must be a number")?;
+  let a = a.into_num().ok_or("Operand must be a number")?;
+  Ok((a, b))
+}
+
+pub fn equal(a: Value, b: Value) -> OpResult {
+  match (b, a) {
+    (Value::Bool(b), Value::Bool(a)) => Ok(Value::Bool(a == b)),
+    // Interned strings are the same allocation so skip comparing the contents
+    (Value::String(b), Value::String(a)) => Ok(Value::Bool(Rc::ptr_eq(&a, &b) || a == b)),
+    (Value::Number(b), Value::Number(a)) => Ok(Value::Bool((a - b).abs() < f64::EPSILON)),
+    (Value::List(b), Value::List(a)) => Ok(Value::Bool(a == b)),
+    (Value::Map(b), Value::Map(a)) => Ok(Value::Bool(a == b)),
+    (Value::UserData(b), Value::UserData(a)) => Ok(Value::Bool(a == b)),
+    (Value::Null, Value::Null) => Ok(Value::Bool(true)),
+    (_, Value::Null) => Ok(Value::Bool(false)),
+    (Value::Null, _) => Ok(Value::Bool(false)),
+    (_, _) => Err("Equality operator can only be used with 2 of the same type".into()),
+  }
+}
+
+pub fn not_equal(a: Value, b: Value) -> OpResult {
+  match (b, a) {
+    (Value::Bool(b), Value::Bool(a)) => Ok(Value::Bool(a != b)),
+    (Value::String(b), Value::String(a)) => Ok(Value::Bool(!Rc::ptr_eq(&a, &b) && a != b)),
+    (Value::Number(b), Value::Number(a)) => Ok(Value::Bool((a - b).abs() > f64::EPSILON)),
+    (Value::List(b), Value::List(a)) => Ok(Value::Bool(a != b)),
+    (Value::Map(b), Value::Map(a)) => Ok(Value::Bool(a != b)),
+    (Value::UserData(b), Value::UserData(a)) => Ok(Value::Bool(a != b)),
+    (Value::Null, Value::Null) => Ok(Value::Bool(false)),
+    (_, Value::Null) => Ok(Value::Bool(true)),
+    (Value::Null, _) => Ok(Value::Bool(true)),
+    (_, _) => Err("Not equal operator can only be used with 2 of the same type".into()),
+  }
+}
+
+pub fn greater(a: Value, b: Value) -> OpResult {
+  match (b, a) {
+    (Value::Bool(b), Value::Bool(a)) => Ok(Value::Bool(a & !b)),
+    (Value::String(b), Value::String(a)) => Ok(Value::Bool(a > b)),
+    (Value::Number(b), Value::Number(a)) => Ok(Value::Bool(a > b)),
+    (Value::Null, Value::Null) => Ok(Value::Bool(false)),
+    (_, _) => Err("Greater than operator can only be used with 2 of the same type".into()),
+  }
+}
+
+pub fn greater_or_equal(a: Value, b: Value) -> OpResult {
+  match (b, a) {
+    (Value::Bool(b), Value::Bool(a)) => Ok(Value::Bool(a >= b)),
+    (Value::String(b), Value::String(a)) => Ok(Value::Bool(a >= b)),
+    (Value::Number(b), Value::Number(a)) => Ok(Value::Bool(a >= b)),
+    (Value::Null, Value::Null) => Ok(Value::Bool(true)),
+    (_, _) => Err("Greater than or equal operator can only be used with 2 of the same type".into()),
+  }
+}
+
+pub fn less(a: Value, b: Value) -> OpResult {
+  match (b, a) {
+    (Value::Bool(b), Value::Bool(a)) => Ok(Value::Bool(!a & b)),
+    (Value::String(b), Value::String(a)) => Ok(Value::Bool(a < b)),
+    (Value::Number(b), Value::Number(a)) => Ok(Value::Bool(a < b)),
+    (Value::Null, Value::Null) => Ok(Value::Bool(false)),
+    (_, _) => Err("Less than operator can only be used with 2 of the same type".into()),
+  }
+}
+
+pub fn less_or_equal(a: Value, b: Value) -> OpResult {
+  match (b, a) {
+    (Value::Bool(b), Value::Bool(a)) => Ok(Value::Bool(a <= b)),
+    (Value::String(b), Value::String(a)) => Ok(Value::Bool(a <= b)),
+    (Value::Number(b), Value::Number(a)) => Ok(Value::Bool(a <= b)),
+    (Value::Null, Value::Null) => Ok(Value::Bool(true)),
+    (_, _) => Err("Less than or equal operator can only be used with 2 of the same type".into()),
+  }
+}
+
+/// An element of a list or character of a string by position, or an entry of
+/// a map by key. Missing keys are null so scripts can check for them.
+pub fn index(target: Value, index: Value) -> OpResult {
+  match (target, index) {
+    (Value::List(list), Value::Number(n)) => {
+      let i = position(n, list.len(), "list")?;
+      Ok(list[i].clone())
+    }
+    (Value::String(s), Value::Number(n)) => {
+      let i = position(n, s.chars().count(), "string")?;
+      Ok(Value::from(
+        s.chars().nth(i).unwrap_or_default().to_string(),
+      ))
+    }
+    (Value::Map(map), Value::String(key)) => {
+      Ok(map.get(key.as_str()).cloned().unwrap_or(Value::Null))
+    }
+    (Value::List(_), index) | (Value::String(_), index) => {
+      Err(format!("Can't index a list or string with a {}", index.type_name()).into())
+    }
+    (Value::Map(_), index) => Err(format!("Can't index a map with a {}", index.type_name()).into()),
+    (target, _) => Err(format!("Can't index a {}", target.type_name()).into()),
+  }
+}
+
+// Check a number can be used to index something of the given length
+fn position(n: f64, length: usize, kind: &str) -> Result<usize, Cow<'static, str>> {
+  if n.fract() != 0.0 {
+    return Err(format!("Index {} is not a whole number", n).into());
+  }
+  if n < 0.0 || n >= length as f64 {
+    return Err(
+      format!(
+        "Index {} is out of range for a {} of length {}",
+        n, kind, length
+      )
+      .into(),
+    );
+  }
+  Ok(n as usize)
+}
+
+/// The operator a comparison opcode performs, for instructions that carry the
+/// comparison to do as an operand
+pub fn comparison(op: OpCode) -> Option<fn(Value, Value) -> OpResult> {
+  match op {
+    OpCode::Equal => Some(equal),
+    OpCode::NotEqual => Some(not_equal),
+    OpCode::Greater => Some(greater),
+    OpCode::GreaterOrEqual => Some(greater_or_equal),
+    OpCode::Less => Some(less),
+    OpCode::LessOrEqual => Some(less_or_equal),
+    _ => None,
+  }
+}
@@ -0,0 +1,326 @@
+use crate::{
+  chunk::{Chunk, OpCode, Operand},
+  value::{Function, Value},
+};
+use std::{convert::TryFrom, rc::Rc};
+
+/// Run peephole optimizations over a compiled function and every function
+/// nested inside of its constants. Common instruction sequences are rewritten
+/// into superinstructions and jumps are threaded, all without changing what
+/// the program does. If the chunk can't be decoded it's left untouched so the
+/// verifier can report the problem.
+pub fn optimize(function: &mut Function) {
+  for constant in &mut function.chunk.constants {
+    if let Value::Function(function) = constant {
+      optimize(Rc::make_mut(function));
+    }
+  }
+  let mut instructions = match decode(&function.chunk) {
+    Some(instructions) => instructions,
+    None => return,
+  };
+  let passes: &[fn(&mut Vec<Instruction>) -> bool] = &[
+    |instructions| thread_jumps(instructions),
+    remove_redundant_jumps,
+    increment_local,
+    add_constant,
+    pop_jump_if_false,
+    compare_jump_if_false,
+  ];
+  let mut changed = true;
+  while changed {
+    changed = false;
+    for pass in passes {
+      changed |= pass(&mut instructions);
+    }
+  }
+  if let Some((code, lines)) = encode(&instructions) {
+    function.chunk.code = code;
+    function.chunk.lines = lines;
+  }
+}
+
+/// A decoded instruction where jumps point at the index of another
+/// instruction rather than a byte offset, so instructions can be added and
+/// removed without having to fix up every jump by hand
+#[derive(Debug, Clone)]
+struct Instruction {
+  op: OpCode,
+  // Every operand other than the jump offset
+  operands: Vec<u8>,
+  target: Option<usize>,
+  line: usize,
+}
+
+impl Instruction {
+  fn len(&self) -> usize {
+    1 + self.op.operand_len()
+  }
+}
+
+fn decode(chunk: &Chunk) -> Option<Vec<Instruction>> {
+  if chunk.lines.len() != chunk.code.len() {
+    return None;
+  }
+  let mut instructions = Vec::new();
+  // The instruction that starts at each offset
+  let mut starts = vec![None; chunk.code.len()];
+  let mut offset = 0;
+  while offset < chunk.code.len() {
+    let op = OpCode::try_from(chunk.code[offset]).ok()?;
+    let end = offset + 1 + op.operand_len();
+    if end > chunk.code.len() {
+      return None;
+    }
+    let operands = op
+      .operands()
+      .iter()
+      .filter(|o| **o != Operand::Jump)
+      .map(|o| o.size())
+      .sum::<usize>();
+    starts[offset] = Some(instructions.len());
+    instructions.push(Instruction {
+      op,
+      operands: chunk.code[offset + 1..offset + 1 + operands].to_vec(),
+      target: chunk.jump_target(offset),
+      line: chunk.lines[offset],
+    });
+    offset = end;
+  }
+  for instruction in &mut instructions {
+    if let Some(target) = instruction.target {
+      instruction.target = Some(starts.get(target).copied().flatten()?);
+    }
+  }
+  Some(instructions)
+}
+
+fn encode(instructions: &[Instruction]) -> Option<(Vec<u8>, Vec<usize>)> {
+  let mut offsets = Vec::with_capacity(instructions.len());
+  let mut offset = 0;
+  for instruction in instructions {
+    offsets.push(offset);
+    offset += instruction.len();
+  }
+  let mut code = Vec::with_capacity(offset);
+  let mut lines = Vec::with_capacity(offset);
+  for (i, instruction) in instructions.iter().enumerate() {
+    let mut op = instruction.op;
+    let next = offsets[i] + instruction.len();
+    let jump = match instruction.target {
+      Some(target) => {
+        let target = offsets[target];
+        if let OpCode::Jump | OpCode::Loop = op {
+          op = if target < next {
+            OpCode::Loop
+          } else {
+            OpCode::Jump
+          };
+        }
+        let jump = match op {
+          OpCode::Loop => next - target,
+          // Conditional jumps can only go forward
+          _ => target.checked_sub(next)?,
+        };
+        if jump > u16::MAX as usize {
+          return None;
+        }
+        Some(jump)
+      }
+      None => None,
+    };
+    code.push(op.into());
+    code.extend(&instruction.operands);
+    if let Some(jump) = jump {
+      code.push(((jump >> 8) & 0xff) as u8);
+      code.push((jump & 0xff) as u8);
+    }
+    lines.resize(code.len(), instruction.line);
+  }
+  Some((code, lines))
+}
+
+/// How many jumps land on each instruction
+fn jump_counts(instructions: &[Instruction]) -> Vec<usize> {
+  let mut counts = vec![0; instructions.len()];
+  for target in instructions.iter().filter_map(|i| i.target) {
+    counts[target] += 1;
+  }
+  counts
+}
+
+/// Remove every instruction not marked to be kept. Jumps to a removed
+/// instruction now go to the next one that's left.
+fn compact(instructions: &mut Vec<Instruction>, keep: &[bool]) -> bool {
+  if keep.iter().all(|k| *k) {
+    return false;
+  }
+  let mut index = Vec::with_capacity(instructions.len());
+  let mut kept = 0;
+  for k in keep {
+    index.push(kept);
+    if *k {
+      kept += 1;
+    }
+  }
+  let mut i = 0;
+  instructions.retain(|_| {
+    i += 1;
+    keep[i - 1]
+  });
+  for instruction in instructions.iter_mut() {
+    if let Some(target) = instruction.target {
+      instruction.target = Some(index[target]);
+    }
+  }
+  true
+}
+
+fn is(instructions: &[Instruction], i: usize, op: OpCode) -> bool {
+  instructions.get(i).map(|i| i.op == op).unwrap_or(false)
+}
+
+/// Jumps that land on an unconditional jump go straight to where that jump
+/// goes instead
+fn thread_jumps(instructions: &mut [Instruction]) -> bool {
+  let mut changed = false;
+  for i in 0..instructions.len() {
+    let target = match instructions[i].target {
+      Some(target) => target,
+      None => continue,
+    };
+    let mut threaded = target;
+    // Bail out on jumps that loop forever between each other
+    for _ in 0..instructions.len() {
+      match instructions[threaded].op {
+        OpCode::Jump | OpCode::Loop => threaded = instructions[threaded].target.unwrap_or(threaded),
+        _ => break,
+      }
+    }
+    let unconditional = matches!(instructions[i].op, OpCode::Jump | OpCode::Loop);
+    if threaded != target && (unconditional || threaded > i) {
+      instructions[i].target = Some(threaded);
+      changed = true;
+    }
+  }
+  changed
+}
+
+/// Jumps to the very next instruction do nothing
+fn remove_redundant_jumps(instructions: &mut Vec<Instruction>) -> bool {
+  let keep = instructions
+    .iter()
+    .enumerate()
+    .map(|(i, instruction)| {
+      let unconditional = matches!(instruction.op, OpCode::Jump | OpCode::Loop);
+      !unconditional || instruction.target != Some(i + 1)
+    })
+    .collect::<Vec<_>>();
+  compact(instructions, &keep)
+}
+
+/// `GetLocal s, Constant c, Add, SetLocal s, Pop` becomes `IncrementLocal s c`
+fn increment_local(instructions: &mut Vec<Instruction>) -> bool {
+  let counts = jump_counts(instructions);
+  let mut keep = vec![true; instructions.len()];
+  let mut i = 0;
+  while i + 4 < instructions.len() {
+    let matched = is(instructions, i, OpCode::GetLocal)
+      && is(instructions, i + 1, OpCode::Constant)
+      && is(instructions, i + 2, OpCode::Add)
+      && is(instructions, i + 3, OpCode::SetLocal)
+      && is(instructions, i + 4, OpCode::Pop)
+      && instructions[i].operands == instructions[i + 3].operands
+      && counts[i + 1..=i + 4].iter().all(|c| *c == 0);
+    if matched {
+      let constant = instructions[i + 1].operands[0];
+      instructions[i].op = OpCode::IncrementLocal;
+      instructions[i].operands.push(constant);
+      keep[i + 1..=i + 4].iter_mut().for_each(|k| *k = false);
+      i += 5;
+    } else {
+      i += 1;
+    }
+  }
+  compact(instructions, &keep)
+}
+
+/// `Constant c, Add` becomes `AddConstant c`
+fn add_constant(instructions: &mut Vec<Instruction>) -> bool {
+  let counts = jump_counts(instructions);
+  let mut keep = vec![true; instructions.len()];
+  let mut i = 0;
+  while i + 1 < instructions.len() {
+    if is(instructions, i, OpCode::Constant)
+      && is(instructions, i + 1, OpCode::Add)
+      && counts[i + 1] == 0
+    {
+      instructions[i].op = OpCode::AddConstant;
+      keep[i + 1] = false;
+      i += 2;
+    } else {
+      i += 1;
+    }
+  }
+  compact(instructions, &keep)
+}
+
+/// The compiler follows a `JumpIfFalse` with a `Pop` of the condition on both
+/// paths. When nothing else can reach the `Pop` at the target we can pop as
+/// part of the jump and drop both of them.
+fn pop_jump_if_false(instructions: &mut Vec<Instruction>) -> bool {
+  let counts = jump_counts(instructions);
+  let mut keep = vec![true; instructions.len()];
+  for i in 0..instructions.len() {
+    let target = match instructions[i].target {
+      Some(target) if instructions[i].op == OpCode::JumpIfFalse => target,
+      _ => continue,
+    };
+    let matched = is(instructions, i + 1, OpCode::Pop)
+      && counts[i + 1] == 0
+      && keep[i + 1]
+      && is(instructions, target, OpCode::Pop)
+      && counts[target] == 1
+      && keep[target]
+      && target > i + 1
+      && target + 1 < instructions.len()
+      && matches!(
+        instructions[target - 1].op,
+        OpCode::Jump | OpCode::Loop | OpCode::Return
+      );
+    if matched {
+      instructions[i].op = OpCode::PopJumpIfFalse;
+      instructions[i].target = Some(target + 1);
+      keep[i + 1] = false;
+      keep[target] = false;
+    }
+  }
+  compact(instructions, &keep)
+}
+
+/// A comparison followed by a `PopJumpIfFalse` becomes a single
+/// `CompareJumpIfFalse` that never pushes the result
+fn compare_jump_if_false(instructions: &mut Vec<Instruction>) -> bool {
+  let counts = jump_counts(instructions);
+  let mut keep = vec![true; instructions.len()];
+  let mut i = 0;
+  while i + 1 < instructions.len() {
+    if instructions[i].op.is_comparison()
+      && is(instructions, i + 1, OpCode::PopJumpIfFalse)
+      && counts[i + 1] == 0
+    {
+      let compare = instructions[i].op;
+      instructions[i] = Instruction {
+        op: OpCode::CompareJumpIfFalse,
+        operands: vec![compare.into()],
+        target: instructions[i + 1].target,
+        line: instructions[i].line,
+      };
+      keep[i + 1] = false;
+      i += 2;
+    } else {
+      i += 1;
+    }
+  }
+  compact(instructions, &keep)
+}
@@ -0,0 +1,56 @@
+use std::{
+  cell::RefCell,
+  io::{self, Write},
+  rc::Rc,
+};
+
+/// An in-memory sink that can be handed to a VM as its stdout or stderr
+/// while the host keeps a handle to read what was written.
+#[derive(Debug, Clone, Default)]
+pub struct Buffer {
+  bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Buffer {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Everything written so far
+  pub fn contents(&self) -> String {
+    String::from_utf8_lossy(&self.bytes.borrow()).into_owned()
+  }
+
+  /// Everything written so far, leaving the buffer empty
+  pub fn take(&self) -> String {
+    let bytes = self.bytes.replace(Vec::new());
+    String::from_utf8_lossy(&bytes).into_owned()
+  }
+}
+
+impl Write for Buffer {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.bytes.borrow_mut().extend_from_slice(buf);
+    Ok(buf.len())
+  }
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// The output of a VM that has had both of its sinks replaced with buffers,
+/// returned by `VM::capture_output`
+#[derive(Debug, Clone, Default)]
+pub struct Output {
+  pub stdout: Buffer,
+  pub stderr: Buffer,
+}
+
+impl Output {
+  pub fn stdout(&self) -> String {
+    self.stdout.contents()
+  }
+  pub fn stderr(&self) -> String {
+    self.stderr.contents()
+  }
+}
@@ -0,0 +1,148 @@
+use crate::vfs::FileSystem;
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+};
+
+/// Something a native wants to do outside of the VM. Natives ask for one
+/// with `Context::require` before touching the outside world, which is the
+/// one place a VM's `Permissions` are checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission<'a> {
+  Read(&'a Path),
+  Write(&'a Path),
+  Env,
+  Run,
+}
+
+impl fmt::Display for Permission<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Permission::Read(path) => write!(f, "read access to '{}'", path.display()),
+      Permission::Write(path) => write!(f, "write access to '{}'", path.display()),
+      Permission::Env => write!(f, "access to environment variables"),
+      Permission::Run => write!(f, "permission to run processes"),
+    }
+  }
+}
+
+/// Which paths reading from or writing to is allowed for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathAccess {
+  None,
+  All,
+  /// These paths and anything inside of them
+  Only(Vec<PathBuf>),
+}
+
+impl PathAccess {
+  fn allows(&self, path: &Path, fs: &dyn FileSystem) -> bool {
+    match self {
+      PathAccess::None => false,
+      PathAccess::All => true,
+      PathAccess::Only(allowed) => {
+        let path = fs.canonicalize(path);
+        allowed
+          .iter()
+          .any(|allowed| path.starts_with(fs.canonicalize(allowed)))
+      }
+    }
+  }
+
+  // Grant more paths on top of what's already allowed
+  fn extend(&mut self, paths: Vec<PathBuf>) {
+    match self {
+      PathAccess::All => {}
+      PathAccess::Only(allowed) => allowed.extend(paths),
+      PathAccess::None => *self = PathAccess::Only(paths),
+    }
+  }
+}
+
+/// What the natives of a VM are allowed to do outside of it. `VM::new`
+/// allows everything, while the `cedarc` binary only allows what it's given
+/// with its `--allow-*` flags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Permissions {
+  pub read: PathAccess,
+  pub write: PathAccess,
+  pub env: bool,
+  pub run: bool,
+}
+
+impl Default for Permissions {
+  fn default() -> Self {
+    Self::none()
+  }
+}
+
+impl Permissions {
+  /// Nothing outside of the VM is allowed
+  pub fn none() -> Self {
+    Self {
+      read: PathAccess::None,
+      write: PathAccess::None,
+      env: false,
+      run: false,
+    }
+  }
+
+  /// Everything is allowed
+  pub fn all() -> Self {
+    Self {
+      read: PathAccess::All,
+      write: PathAccess::All,
+      env: true,
+      run: true,
+    }
+  }
+
+  /// Allow reading from every path
+  pub fn allow_read(mut self) -> Self {
+    self.read = PathAccess::All;
+    self
+  }
+
+  /// Allow reading from these paths and anything inside of them
+  pub fn allow_read_paths<P: Into<PathBuf>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+    self
+      .read
+      .extend(paths.into_iter().map(Into::into).collect());
+    self
+  }
+
+  /// Allow writing to every path
+  pub fn allow_write(mut self) -> Self {
+    self.write = PathAccess::All;
+    self
+  }
+
+  /// Allow writing to these paths and anything inside of them
+  pub fn allow_write_paths<P: Into<PathBuf>>(mut self, paths: impl IntoIterator<Item = P>) -> Self {
+    self
+      .write
+      .extend(paths.into_iter().map(Into::into).collect());
+    self
+  }
+
+  pub fn allow_env(mut self) -> Self {
+    self.env = true;
+    self
+  }
+
+  pub fn allow_run(mut self) -> Self {
+    self.run = true;
+    self
+  }
+
+  /// Whether something is allowed, with paths resolved by the filesystem
+  /// the natives will use them with
+  pub fn allows(&self, permission: Permission, fs: &dyn FileSystem) -> bool {
+    match permission {
+      Permission::Read(path) => self.read.allows(path, fs),
+      Permission::Write(path) => self.write.allows(path, fs),
+      Permission::Env => self.env,
+      Permission::Run => self.run,
+    }
+  }
+}
@@ -21,14 +21,12 @@ impl Scanner {
 
   pub fn scan(&mut self) -> Result<Vec<Token>, CedarError> {
     let mut tokens = Vec::new();
-    let mut did_error = false;
+    let mut errors = Vec::new();
     while {
       match self.scan_token() {
         Ok(token) => tokens.push(token),
-        Err(e) => {
-          eprintln!("{}", e);
-          did_error = true;
-        }
+        Err(CedarError::ScannerError(e)) => errors.push(e),
+        Err(e) => return Err(e),
       }
       !self.is_at_end()
     } {}
@@ -37,10 +35,10 @@ impl Scanner {
     if tokens.is_empty() {
       tokens.push(self.make_token(TokenType::EOF));
     }
-    if !did_error {
+    if errors.is_empty() {
       Ok(tokens)
     } else {
-      Err(ScannerError::SignalFailure.into())
+      Err(ScannerError::SignalFailure(errors).into())
     }
   }
   pub fn scan_token(&mut self) -> Result<Token, CedarError> {
@@ -52,12 +50,14 @@ impl Scanner {
     if self.is_at_end() {
       return Ok(self.make_token(TokenType::EOF));
     }
-    let c = self.advance() as char;
+    let c = self.advance();
     match c {
       '(' => return Ok(self.make_token(TokenType::LeftParen)),
       ')' => return Ok(self.make_token(TokenType::RightParen)),
       '{' => return Ok(self.make_token(TokenType::LeftBrace)),
       '}' => return Ok(self.make_token(TokenType::RightBrace)),
+      '[' => return Ok(self.make_token(TokenType::LeftBracket)),
+      ']' => return Ok(self.make_token(TokenType::RightBracket)),
       ';' => return Ok(self.make_token(TokenType::Semicolon)),
       ',' => return Ok(self.make_token(TokenType::Comma)),
       '.' => return Ok(self.make_token(TokenType::Dot)),
@@ -101,13 +101,7 @@ impl Scanner {
     if self.is_at_end() {
       Ok(self.make_token(TokenType::EOF))
     } else {
-      Err(
-        ScannerError::new(
-          format!("Unexpected character {:?}", self.peek() as char),
-          self.line,
-        )
-        .into(),
-      )
+      Err(ScannerError::new(format!("Unexpected character {:?}", self.peek()), self.line).into())
     }
   }
 
@@ -281,6 +275,8 @@ pub enum TokenType {
   RightParen,
   LeftBrace,
   RightBrace,
+  LeftBracket,
+  RightBracket,
   Comma,
   Dot,
   Minus,
@@ -332,41 +328,43 @@ impl TokenType {
       Self::RightParen => 1,
       Self::LeftBrace => 2,
       Self::RightBrace => 3,
-      Self::Comma => 4,
-      Self::Dot => 5,
-      Self::Minus => 6,
-      Self::Plus => 7,
-      Self::Semicolon => 8,
-      Self::Slash => 9,
-      Self::Star => 10,
-      Self::Bang => 11,
-      Self::BangEqual => 12,
-      Self::Equal => 13,
-      Self::EqualEqual => 14,
-      Self::Greater => 15,
-      Self::GreaterEqual => 16,
-      Self::Less => 17,
-      Self::LessEqual => 18,
-      Self::Identifier => 19,
-      Self::String => 20,
-      Self::Number => 21,
-      Self::And => 22,
-      Self::Class => 23,
-      Self::Else => 24,
-      Self::False => 25,
-      Self::Fn => 26,
-      Self::For => 27,
-      Self::If => 28,
-      Self::Null => 29,
-      Self::Or => 30,
-      Self::Print => 31,
-      Self::Return => 32,
-      Self::Super => 33,
-      Self::SelfTok => 34,
-      Self::True => 35,
-      Self::Let => 36,
-      Self::While => 37,
-      Self::EOF => 38,
+      Self::LeftBracket => 4,
+      Self::RightBracket => 5,
+      Self::Comma => 6,
+      Self::Dot => 7,
+      Self::Minus => 8,
+      Self::Plus => 9,
+      Self::Semicolon => 10,
+      Self::Slash => 11,
+      Self::Star => 12,
+      Self::Bang => 13,
+      Self::BangEqual => 14,
+      Self::Equal => 15,
+      Self::EqualEqual => 16,
+      Self::Greater => 17,
+      Self::GreaterEqual => 18,
+      Self::Less => 19,
+      Self::LessEqual => 20,
+      Self::Identifier => 21,
+      Self::String => 22,
+      Self::Number => 23,
+      Self::And => 24,
+      Self::Class => 25,
+      Self::Else => 26,
+      Self::False => 27,
+      Self::Fn => 28,
+      Self::For => 29,
+      Self::If => 30,
+      Self::Null => 31,
+      Self::Or => 32,
+      Self::Print => 33,
+      Self::Return => 34,
+      Self::Super => 35,
+      Self::SelfTok => 36,
+      Self::True => 37,
+      Self::Let => 38,
+      Self::While => 39,
+      Self::EOF => 40,
     }
   }
 }
@@ -376,7 +374,8 @@ pub enum ScannerError {
     message: Cow<'static, str>,
     line: usize,
   },
-  SignalFailure,
+  /// Scanning failed with every error that was found
+  SignalFailure(Vec<ScannerError>),
 }
 
 impl ScannerError {
@@ -395,7 +394,7 @@ impl fmt::Display for ScannerError {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
       Self::Error { message, line } => write!(f, "[line {}] Error: {}", line, message),
-      Self::SignalFailure => write!(
+      Self::SignalFailure(_) => write!(
         f,
         "Error: could not compile program due to invalid input while scanning"
       ),
@@ -0,0 +1,232 @@
+//! Conversions between Cedar values and anything that implements serde's
+//! `Serialize` or `Deserialize`, enabled with the `serde` feature.
+//!
+//! Numbers are all floats in Cedar, so a number with nothing after the
+//! decimal point is handed to serde as an integer. That way a number can be
+//! deserialized into any integer type that it fits in, and serializes as `1`
+//! rather than `1.0`.
+use crate::value::Value;
+use serde::{
+  de::{
+    self, value::MapDeserializer, value::SeqDeserializer, DeserializeOwned, EnumAccess,
+    IntoDeserializer, VariantAccess, Visitor,
+  },
+  forward_to_deserialize_any,
+  ser::{self, SerializeMap, SerializeSeq},
+  Serialize, Serializer,
+};
+use std::fmt;
+
+// The largest integer a float can hold without losing precision
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// Deserialize a Rust type out of a Cedar value
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, Error> {
+  T::deserialize(Deserializer::new(value))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+  message: String,
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.message)
+  }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+  fn custom<T: fmt::Display>(msg: T) -> Self {
+    Self {
+      message: msg.to_string(),
+    }
+  }
+}
+
+// Functions and the like only make sense inside of the VM
+fn unsupported(value: &Value) -> String {
+  format!(
+    "{} can't be converted with serde because it's a {}",
+    value,
+    value.type_name()
+  )
+}
+
+fn integer(n: f64) -> Option<i64> {
+  if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER {
+    Some(n as i64)
+  } else {
+    None
+  }
+}
+
+impl Serialize for Value {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    match self {
+      Value::Number(n) => match integer(*n) {
+        Some(i) => serializer.serialize_i64(i),
+        None => serializer.serialize_f64(*n),
+      },
+      Value::Bool(b) => serializer.serialize_bool(*b),
+      Value::Byte(b) => serializer.serialize_u8(*b),
+      Value::Null => serializer.serialize_unit(),
+      Value::String(s) => serializer.serialize_str(s),
+      Value::List(list) => {
+        let mut seq = serializer.serialize_seq(Some(list.len()))?;
+        for value in list.iter() {
+          seq.serialize_element(value)?;
+        }
+        seq.end()
+      }
+      Value::Map(map) => {
+        let mut out = serializer.serialize_map(Some(map.len()))?;
+        for (key, value) in map.iter() {
+          out.serialize_entry(key, value)?;
+        }
+        out.end()
+      }
+      Value::Function(_) | Value::NativeFn(_) | Value::UserData(_) => {
+        Err(ser::Error::custom(unsupported(self)))
+      }
+    }
+  }
+}
+
+/// Hands a Cedar value to anything that implements `Deserialize`
+pub struct Deserializer {
+  value: Value,
+}
+
+impl Deserializer {
+  pub fn new(value: Value) -> Self {
+    Self { value }
+  }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+  type Deserializer = Deserializer;
+  fn into_deserializer(self) -> Deserializer {
+    Deserializer::new(self)
+  }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+  type Error = Error;
+
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.value {
+      Value::Number(n) => match integer(n) {
+        Some(i) if i >= 0 => visitor.visit_u64(i as u64),
+        Some(i) => visitor.visit_i64(i),
+        None => visitor.visit_f64(n),
+      },
+      Value::Bool(b) => visitor.visit_bool(b),
+      Value::Byte(b) => visitor.visit_u8(b),
+      Value::Null => visitor.visit_unit(),
+      Value::String(s) => visitor.visit_string((*s).clone()),
+      Value::List(list) => {
+        let mut seq = SeqDeserializer::new(list.iter().cloned());
+        let value = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+        Ok(value)
+      }
+      Value::Map(map) => {
+        let mut entries = MapDeserializer::new(map.iter().map(|(k, v)| (k.clone(), v.clone())));
+        let value = visitor.visit_map(&mut entries)?;
+        entries.end()?;
+        Ok(value)
+      }
+      value => Err(de::Error::custom(unsupported(&value))),
+    }
+  }
+
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.value {
+      Value::Null => visitor.visit_none(),
+      _ => visitor.visit_some(self),
+    }
+  }
+
+  fn deserialize_newtype_struct<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    visitor.visit_newtype_struct(self)
+  }
+
+  // Unit variants are strings and the rest are a map with the variant's name
+  // as the only key
+  fn deserialize_enum<V: Visitor<'de>>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    match self.value {
+      Value::String(s) => visitor.visit_enum((*s).clone().into_deserializer()),
+      Value::Map(map) if map.len() == 1 => {
+        let (variant, value) = map.iter().next().expect("map has one entry");
+        visitor.visit_enum(Enum {
+          variant: variant.clone(),
+          value: value.clone(),
+        })
+      }
+      value => Err(de::Error::invalid_type(
+        de::Unexpected::Other(value.type_name()),
+        &"a string or a map with one entry",
+      )),
+    }
+  }
+
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+    bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+    identifier ignored_any
+  }
+}
+
+struct Enum {
+  variant: String,
+  value: Value,
+}
+
+impl<'de> EnumAccess<'de> for Enum {
+  type Error = Error;
+  type Variant = Deserializer;
+
+  fn variant_seed<V: de::DeserializeSeed<'de>>(
+    self,
+    seed: V,
+  ) -> Result<(V::Value, Deserializer), Error> {
+    let variant = seed.deserialize(self.variant.into_deserializer())?;
+    Ok((variant, Deserializer::new(self.value)))
+  }
+}
+
+impl<'de> VariantAccess<'de> for Deserializer {
+  type Error = Error;
+
+  fn unit_variant(self) -> Result<(), Error> {
+    de::Deserialize::deserialize(self)
+  }
+
+  fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+    seed.deserialize(self)
+  }
+
+  fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+    de::Deserializer::deserialize_any(self, visitor)
+  }
+
+  fn struct_variant<V: Visitor<'de>>(
+    self,
+    _fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Error> {
+    de::Deserializer::deserialize_any(self, visitor)
+  }
+}
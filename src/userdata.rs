@@ -0,0 +1,213 @@
+use crate::{
+  native::{Context, FromValue, IntoNativeFunc, NativeFuncHolder},
+  value::Value,
+  CedarError,
+};
+use std::{
+  any::{self, Any, TypeId},
+  borrow::Cow,
+  cell::{Ref, RefCell, RefMut},
+  collections::HashMap,
+  fmt,
+  marker::PhantomData,
+  rc::Rc,
+};
+
+/// A Rust type that scripts can hold values of and call methods on. Methods
+/// are natives that take the value they were called on as their first
+/// argument, so `conn.query("...")` calls the `query` native with `conn` and
+/// the query string.
+pub struct UserType {
+  name: Cow<'static, str>,
+  type_id: TypeId,
+  methods: HashMap<Cow<'static, str>, Rc<NativeFuncHolder>>,
+}
+
+impl UserType {
+  /// A type shown to scripts under the given name with no methods yet
+  pub fn new<T: Any>(name: impl Into<Cow<'static, str>>) -> Self {
+    Self {
+      name: name.into(),
+      type_id: TypeId::of::<T>(),
+      methods: HashMap::new(),
+    }
+  }
+
+  /// Add a method from a Rust function or closure. The first argument is the
+  /// value the method was called on, usually taken as a `UserRef`.
+  pub fn method<N, F, Args>(self, name: N, f: F) -> Self
+  where
+    N: Into<Cow<'static, str>>,
+    F: IntoNativeFunc<Args>,
+  {
+    let name = name.into();
+    let native = NativeFuncHolder::new(format!("{}.{}", self.name, name), f);
+    self.add_method(name, native)
+  }
+
+  /// Add a method that is given the VM's context and its arguments as they
+  /// are. `arity` counts the value the method was called on.
+  pub fn native_method<N, F>(self, name: N, arity: usize, f: F) -> Self
+  where
+    N: Into<Cow<'static, str>>,
+    F: Fn(&mut Context, Vec<Value>) -> Result<Value, CedarError> + 'static,
+  {
+    let name = name.into();
+    let native = NativeFuncHolder::with_context(format!("{}.{}", self.name, name), arity, f);
+    self.add_method(name, native)
+  }
+
+  fn add_method(mut self, name: Cow<'static, str>, native: Rc<NativeFuncHolder>) -> Self {
+    assert!(
+      native.arity > 0,
+      "Method '{}' must take the value it's called on as its first argument",
+      native.name
+    );
+    self.methods.insert(name, native);
+    self
+  }
+
+  /// The name scripts see for this type
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// The Rust type this was registered for
+  pub fn type_id(&self) -> TypeId {
+    self.type_id
+  }
+
+  pub fn get_method(&self, name: &str) -> Option<&Rc<NativeFuncHolder>> {
+    self.methods.get(name)
+  }
+}
+
+impl fmt::Debug for UserType {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "UserType({})", self.name)
+  }
+}
+
+/// A Rust object handed to a script. It's dropped once nothing in the VM or
+/// the host refers to it anymore.
+pub struct UserData {
+  user_type: Rc<UserType>,
+  data: Rc<RefCell<dyn Any>>,
+}
+
+impl UserData {
+  /// Wrap a value of the type that `user_type` was registered for
+  pub fn new<T: Any>(user_type: Rc<UserType>, value: T) -> Self {
+    assert_eq!(
+      user_type.type_id,
+      TypeId::of::<T>(),
+      "{} was registered for a different Rust type than {}",
+      user_type.name,
+      any::type_name::<T>()
+    );
+    Self {
+      user_type,
+      data: Rc::new(RefCell::new(value)),
+    }
+  }
+
+  pub fn type_name(&self) -> &str {
+    &self.user_type.name
+  }
+
+  pub fn user_type(&self) -> &Rc<UserType> {
+    &self.user_type
+  }
+
+  pub fn data(&self) -> &Rc<RefCell<dyn Any>> {
+    &self.data
+  }
+
+  /// A typed handle to the object if it's a `T`
+  pub fn downcast<T: Any>(&self) -> Option<UserRef<T>> {
+    if self.user_type.type_id == TypeId::of::<T>() {
+      Some(UserRef {
+        data: self.data.clone(),
+        ty: PhantomData,
+      })
+    } else {
+      None
+    }
+  }
+}
+
+impl PartialEq for UserData {
+  fn eq(&self, other: &Self) -> bool {
+    Rc::ptr_eq(&self.data, &other.data)
+  }
+}
+
+impl fmt::Debug for UserData {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "UserData({})", self.user_type.name)
+  }
+}
+impl fmt::Display for UserData {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "<{}>", self.user_type.name)
+  }
+}
+
+/// A handle to userdata known to hold a `T`. Natives and methods take this as
+/// an argument to get at the Rust object. Borrowing follows the rules of
+/// `RefCell`, so a method that calls back into a script must not hold a
+/// mutable borrow while the script uses the same object.
+pub struct UserRef<T> {
+  data: Rc<RefCell<dyn Any>>,
+  ty: PhantomData<T>,
+}
+
+impl<T: Any> UserRef<T> {
+  pub fn borrow(&self) -> Ref<'_, T> {
+    Ref::map(self.data.borrow(), |data| {
+      data
+        .downcast_ref()
+        .expect("UserRef was checked to hold its type")
+    })
+  }
+  pub fn borrow_mut(&self) -> RefMut<'_, T> {
+    RefMut::map(self.data.borrow_mut(), |data| {
+      data
+        .downcast_mut()
+        .expect("UserRef was checked to hold its type")
+    })
+  }
+}
+
+impl<T: Any> FromValue for UserRef<T> {
+  fn from_value(value: Value) -> Option<Self> {
+    match value {
+      Value::UserData(data) => data.downcast(),
+      _ => None,
+    }
+  }
+  fn expected() -> Cow<'static, str> {
+    // The name the type was registered under isn't known here so use the
+    // Rust name without its module paths
+    short_type_name(any::type_name::<T>()).into()
+  }
+}
+
+// Take the module path off of every path in a type's name, including those
+// of its generic arguments, e.g. `Wrapper<Bar, Vec<u8>>` for
+// `app::Wrapper<app::Bar, alloc::vec::Vec<u8>>`
+fn short_type_name(name: &str) -> String {
+  let mut short = String::with_capacity(name.len());
+  let mut path_start = 0;
+  for (i, c) in name.char_indices() {
+    if !(c.is_alphanumeric() || c == '_' || c == ':') {
+      let path = &name[path_start..i];
+      short.push_str(path.rsplit("::").next().unwrap_or(path));
+      short.push(c);
+      path_start = i + c.len_utf8();
+    }
+  }
+  let path = &name[path_start..];
+  short.push_str(path.rsplit("::").next().unwrap_or(path));
+  short
+}
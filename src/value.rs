@@ -1,16 +1,23 @@
-use crate::{chunk::Chunk, native::NativeFuncHolder};
-use std::{borrow::Cow, fmt};
+use crate::{chunk::Chunk, native::NativeFuncHolder, userdata::UserData};
+use std::{borrow::Cow, collections::BTreeMap, fmt, rc::Rc};
 
+/// A Cedar value. Anything bigger than a word lives behind an `Rc` so that a
+/// value is only ever 16 bytes, which keeps pushing, popping and cloning
+/// values on the stack cheap.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
   Number(f64),
   Bool(bool),
   Byte(u8),
   Null,
-  String(Cow<'static, str>),
-  Heap(usize),
-  Function(Function),
-  NativeFn(NativeFuncHolder),
+  String(Rc<String>),
+  Function(Rc<Function>),
+  NativeFn(Rc<NativeFuncHolder>),
+  /// Collections are shared until they're changed, the same as strings
+  List(Rc<Vec<Value>>),
+  Map(Rc<BTreeMap<String, Value>>),
+  /// A Rust object handed to the script by the host
+  UserData(Rc<UserData>),
 }
 
 impl Value {
@@ -35,20 +42,49 @@ impl Value {
       None
     }
   }
-  pub fn into_string(self) -> Option<Cow<'static, str>> {
+  pub fn into_string(self) -> Option<Rc<String>> {
     if let Value::String(s) = self {
       Some(s)
     } else {
       None
     }
   }
-  pub fn into_function(self) -> Option<Function> {
+  /// The name of this value's type for error messages
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Value::Number(_) => "number",
+      Value::Bool(_) => "bool",
+      Value::Byte(_) => "byte",
+      Value::Null => "null",
+      Value::String(_) => "string",
+      Value::Function(_) => "function",
+      Value::NativeFn(_) => "native function",
+      Value::List(_) => "list",
+      Value::Map(_) => "map",
+      Value::UserData(_) => "userdata",
+    }
+  }
+  pub fn into_function(self) -> Option<Rc<Function>> {
     if let Value::Function(f) = self {
       Some(f)
     } else {
       None
     }
   }
+  pub fn into_list(self) -> Option<Rc<Vec<Value>>> {
+    if let Value::List(l) = self {
+      Some(l)
+    } else {
+      None
+    }
+  }
+  pub fn into_map(self) -> Option<Rc<BTreeMap<String, Value>>> {
+    if let Value::Map(m) = self {
+      Some(m)
+    } else {
+      None
+    }
+  }
 }
 
 impl fmt::Display for Value {
@@ -59,12 +95,72 @@ impl fmt::Display for Value {
       Value::Byte(b) => write!(f, "{}", b),
       Value::Null => write!(f, "null"),
       Value::String(s) => write!(f, "{}", s),
-      Value::Heap(h) => write!(f, "heap {}", h),
       Value::Function(func) => write!(f, "{}", func),
       Value::NativeFn(func) => write!(f, "{}", func),
+      Value::List(list) => {
+        write!(f, "[")?;
+        for (i, value) in list.iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          write_element(f, value)?;
+        }
+        write!(f, "]")
+      }
+      Value::Map(map) => {
+        write!(f, "{{")?;
+        for (i, (key, value)) in map.iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{:?}: ", key)?;
+          write_element(f, value)?;
+        }
+        write!(f, "}}")
+      }
+      Value::UserData(data) => write!(f, "{}", data),
     }
   }
 }
+
+// Strings inside a collection are quoted so that `["a, b"]` can be told apart
+// from `["a", "b"]`
+fn write_element(f: &mut fmt::Formatter, value: &Value) -> fmt::Result {
+  match value {
+    Value::String(s) => write!(f, "{:?}", s),
+    value => write!(f, "{}", value),
+  }
+}
+impl From<&str> for Value {
+  fn from(s: &str) -> Self {
+    Value::String(Rc::new(s.to_string()))
+  }
+}
+
+impl From<String> for Value {
+  fn from(s: String) -> Self {
+    Value::String(Rc::new(s))
+  }
+}
+
+impl From<Vec<Value>> for Value {
+  fn from(list: Vec<Value>) -> Self {
+    Value::List(Rc::new(list))
+  }
+}
+
+impl From<BTreeMap<String, Value>> for Value {
+  fn from(map: BTreeMap<String, Value>) -> Self {
+    Value::Map(Rc::new(map))
+  }
+}
+
+impl From<Function> for Value {
+  fn from(function: Function) -> Self {
+    Value::Function(Rc::new(function))
+  }
+}
+
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct Function {
   pub arity: usize,
@@ -0,0 +1,282 @@
+use crate::{
+  chunk::{Chunk, OpCode, Operand},
+  value::{Function, Value},
+};
+use std::{convert::TryFrom, fmt};
+
+/// Checks that a compiled function, and every function nested inside of its
+/// constants, is safe for the VM to execute. This means every opcode is
+/// valid, every operand is in range, jumps land on instruction boundaries and
+/// the stack depth at any instruction is the same no matter how we got there.
+pub fn verify(function: &Function) -> Result<(), VerifierError> {
+  Verifier::new(function).verify()
+}
+
+struct Verifier<'a> {
+  function: &'a Function,
+  chunk: &'a Chunk,
+  // Marks which offsets in the code are the start of an instruction
+  boundaries: Vec<bool>,
+}
+
+impl<'a> Verifier<'a> {
+  fn new(function: &'a Function) -> Self {
+    Self {
+      function,
+      chunk: &function.chunk,
+      boundaries: vec![false; function.chunk.code.len()],
+    }
+  }
+
+  fn verify(mut self) -> Result<(), VerifierError> {
+    if self.chunk.code.is_empty() {
+      return Err(self.error(0, VerifierErrorKind::EmptyChunk));
+    }
+    if self.chunk.lines.len() != self.chunk.code.len() {
+      return Err(self.error(0, VerifierErrorKind::MissingLineInfo));
+    }
+    self.check_instructions()?;
+    self.check_stack()?;
+    for constant in &self.chunk.constants {
+      if let Value::Function(function) = constant {
+        verify(function)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Walk every instruction linearly making sure it decodes properly and that
+  /// any constants it refers to exist
+  fn check_instructions(&mut self) -> Result<(), VerifierError> {
+    let mut offset = 0;
+    while offset < self.chunk.code.len() {
+      self.boundaries[offset] = true;
+      let op = self.decode(offset)?;
+      let mut position = offset + 1;
+      for operand in op.operands() {
+        let byte = self.chunk.code[position];
+        match operand {
+          Operand::Constant => {
+            let index = byte as usize;
+            if index >= self.chunk.constants.len() {
+              return Err(self.error(offset, VerifierErrorKind::ConstantOutOfRange(index)));
+            }
+            let is_string = matches!(self.chunk.constants[index], Value::String(_));
+            if op == OpCode::Invoke && !is_string {
+              return Err(self.error(offset, VerifierErrorKind::NonStringMethodName(index)));
+            }
+          }
+          Operand::OpCode => match OpCode::try_from(byte) {
+            Ok(cmp) if cmp.is_comparison() => {}
+            _ => return Err(self.error(offset, VerifierErrorKind::InvalidComparison(byte))),
+          },
+          Operand::Byte | Operand::Global | Operand::Jump => {}
+        }
+        position += operand.size();
+      }
+      offset = position;
+    }
+    Ok(())
+  }
+
+  /// Follow every path through the code tracking how deep the stack is, so
+  /// that the VM can never pop from an empty stack or read a local that does
+  /// not exist
+  fn check_stack(&self) -> Result<(), VerifierError> {
+    let len = self.chunk.code.len();
+    let mut depths: Vec<Option<usize>> = vec![None; len];
+    // Slot 0 is the function being called followed by its arguments
+    let mut work = vec![(0, self.function.arity + 1)];
+    while let Some((offset, depth)) = work.pop() {
+      match depths[offset] {
+        Some(expected) if expected == depth => continue,
+        Some(expected) => {
+          return Err(self.error(
+            offset,
+            VerifierErrorKind::InconsistentStackDepth {
+              expected,
+              found: depth,
+            },
+          ))
+        }
+        None => depths[offset] = Some(depth),
+      }
+
+      let op = self.decode(offset)?;
+      let (pops, pushes) = match op {
+        OpCode::Jump | OpCode::Loop | OpCode::IncrementLocal => (0, 0),
+        OpCode::Return | OpCode::Print | OpCode::Pop | OpCode::DefineGlobal => (1, 0),
+        OpCode::Constant
+        | OpCode::Null
+        | OpCode::True
+        | OpCode::False
+        | OpCode::GetGlobal
+        | OpCode::GetLocal => (0, 1),
+        OpCode::Negate
+        | OpCode::Not
+        | OpCode::SetGlobal
+        | OpCode::SetLocal
+        | OpCode::JumpIfFalse
+        | OpCode::AddConstant => (1, 1),
+        OpCode::PopJumpIfFalse => (1, 0),
+        OpCode::CompareJumpIfFalse => (2, 0),
+        OpCode::Add
+        | OpCode::Subtract
+        | OpCode::Multiply
+        | OpCode::Divide
+        | OpCode::Equal
+        | OpCode::NotEqual
+        | OpCode::Greater
+        | OpCode::GreaterOrEqual
+        | OpCode::Less
+        | OpCode::LessOrEqual
+        | OpCode::Index => (2, 1),
+        OpCode::BuildList => (self.operand(offset) as usize, 1),
+        OpCode::Call => (self.operand(offset) as usize + 1, 1),
+        OpCode::Invoke => (self.chunk.code[offset + 2] as usize + 1, 1),
+      };
+      if depth < pops {
+        return Err(self.error(offset, VerifierErrorKind::StackUnderflow(op)));
+      }
+      if let OpCode::GetLocal | OpCode::SetLocal | OpCode::IncrementLocal = op {
+        let slot = self.operand(offset) as usize;
+        if slot >= depth {
+          return Err(self.error(offset, VerifierErrorKind::InvalidLocal(slot)));
+        }
+      }
+
+      let next = offset + 1 + op.operand_len();
+      let depth = depth - pops + pushes;
+      match op {
+        OpCode::Return => {}
+        OpCode::Jump | OpCode::Loop => work.push((self.jump_target(offset)?, depth)),
+        OpCode::JumpIfFalse | OpCode::PopJumpIfFalse | OpCode::CompareJumpIfFalse => {
+          work.push((self.jump_target(offset)?, depth));
+          work.push((self.fallthrough(offset, next)?, depth));
+        }
+        _ => work.push((self.fallthrough(offset, next)?, depth)),
+      }
+    }
+    Ok(())
+  }
+
+  fn decode(&self, offset: usize) -> Result<OpCode, VerifierError> {
+    let byte = self.chunk.code[offset];
+    let op = OpCode::try_from(byte)
+      .map_err(|_| self.error(offset, VerifierErrorKind::InvalidOpCode(byte)))?;
+    if offset + op.operand_len() >= self.chunk.code.len() {
+      return Err(self.error(offset, VerifierErrorKind::TruncatedOperand(op)));
+    }
+    Ok(op)
+  }
+
+  // Only valid to call once decode has checked the operand is there
+  fn operand(&self, offset: usize) -> u8 {
+    self.chunk.code[offset + 1]
+  }
+
+  fn jump_target(&self, offset: usize) -> Result<usize, VerifierError> {
+    let op = self.decode(offset)?;
+    let next = offset + 1 + op.operand_len();
+    let jump = ((self.chunk.code[next - 2] as isize) << 8) | self.chunk.code[next - 1] as isize;
+    let next = next as isize;
+    let target = if op == OpCode::Loop {
+      next - jump
+    } else {
+      next + jump
+    };
+    if target >= 0
+      && self
+        .boundaries
+        .get(target as usize)
+        .copied()
+        .unwrap_or(false)
+    {
+      Ok(target as usize)
+    } else {
+      Err(self.error(offset, VerifierErrorKind::InvalidJumpTarget(target)))
+    }
+  }
+
+  fn fallthrough(&self, offset: usize, next: usize) -> Result<usize, VerifierError> {
+    if next < self.chunk.code.len() {
+      Ok(next)
+    } else {
+      Err(self.error(offset, VerifierErrorKind::FallsOffEnd))
+    }
+  }
+
+  fn error(&self, offset: usize, kind: VerifierErrorKind) -> VerifierError {
+    VerifierError {
+      function: self.function.to_string(),
+      offset,
+      kind,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifierError {
+  pub function: String,
+  pub offset: usize,
+  pub kind: VerifierErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifierErrorKind {
+  EmptyChunk,
+  MissingLineInfo,
+  InvalidOpCode(u8),
+  TruncatedOperand(OpCode),
+  ConstantOutOfRange(usize),
+  InvalidJumpTarget(isize),
+  InvalidLocal(usize),
+  InvalidComparison(u8),
+  NonStringMethodName(usize),
+  StackUnderflow(OpCode),
+  InconsistentStackDepth { expected: usize, found: usize },
+  FallsOffEnd,
+}
+
+impl fmt::Display for VerifierError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "[verifier] Error in {} at {:04}: {}",
+      self.function, self.offset, self.kind
+    )
+  }
+}
+
+impl fmt::Display for VerifierErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      VerifierErrorKind::EmptyChunk => write!(f, "Chunk contains no code"),
+      VerifierErrorKind::MissingLineInfo => {
+        write!(f, "Chunk does not have line information for every byte")
+      }
+      VerifierErrorKind::InvalidOpCode(b) => write!(f, "Invalid opcode {}", b),
+      VerifierErrorKind::TruncatedOperand(op) => write!(f, "{} is missing its operands", op),
+      VerifierErrorKind::ConstantOutOfRange(i) => write!(f, "Constant {} does not exist", i),
+      VerifierErrorKind::InvalidJumpTarget(t) => {
+        write!(f, "Jump target {} is not the start of an instruction", t)
+      }
+      VerifierErrorKind::InvalidLocal(s) => write!(f, "Local slot {} is not on the stack", s),
+      VerifierErrorKind::InvalidComparison(b) => {
+        write!(f, "Operand {} is not a comparison opcode", b)
+      }
+      VerifierErrorKind::NonStringMethodName(i) => {
+        write!(f, "Constant {} used as a method name is not a string", i)
+      }
+      VerifierErrorKind::StackUnderflow(op) => write!(f, "{} pops from an empty stack", op),
+      VerifierErrorKind::InconsistentStackDepth { expected, found } => write!(
+        f,
+        "Stack depth is {} on one path and {} on another",
+        expected, found
+      ),
+      VerifierErrorKind::FallsOffEnd => write!(f, "Execution can run past the end of the chunk"),
+    }
+  }
+}
+
+impl std::error::Error for VerifierError {}
@@ -0,0 +1,634 @@
+//! The filesystem that the natives of a VM see. By default it's the real one,
+//! but an embedder can hand scripts files from memory or an archive instead,
+//! and tests can run scripts that write files without touching the disk.
+use std::{
+  cell::RefCell,
+  collections::{BTreeMap, BTreeSet},
+  env,
+  fs::{self, OpenOptions},
+  io::{self, ErrorKind, Write},
+  path::{Component, Path, PathBuf},
+  rc::Rc,
+  time::SystemTime,
+};
+
+/// What is known about a file or directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+  pub is_dir: bool,
+  /// The size of a file in bytes, which is 0 for directories
+  pub len: u64,
+  /// When a file was last written to, if the filesystem keeps track
+  pub modified: Option<SystemTime>,
+  /// Whether the path is a link, which only `symlink_metadata` can see
+  pub is_symlink: bool,
+}
+
+/// The operations natives use to work with files. Paths are given as the
+/// script wrote them, and permissions have already been checked by the time
+/// they get here.
+pub trait FileSystem {
+  fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+  /// Create the file if it doesn't exist and replace its contents if it does
+  fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+  fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+  /// The names of everything in a directory, in no particular order
+  fn read_dir(&self, path: &Path) -> io::Result<Vec<String>>;
+  /// Create a directory along with any directories above it that don't
+  /// exist yet
+  fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+  fn remove_file(&self, path: &Path) -> io::Result<()>;
+  /// Remove a directory that has nothing in it
+  fn remove_dir(&self, path: &Path) -> io::Result<()>;
+  /// Move a file or directory, replacing a file that's already at `to`
+  fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+  /// The absolute path that permissions are checked against, with `.`, `..`
+  /// and links taken out so that none of them can step outside of an
+  /// allowed directory. Filesystems without links can rely on the default,
+  /// which takes out `.` and `..` and treats relative paths as relative to
+  /// the root.
+  fn canonicalize(&self, path: &Path) -> PathBuf {
+    normalize(path)
+  }
+
+  /// Like `metadata` but about a link itself rather than what it points to,
+  /// which filesystems without links don't need to implement
+  fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+    self.metadata(path)
+  }
+
+  fn read_to_string(&self, path: &Path) -> io::Result<String> {
+    String::from_utf8(self.read(path)?)
+      .map_err(|_| io::Error::new(ErrorKind::InvalidData, "File is not valid UTF-8"))
+  }
+
+  /// Add to the end of a file, creating it if it doesn't exist
+  fn append(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = match self.read(path) {
+      Ok(file) => file,
+      Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+      Err(e) => return Err(e),
+    };
+    file.extend_from_slice(contents);
+    self.write(path, &file)
+  }
+
+  /// Copy a file, replacing a file that's already at `to`
+  fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+    let contents = self.read(from)?;
+    self.write(to, &contents)
+  }
+
+  /// Remove a directory and everything in it
+  fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+    for name in self.read_dir(path)? {
+      let child = path.join(name);
+      // A link is removed rather than what it points to
+      if self.symlink_metadata(&child)?.is_dir {
+        self.remove_dir_all(&child)?;
+      } else {
+        self.remove_file(&child)?;
+      }
+    }
+    self.remove_dir(path)
+  }
+}
+
+/// The filesystem of the OS the VM is running on
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsFs;
+
+impl FileSystem for OsFs {
+  fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+    fs::read(path)
+  }
+  fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+    fs::write(path, contents)
+  }
+  fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+    fs::metadata(path).map(os_metadata)
+  }
+  fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+    fs::symlink_metadata(path).map(os_metadata)
+  }
+  fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+    fs::read_dir(path)?
+      .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+      .collect()
+  }
+  fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)
+  }
+  fn remove_file(&self, path: &Path) -> io::Result<()> {
+    fs::remove_file(path)
+  }
+  fn remove_dir(&self, path: &Path) -> io::Result<()> {
+    fs::remove_dir(path)
+  }
+  fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to)
+  }
+  fn append(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+    OpenOptions::new()
+      .append(true)
+      .create(true)
+      .open(path)?
+      .write_all(contents)
+  }
+  fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to).map(|_| ())
+  }
+  fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+    fs::remove_dir_all(path)
+  }
+  // Relative paths are joined onto the current directory, then links are
+  // followed as far as the path exists
+  fn canonicalize(&self, path: &Path) -> PathBuf {
+    let absolute = match env::current_dir() {
+      Ok(cwd) => cwd.join(path),
+      Err(_) => path.to_path_buf(),
+    };
+    // Canonicalize the part of the path that exists, then the rest can't hold
+    // any links so its `..`s are safe to take out by hand
+    let mut existing = absolute.as_path();
+    let mut rest = Vec::new();
+    let mut resolved = loop {
+      if let Ok(canonical) = existing.canonicalize() {
+        break canonical;
+      }
+      match (existing.parent(), existing.components().next_back()) {
+        (Some(parent), Some(component)) => {
+          rest.push(component);
+          existing = parent;
+        }
+        _ => break PathBuf::new(),
+      }
+    };
+    for component in rest.into_iter().rev() {
+      match component {
+        Component::CurDir => {}
+        Component::ParentDir => {
+          resolved.pop();
+        }
+        component => resolved.push(component),
+      }
+    }
+    resolved
+  }
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+  File(Vec<u8>, SystemTime),
+  Dir,
+}
+
+fn os_metadata(metadata: fs::Metadata) -> Metadata {
+  Metadata {
+    is_dir: metadata.is_dir(),
+    len: if metadata.is_dir() { 0 } else { metadata.len() },
+    modified: metadata.modified().ok(),
+    is_symlink: metadata.file_type().is_symlink(),
+  }
+}
+
+/// A filesystem that only exists in memory. It starts out as an empty root
+/// directory and relative paths are relative to the root. Clones share the
+/// same files, so a host can keep one to look at what a script wrote.
+#[derive(Debug, Clone)]
+pub struct MemoryFs {
+  entries: Rc<RefCell<BTreeMap<PathBuf, Entry>>>,
+}
+
+impl Default for MemoryFs {
+  fn default() -> Self {
+    let mut entries = BTreeMap::new();
+    entries.insert(PathBuf::from("/"), Entry::Dir);
+    Self {
+      entries: Rc::new(RefCell::new(entries)),
+    }
+  }
+}
+
+impl MemoryFs {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a file along with any directories above it that don't exist yet
+  pub fn with_file<P, C>(self, path: P, contents: C) -> Self
+  where
+    P: AsRef<Path>,
+    C: Into<Vec<u8>>,
+  {
+    let file = Entry::File(contents.into(), SystemTime::now());
+    self.insert(normalize(path.as_ref()), file);
+    self
+  }
+
+  /// Add a directory along with any directories above it
+  pub fn with_dir<P: AsRef<Path>>(self, path: P) -> Self {
+    self.insert(normalize(path.as_ref()), Entry::Dir);
+    self
+  }
+
+  /// The contents of a file as a string, for checking what a script wrote
+  pub fn contents<P: AsRef<Path>>(&self, path: P) -> Option<String> {
+    match self.entries.borrow().get(&normalize(path.as_ref())) {
+      Some(Entry::File(contents, _)) => Some(String::from_utf8_lossy(contents).into_owned()),
+      _ => None,
+    }
+  }
+
+  fn insert(&self, path: PathBuf, entry: Entry) {
+    let mut entries = self.entries.borrow_mut();
+    for parent in path.ancestors().skip(1) {
+      entries.insert(parent.to_path_buf(), Entry::Dir);
+    }
+    entries.insert(path, entry);
+  }
+
+  // Check that something can be put at this path
+  fn check_parent(entries: &BTreeMap<PathBuf, Entry>, path: &Path) -> io::Result<()> {
+    match path.parent().map(|parent| entries.get(parent)) {
+      Some(Some(Entry::Dir)) => Ok(()),
+      Some(Some(Entry::File(..))) => Err(not_a_directory()),
+      _ => Err(not_found()),
+    }
+  }
+}
+
+impl FileSystem for MemoryFs {
+  fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+    match self.entries.borrow().get(&normalize(path)) {
+      Some(Entry::File(contents, _)) => Ok(contents.clone()),
+      Some(Entry::Dir) => Err(is_a_directory()),
+      None => Err(not_found()),
+    }
+  }
+
+  fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = normalize(path);
+    let mut entries = self.entries.borrow_mut();
+    Self::check_parent(&entries, &path)?;
+    if let Some(Entry::Dir) = entries.get(&path) {
+      return Err(is_a_directory());
+    }
+    entries.insert(path, Entry::File(contents.to_vec(), SystemTime::now()));
+    Ok(())
+  }
+
+  fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+    match self.entries.borrow().get(&normalize(path)) {
+      Some(Entry::File(contents, modified)) => Ok(Metadata {
+        is_dir: false,
+        len: contents.len() as u64,
+        modified: Some(*modified),
+        is_symlink: false,
+      }),
+      Some(Entry::Dir) => Ok(Metadata {
+        is_dir: true,
+        len: 0,
+        modified: None,
+        is_symlink: false,
+      }),
+      None => Err(not_found()),
+    }
+  }
+
+  fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+    let path = normalize(path);
+    let entries = self.entries.borrow();
+    match entries.get(&path) {
+      Some(Entry::Dir) => Ok(
+        entries
+          .keys()
+          .filter(|child| child.parent() == Some(&path))
+          .filter_map(|child| Some(child.file_name()?.to_string_lossy().into_owned()))
+          .collect(),
+      ),
+      Some(Entry::File(..)) => Err(not_a_directory()),
+      None => Err(not_found()),
+    }
+  }
+
+  fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+    let path = normalize(path);
+    let entries = self.entries.borrow();
+    if path
+      .ancestors()
+      .any(|dir| matches!(entries.get(dir), Some(Entry::File(..))))
+    {
+      return Err(not_a_directory());
+    }
+    drop(entries);
+    self.insert(path, Entry::Dir);
+    Ok(())
+  }
+
+  fn remove_file(&self, path: &Path) -> io::Result<()> {
+    let path = normalize(path);
+    let mut entries = self.entries.borrow_mut();
+    match entries.get(&path) {
+      Some(Entry::File(..)) => {
+        entries.remove(&path);
+        Ok(())
+      }
+      Some(Entry::Dir) => Err(is_a_directory()),
+      None => Err(not_found()),
+    }
+  }
+
+  fn remove_dir(&self, path: &Path) -> io::Result<()> {
+    if !self.read_dir(path)?.is_empty() {
+      return Err(not_empty());
+    }
+    let path = normalize(path);
+    if path.parent().is_none() {
+      return Err(io::Error::other("Cannot remove the root directory"));
+    }
+    self.entries.borrow_mut().remove(&path);
+    Ok(())
+  }
+
+  fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+    let (from, to) = (normalize(from), normalize(to));
+    let mut entries = self.entries.borrow_mut();
+    let moving_dir = match entries.get(&from) {
+      Some(entry) => matches!(entry, Entry::Dir),
+      None => return Err(not_found()),
+    };
+    if from == to {
+      return Ok(());
+    }
+    if to.starts_with(&from) {
+      return Err(into_itself());
+    }
+    Self::check_parent(&entries, &to)?;
+    match entries.get(&to) {
+      Some(Entry::Dir) if !moving_dir => return Err(is_a_directory()),
+      Some(Entry::Dir) if entries.keys().any(|k| k.parent() == Some(&to)) => {
+        return Err(not_empty())
+      }
+      Some(Entry::File(..)) if moving_dir => return Err(not_a_directory()),
+      _ => {}
+    }
+    let moved = entries
+      .keys()
+      .filter(|path| path.starts_with(&from))
+      .cloned()
+      .collect::<Vec<_>>();
+    for path in moved {
+      if let Some(entry) = entries.remove(&path) {
+        let rest = path.strip_prefix(&from).expect("path starts with from");
+        entries.insert(to.join(rest), entry);
+      }
+    }
+    Ok(())
+  }
+}
+
+/// A filesystem layered over another that is never changed. Files are read
+/// from the upper layer if they've been written there and from the lower
+/// layer otherwise, and every write goes to the upper layer. Removing
+/// something from the lower layer only hides it. This lets scripts see a real
+/// directory or an archive while anything they change stays in memory.
+///
+/// Relative paths are joined onto the overlay's root, which is the current
+/// directory when it's made, so both layers only ever see absolute paths and
+/// `data/x` is the same file as `/<root>/data/x` in each of them.
+pub struct OverlayFs {
+  lower: Box<dyn FileSystem>,
+  upper: MemoryFs,
+  root: PathBuf,
+  // Paths that were removed, hiding them and everything inside them in the
+  // lower layer
+  removed: RefCell<BTreeSet<PathBuf>>,
+}
+
+impl OverlayFs {
+  pub fn new<F: FileSystem + 'static>(lower: F) -> Self {
+    Self::with_upper(lower, MemoryFs::new())
+  }
+
+  /// Use an existing in-memory filesystem as the upper layer, so the host
+  /// can keep a handle on what was written
+  pub fn with_upper<F: FileSystem + 'static>(lower: F, upper: MemoryFs) -> Self {
+    Self {
+      lower: Box::new(lower),
+      upper,
+      root: env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+      removed: RefCell::new(BTreeSet::new()),
+    }
+  }
+
+  /// Resolve relative paths against this directory rather than the current
+  /// one, such as `/` for a lower layer that's a `MemoryFs`
+  pub fn with_root<P: AsRef<Path>>(mut self, root: P) -> Self {
+    self.root = self.resolve(root.as_ref());
+    self
+  }
+
+  pub fn upper(&self) -> &MemoryFs {
+    &self.upper
+  }
+
+  /// The absolute path both layers use for a path given to the overlay
+  pub fn resolve(&self, path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in self.root.join(path).components() {
+      match component {
+        Component::CurDir => {}
+        Component::ParentDir => {
+          resolved.pop();
+        }
+        component => resolved.push(component),
+      }
+    }
+    resolved
+  }
+
+  fn hidden(&self, path: &Path) -> bool {
+    let removed = self.removed.borrow();
+    path.ancestors().any(|path| removed.contains(path))
+  }
+
+  // Run an operation on the upper layer, falling back to the lower one if
+  // the path isn't there and hasn't been removed
+  fn layered<T>(
+    &self,
+    path: &Path,
+    op: impl Fn(&dyn FileSystem, &Path) -> io::Result<T>,
+  ) -> io::Result<T> {
+    let path = self.resolve(path);
+    match op(&self.upper, &path) {
+      Err(e) if e.kind() == ErrorKind::NotFound && self.hidden(&path) => Err(e),
+      Err(e) if e.kind() == ErrorKind::NotFound => op(&*self.lower, &path),
+      result => result,
+    }
+  }
+
+  // Copy the directories above a resolved path up from the lower layer so
+  // that it can be written to in the upper one
+  fn copy_up_parents(&self, path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+      if !self.metadata(parent)?.is_dir {
+        return Err(not_a_directory());
+      }
+      if self.upper.metadata(parent).is_err() {
+        self.upper.insert(normalize(parent), Entry::Dir);
+      }
+    }
+    Ok(())
+  }
+
+  // Hide whatever the lower layer has at a resolved path
+  fn hide(&self, path: &Path) {
+    if self.lower.metadata(path).is_ok() {
+      self.removed.borrow_mut().insert(path.to_path_buf());
+    }
+  }
+}
+
+impl FileSystem for OverlayFs {
+  fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+    self.layered(path, |fs, path| fs.read(path))
+  }
+
+  fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = &self.resolve(path);
+    if let Ok(metadata) = self.metadata(path) {
+      if metadata.is_dir {
+        return Err(is_a_directory());
+      }
+    }
+    self.copy_up_parents(path)?;
+    self.upper.write(path, contents)
+  }
+
+  fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+    self.layered(path, |fs, path| fs.metadata(path))
+  }
+
+  fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+    self.layered(path, |fs, path| fs.symlink_metadata(path))
+  }
+
+  fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+    let path = &self.resolve(path);
+    if !self.metadata(path)?.is_dir {
+      return Err(not_a_directory());
+    }
+    let mut names = self.upper.read_dir(path).unwrap_or_default();
+    for name in self.lower.read_dir(path).unwrap_or_default() {
+      if !self.hidden(&path.join(&name)) && !names.contains(&name) {
+        names.push(name);
+      }
+    }
+    Ok(names)
+  }
+
+  fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+    let path = &self.resolve(path);
+    for dir in path.ancestors() {
+      if let Ok(metadata) = self.metadata(dir) {
+        if !metadata.is_dir {
+          return Err(not_a_directory());
+        }
+      }
+    }
+    self.upper.create_dir_all(path)
+  }
+
+  fn remove_file(&self, path: &Path) -> io::Result<()> {
+    let path = &self.resolve(path);
+    if self.metadata(path)?.is_dir {
+      return Err(is_a_directory());
+    }
+    if self.upper.metadata(path).is_ok() {
+      self.upper.remove_file(path)?;
+    }
+    self.hide(path);
+    Ok(())
+  }
+
+  fn remove_dir(&self, path: &Path) -> io::Result<()> {
+    let path = &self.resolve(path);
+    if !self.read_dir(path)?.is_empty() {
+      return Err(not_empty());
+    }
+    if self.upper.metadata(path).is_ok() {
+      self.upper.remove_dir(path)?;
+    }
+    self.hide(path);
+    Ok(())
+  }
+
+  // Only the lower layer can have links, so the path is resolved against the
+  // overlay's root and then left to the lower layer to follow any
+  fn canonicalize(&self, path: &Path) -> PathBuf {
+    self.lower.canonicalize(&self.resolve(path))
+  }
+
+  // Nothing in the lower layer can be moved, so it's copied up and hidden
+  fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+    let (from, to) = (&self.resolve(from), &self.resolve(to));
+    if from == to {
+      return self.metadata(from).map(|_| ());
+    }
+    if to.starts_with(from) {
+      return Err(into_itself());
+    }
+    if self.metadata(from)?.is_dir {
+      self.copy_up_parents(to)?;
+      self.create_dir_all(to)?;
+      for name in self.read_dir(from)? {
+        self.rename(&from.join(&name), &to.join(&name))?;
+      }
+      self.remove_dir(from)
+    } else {
+      self.copy(from, to)?;
+      self.remove_file(from)
+    }
+  }
+}
+
+// An absolute path with `.` and `..` taken out by hand, which is all an
+// in-memory filesystem needs since it has no links
+fn normalize(path: &Path) -> PathBuf {
+  let mut normal = PathBuf::from("/");
+  for component in path.components() {
+    match component {
+      Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+      Component::ParentDir => {
+        normal.pop();
+      }
+      Component::Normal(name) => normal.push(name),
+    }
+  }
+  normal
+}
+
+fn not_found() -> io::Error {
+  io::Error::new(ErrorKind::NotFound, "No such file or directory")
+}
+
+fn is_a_directory() -> io::Error {
+  io::Error::other("Is a directory")
+}
+
+fn not_a_directory() -> io::Error {
+  io::Error::other("Not a directory")
+}
+
+fn not_empty() -> io::Error {
+  io::Error::other("Directory not empty")
+}
+
+fn into_itself() -> io::Error {
+  io::Error::new(
+    ErrorKind::InvalidInput,
+    "Cannot move a directory inside of itself",
+  )
+}
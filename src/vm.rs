@@ -1,402 +1,770 @@
 use crate::{
+  builder::VmBuilder,
   chunk::{Chunk, OpCode},
-  compiler::compile,
+  compiler::{compile, CompilerError},
+  globals::Globals,
+  interner::Interner,
+  native::{Context, FromValue, IntoNativeFunc, IntoValue},
+  ops::{self, OpResult},
+  optimizer::optimize,
+  output::Output,
+  permissions::Permissions,
+  scanner::ScannerError,
+  userdata::{UserData, UserType},
   value::{Function, Value},
+  verifier::verify,
+  vfs::{FileSystem, OsFs},
   CedarError,
 };
 use std::{
-  borrow::{Borrow, Cow},
+  any::{self, Any, TypeId},
+  borrow::Cow,
   collections::HashMap,
-  f64, fmt,
+  convert::TryFrom,
+  fmt,
+  io::{self, BufRead, BufReader, BufWriter, Write},
+  mem,
+  rc::Rc,
 };
 
-#[derive(Default)]
 pub struct VM {
   frames: Vec<CallFrame>,
   frame_count: usize,
   stack: Vec<Value>,
-  heap: Vec<(Value, bool)>,
-  globals: HashMap<Cow<'static, str>, Value>,
+  globals: Globals,
+  strings: Interner,
+  types: HashMap<TypeId, Rc<UserType>>,
+  optimize: bool,
+  stdin: Box<dyn BufRead>,
+  stdout: Box<dyn Write>,
+  stderr: Box<dyn Write>,
+  limits: Limits,
+  permissions: Permissions,
+  fs: Box<dyn FileSystem>,
+  args: Vec<String>,
+  // Instructions left before the current call from the host is stopped
+  fuel: u64,
+}
+
+/// Bounds on what a script can use, so that code that isn't trusted can't
+/// hang or exhaust the host. Anything left as `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+  /// How deeply calls can nest
+  pub max_call_depth: Option<usize>,
+  /// How many values can be on the stack at once, counting the locals and
+  /// temporaries of every frame
+  pub max_stack: Option<usize>,
+  /// How many instructions each call from the host can execute
+  pub max_instructions: Option<u64>,
+  /// How many bytes a string can hold. No string can ever be longer than
+  /// `ops::MAX_STRING_LENGTH`, even when this is `None`.
+  pub max_string_len: Option<usize>,
+  /// How many entries a list or map can hold
+  pub max_collection_len: Option<usize>,
+}
+
+impl Default for VM {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl VM {
+  /// A VM with every module of the standard library loaded, which is what
+  /// the `cedarc` binary runs scripts with
   pub fn new() -> Self {
+    let mut vm = Self::sandboxed();
+    vm.globals = crate::libstd::load();
+    vm.permissions = Permissions::all();
+    vm
+  }
+
+  /// A VM for pure computation. It has no standard library, so scripts have
+  /// no access to files or anything else outside of the VM.
+  pub fn sandboxed() -> Self {
     Self {
       frames: Vec::new(),
       frame_count: 0,
       stack: Vec::new(),
-      heap: Vec::new(),
-      globals: crate::libstd::load(),
+      globals: Globals::new(),
+      strings: Interner::new(),
+      types: HashMap::new(),
+      optimize: false,
+      stdin: Box::new(BufReader::new(io::stdin())),
+      stdout: Box::new(BufWriter::new(io::stdout())),
+      stderr: Box::new(BufWriter::new(io::stderr())),
+      limits: Limits::default(),
+      permissions: Permissions::none(),
+      fs: Box::new(OsFs),
+      args: Vec::new(),
+      fuel: u64::MAX,
     }
   }
 
+  /// Build a VM that starts with nothing and only gets the modules, natives
+  /// and limits it's given
+  pub fn builder() -> VmBuilder {
+    VmBuilder::new()
+  }
+
+  pub fn limits(&self) -> Limits {
+    self.limits
+  }
+  pub fn set_limits(&mut self, limits: Limits) {
+    self.limits = limits;
+  }
+
+  /// What natives are allowed to do outside of the VM
+  pub fn permissions(&self) -> &Permissions {
+    &self.permissions
+  }
+  pub fn set_permissions(&mut self, permissions: Permissions) {
+    self.permissions = permissions;
+  }
+
+  /// The arguments given to the script, which `args` returns
+  pub fn args(&self) -> &[String] {
+    &self.args
+  }
+  pub fn set_args<I, S>(&mut self, args: I)
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.args = args.into_iter().map(Into::into).collect();
+  }
+
+  /// The filesystem natives read and write files through
+  pub fn fs(&self) -> &dyn FileSystem {
+    &*self.fs
+  }
+  /// Give natives a different filesystem than the OS's, such as a
+  /// `MemoryFs` so scripts can't touch the disk at all
+  pub fn set_fs<F: FileSystem + 'static>(&mut self, fs: F) {
+    self.fs = Box::new(fs);
+  }
+
+  /// The global variables of this VM, which the compiler resolves names to
+  /// slots in
+  pub fn globals(&self) -> &Globals {
+    &self.globals
+  }
+  pub fn globals_mut(&mut self) -> &mut Globals {
+    &mut self.globals
+  }
+
+  /// Run the peephole optimizer over scripts before they're executed
+  pub fn set_optimize(&mut self, optimize: bool) {
+    self.optimize = optimize;
+  }
+
+  /// Where `read-line` and the other stdin natives read from, which is the
+  /// host process's stdin by default
+  pub fn set_stdin<R: BufRead + 'static>(&mut self, stdin: R) {
+    self.stdin = Box::new(stdin);
+  }
+  pub fn stdin(&mut self) -> &mut dyn BufRead {
+    &mut *self.stdin
+  }
+
+  /// Where `print` writes to. Output is buffered by default and flushed
+  /// whenever a call from the host returns.
+  pub fn set_stdout<W: Write + 'static>(&mut self, stdout: W) {
+    self.stdout = Box::new(stdout);
+  }
+  /// Where compile errors are written to
+  pub fn set_stderr<W: Write + 'static>(&mut self, stderr: W) {
+    self.stderr = Box::new(stderr);
+  }
+  pub fn stdout(&mut self) -> &mut dyn Write {
+    &mut *self.stdout
+  }
+  pub fn stderr(&mut self) -> &mut dyn Write {
+    &mut *self.stderr
+  }
+
+  /// Send stdout and stderr to in-memory buffers so that the host can read
+  /// back what a script printed
+  ///
+  /// ```
+  /// # use cedar::VM;
+  /// let mut vm = VM::new();
+  /// let output = vm.capture_output();
+  /// vm.interpret("print 1 + 2;".into()).unwrap();
+  /// assert_eq!(output.stdout(), "3\n");
+  /// ```
+  pub fn capture_output(&mut self) -> Output {
+    let output = Output::default();
+    self.set_stdout(output.stdout.clone());
+    self.set_stderr(output.stderr.clone());
+    output
+  }
+
   pub fn interpret(&mut self, source: String) -> Result<(), CedarError> {
-    let function = compile(source)?;
+    let mut function = match compile(source, &mut self.globals) {
+      Ok(function) => function,
+      Err(e) => {
+        self.report(&e);
+        return Err(e);
+      }
+    };
+    if self.optimize {
+      optimize(&mut function);
+    }
     //function.chunk.disassemble("MAIN");
-    self.call(function.clone(), 0)?;
-    self.stack.push(Value::Function(function));
-    self.run()
+    self.execute(function)
   }
 
-  fn ip(&mut self) -> &mut usize {
-    &mut self.frames[self.frame_count - 1].ip
+  /// Run an already compiled function as a script. The function is verified
+  /// first so that malformed bytecode results in an error rather than
+  /// undefined behavior in the VM.
+  pub fn execute(&mut self, mut function: Function) -> Result<(), CedarError> {
+    self.strings.intern_constants(&mut function);
+    self.invoke(Value::from(function), &[]).map(|_| ())
   }
-  fn ip_(&self) -> usize {
-    self.frames[self.frame_count - 1].ip
+
+  /// Call a function that a script defined as a global, such as an event
+  /// handler, and get back whatever it returned
+  pub fn call_function(&mut self, name: &str, args: &[Value]) -> Result<Value, CedarError> {
+    let function = self
+      .get_global(name)
+      .ok_or_else(|| CedarError::HostError(format!("Undefined function '{}'", name).into()))?;
+    self.invoke(function, args)
   }
-  fn slots(&self) -> usize {
-    self.frames[self.frame_count - 1].slots
+
+  /// The same as `call_function` but converting the result to a Rust type
+  pub fn call_function_as<R>(&mut self, name: &str, args: &[Value]) -> Result<R, CedarError>
+  where
+    R: FromValue,
+  {
+    let value = self.call_function(name, args)?;
+    convert(name, value)
   }
 
-  fn run(&mut self) -> Result<(), CedarError> {
+  /// Call any callable value with the given arguments. A function is
+  /// verified the same as a compiled script before it runs, since the host
+  /// could have built it by hand or with the assembler.
+  pub fn invoke(&mut self, callee: Value, args: &[Value]) -> Result<Value, CedarError> {
+    if args.len() > u8::MAX as usize {
+      return Err(CedarError::HostError(
+        format!("Cannot call a function with {} arguments", args.len()).into(),
+      ));
+    }
+    if let Value::Function(function) = &callee {
+      verify(function)?;
+    }
+    let base = self.stack.len();
+    let depth = self.frames.len();
+    if depth == 0 {
+      self.fuel = self.limits.max_instructions.unwrap_or(u64::MAX);
+    }
+    let result = self.push_call(&callee, args).and_then(|_| match callee {
+      Value::Function(function) => self
+        .call(function, args.len() as u8)
+        .and_then(|_| self.run(depth)),
+      callee => self
+        .call_value(callee, args.len() as u8)
+        .and_then(|_| self.pop()),
+    });
+    if result.is_err() {
+      // Leave the VM in a usable state for the next call
+      self.stack.truncate(base);
+      self.frames.truncate(depth);
+      self.frame_count = depth;
+    }
+    if depth == 0 {
+      self.flush()?;
+    }
+    result
+  }
+
+  // Put a call from the host on the stack the same way the script would
+  fn push_call(&mut self, callee: &Value, args: &[Value]) -> Result<(), CedarError> {
+    self.push(callee.clone())?;
+    for arg in args {
+      self.push(arg.clone())?;
+    }
+    Ok(())
+  }
+
+  fn flush(&mut self) -> Result<(), CedarError> {
+    self.stdout.flush()?;
+    self.stderr.flush()?;
+    Ok(())
+  }
+
+  // Write out every error found while compiling. A failure to write is
+  // ignored since the compile error is what the host needs to hear about.
+  fn report(&mut self, error: &CedarError) {
+    let errors = match error {
+      CedarError::ScannerError(ScannerError::SignalFailure(errors)) => {
+        errors.iter().map(ToString::to_string).collect()
+      }
+      CedarError::CompilerError(CompilerError::Failed(errors)) => {
+        errors.iter().map(ToString::to_string).collect()
+      }
+      _ => Vec::new(),
+    };
+    for error in errors {
+      let _ = writeln!(self.stderr, "{}", error);
+    }
+    let _ = self.flush();
+  }
+
+  /// Make a Rust function or closure callable from scripts under the given
+  /// name. Closures can capture state, and take up to 12 arguments of any type
+  /// that implements `FromValue`, returning anything that implements
+  /// `IntoValue`.
+  ///
+  /// ```
+  /// # use cedar::VM;
+  /// let mut vm = VM::new();
+  /// vm.register_fn("hypot", |a: f64, b: f64| (a * a + b * b).sqrt()).unwrap();
+  /// vm.interpret("let c = hypot(3, 4);".into()).unwrap();
+  /// assert_eq!(vm.get_global_as::<f64>("c").unwrap(), 5.0);
+  /// ```
+  pub fn register_fn<F, Args>(&mut self, name: &str, f: F) -> Result<(), CedarError>
+  where
+    F: IntoNativeFunc<Args>,
+  {
+    self
+      .globals
+      .register_fn(name.to_string(), f)
+      .map(|_| ())
+      .ok_or_else(|| CedarError::HostError("Too many global variables".into()))
+  }
+
+  /// Make a native that is given a `Context` callable from scripts. Through
+  /// the context it can call functions it was passed, which is how natives
+  /// like `map` are written.
+  ///
+  /// ```
+  /// # use cedar::VM;
+  /// let mut vm = VM::new();
+  /// vm.register_native("twice", 2, |cx, args| {
+  ///   let once = cx.call(&args[0], &args[1..])?;
+  ///   cx.call(&args[0], &[once])
+  /// })
+  /// .unwrap();
+  /// vm.interpret("fn inc(x) { return x + 1; } let n = twice(inc, 1);".into()).unwrap();
+  /// assert_eq!(vm.get_global_as::<f64>("n").unwrap(), 3.0);
+  /// ```
+  pub fn register_native<F>(&mut self, name: &str, arity: usize, f: F) -> Result<(), CedarError>
+  where
+    F: Fn(&mut Context, Vec<Value>) -> Result<Value, CedarError> + 'static,
+  {
+    self
+      .globals
+      .register_native(name.to_string(), arity, f)
+      .map(|_| ())
+      .ok_or_else(|| CedarError::HostError("Too many global variables".into()))
+  }
+
+  /// Let scripts hold values of a Rust type and call its methods. Values of
+  /// the type are made with `userdata`.
+  ///
+  /// ```
+  /// # use cedar::{userdata::{UserRef, UserType}, VM};
+  /// struct Counter(f64);
+  ///
+  /// let mut vm = VM::new();
+  /// vm.register_type(
+  ///   UserType::new::<Counter>("Counter").method("add", |c: UserRef<Counter>, n: f64| {
+  ///     c.borrow_mut().0 += n;
+  ///     c.borrow().0
+  ///   }),
+  /// );
+  /// let counter = vm.userdata(Counter(0.0)).unwrap();
+  /// vm.set_global("counter", counter).unwrap();
+  /// vm.interpret("counter.add(2); let total = counter.add(3);".into()).unwrap();
+  /// assert_eq!(vm.get_global_as::<f64>("total").unwrap(), 5.0);
+  /// ```
+  pub fn register_type(&mut self, user_type: UserType) {
+    self.types.insert(user_type.type_id(), Rc::new(user_type));
+  }
+
+  /// Wrap a Rust object of a registered type as a value scripts can use
+  pub fn userdata<T: Any>(&self, value: T) -> Result<Value, CedarError> {
+    let user_type = self.types.get(&TypeId::of::<T>()).ok_or_else(|| {
+      CedarError::HostError(
+        format!(
+          "{} has not been registered as a type",
+          any::type_name::<T>()
+        )
+        .into(),
+      )
+    })?;
+    Ok(Value::UserData(Rc::new(UserData::new(
+      user_type.clone(),
+      value,
+    ))))
+  }
+
+  /// The value of a global variable if it's been defined
+  pub fn get_global(&self, name: &str) -> Option<Value> {
+    self.globals.get(self.globals.slot(name)?).cloned()
+  }
+
+  /// The value of a global variable converted to a Rust type
+  pub fn get_global_as<T>(&self, name: &str) -> Result<T, CedarError>
+  where
+    T: FromValue,
+  {
+    let value = self
+      .get_global(name)
+      .ok_or_else(|| CedarError::HostError(format!("Undefined variable '{}'", name).into()))?;
+    convert(name, value)
+  }
+
+  /// The value of a global variable deserialized into any type that
+  /// implements serde's `Deserialize`
+  #[cfg(feature = "serde")]
+  pub fn deserialize_global<T>(&self, name: &str) -> Result<T, CedarError>
+  where
+    T: serde::de::DeserializeOwned,
+  {
+    let value = self
+      .get_global(name)
+      .ok_or_else(|| CedarError::HostError(format!("Undefined variable '{}'", name).into()))?;
+    crate::serde_value::from_value(value).map_err(|e| {
+      CedarError::HostError(format!("'{}' could not be deserialized: {}", name, e).into())
+    })
+  }
+
+  /// Define a global variable, or change its value if it already exists
+  pub fn set_global<T>(&mut self, name: &str, value: T) -> Result<(), CedarError>
+  where
+    T: IntoValue,
+  {
+    self
+      .globals
+      .insert(name, value.to_value())
+      .map(|_| ())
+      .ok_or_else(|| CedarError::HostError("Too many global variables".into()))
+  }
+
+  fn frame(&self) -> Result<&CallFrame, CedarError> {
+    self
+      .frames
+      .last()
+      .ok_or_else(|| InterpreterResult::runtime_error("No call frame to execute", 0).into())
+  }
+  fn frame_mut(&mut self) -> Result<&mut CallFrame, CedarError> {
+    self
+      .frames
+      .last_mut()
+      .ok_or_else(|| InterpreterResult::runtime_error("No call frame to execute", 0).into())
+  }
+  fn slots(&self) -> Result<usize, CedarError> {
+    Ok(self.frame()?.slots)
+  }
+
+  /// Execute instructions until the frame that was called when there were
+  /// `depth` frames returns, handing back the value it returned
+  fn run(&mut self, depth: usize) -> Result<Value, CedarError> {
+    // Checking the limits slows down every instruction, so scripts without
+    // any run a copy of the loop that doesn't check them at all
+    if self.limits.max_instructions.is_some() || self.limits.max_stack.is_some() {
+      self.dispatch::<true>(depth)
+    } else {
+      self.dispatch::<false>(depth)
+    }
+  }
+  fn dispatch<const LIMITED: bool>(&mut self, depth: usize) -> Result<Value, CedarError> {
     loop {
-      let op = self.read_instruction();
-      *self.ip() += 1;
+      if LIMITED {
+        if self.fuel == 0 {
+          return Err(self.error("Instruction limit exceeded"));
+        }
+        self.fuel -= 1;
+      }
+      let op = self.read_instruction()?;
       match op {
         OpCode::Return => {
-          // Collect garbage on exit not that it matters much
-          self.collect_garbage();
-          let result = self.pop();
-          self.frames.pop();
+          let result = self.pop()?;
+          let frame = self.frames.pop().ok_or_else(|| {
+            InterpreterResult::runtime_error("Returned without a call frame", self.line())
+          })?;
           self.frame_count -= 1;
-          if self.frame_count == 0 {
-            self.pop();
-            return Ok(());
+          // Drop the function, its arguments and any locals left on the stack
+          self.stack.truncate(frame.slots);
+          if self.frames.len() == depth {
+            return Ok(result);
           }
-          self.push(result);
+          self.push_result(result);
         }
         OpCode::Constant => {
-          let constant = self.read_constant();
-          self.push(constant);
-        }
-        OpCode::Negate => {
-          let n = -self.pop().into_num().ok_or_else(|| {
-            InterpreterResult::runtime_error("Operand must be a number", self.line())
-          })?;
-          self.push(Value::Number(n));
-        }
-        OpCode::Add => {
-          let b = self.pop();
-          let a = self.pop();
-          match (b, a) {
-            (Value::Number(b), Value::Number(a)) => self.push(Value::Number(a + b)),
-            (Value::String(b), Value::String(a)) => {
-              let mut string = a.clone();
-              string.to_mut().push_str(b.borrow());
-              self.heap.push((Value::String(string), false));
-              self.push(Value::Heap(self.heap.len() - 1));
-            }
-            (Value::Number(b), Value::String(a)) => {
-              let mut string = a.clone();
-              string.to_mut().push_str(&b.to_string());
-              self.heap.push((Value::String(string), false));
-              self.push(Value::Heap(self.heap.len() - 1));
-            }
-            (Value::Bool(b), Value::String(a)) => {
-              let mut string = a.clone();
-              string.to_mut().push_str(&b.to_string());
-              self.heap.push((Value::String(string), false));
-              self.push(Value::Heap(self.heap.len() - 1));
-            }
-            (Value::Null, Value::String(a)) => {
-              let mut string = a.clone();
-              string.to_mut().push_str("null");
-              self.heap.push((Value::String(string), false));
-              self.push(Value::Heap(self.heap.len() - 1));
-            }
-            (_, Value::Number(_)) => {
-              return Err(
-                InterpreterResult::runtime_error("Second operand is not a number", self.line())
-                  .into(),
-              )
-            }
-            (Value::Number(_), _) => {
-              return Err(
-                InterpreterResult::runtime_error("First operand is not a number", self.line()).into(),
-              )
-            }
-            (_, _) => {
-              return Err(
-                InterpreterResult::runtime_error(
-                  "Addition operator can only be used with 2 number values or a String and another value",
-                  self.line(),
-                )
-                .into(),
-              )
-            }
-          }
-        }
-        OpCode::Subtract => {
-          let b = self.pop().into_num().ok_or_else(|| {
-            InterpreterResult::runtime_error("Operand must be a number", self.line())
-          })?;
-          let a = self.pop().into_num().ok_or_else(|| {
-            InterpreterResult::runtime_error("Operand must be a number", self.line())
-          })?;
-          self.push(Value::Number(a - b));
-        }
-        OpCode::Multiply => {
-          let b = self.pop().into_num().ok_or_else(|| {
-            InterpreterResult::runtime_error("Operand must be a number", self.line())
-          })?;
-          let a = self.pop().into_num().ok_or_else(|| {
-            InterpreterResult::runtime_error("Operand must be a number", self.line())
-          })?;
-          self.push(Value::Number(a * b));
-        }
-        OpCode::Divide => {
-          let b = self.pop().into_num().ok_or_else(|| {
-            InterpreterResult::runtime_error("Operand must be a number", self.line())
-          })?;
-          let a = self.pop().into_num().ok_or_else(|| {
-            InterpreterResult::runtime_error("Operand must be a number", self.line())
-          })?;
-          self.push(Value::Number(a / b));
+          let constant = self.read_constant()?;
+          self.push_within::<LIMITED>(constant)?;
         }
+        OpCode::Negate => self.unary(ops::negate)?,
+        OpCode::Not => self.unary(ops::not)?,
+        OpCode::Add => self.add()?,
+        OpCode::Subtract => self.binary(ops::subtract)?,
+        OpCode::Multiply => self.binary(ops::multiply)?,
+        OpCode::Divide => self.binary(ops::divide)?,
+        OpCode::Equal => self.binary(ops::equal)?,
+        OpCode::NotEqual => self.binary(ops::not_equal)?,
+        OpCode::Greater => self.binary(ops::greater)?,
+        OpCode::GreaterOrEqual => self.binary(ops::greater_or_equal)?,
+        OpCode::Less => self.binary(ops::less)?,
+        OpCode::LessOrEqual => self.binary(ops::less_or_equal)?,
         OpCode::False => {
-          self.push(Value::Bool(false));
+          self.push_within::<LIMITED>(Value::Bool(false))?;
         }
         OpCode::True => {
-          self.push(Value::Bool(true));
+          self.push_within::<LIMITED>(Value::Bool(true))?;
         }
         OpCode::Null => {
-          self.push(Value::Null);
-        }
-        OpCode::Not => {
-          let boolean = self.pop().into_bool().ok_or_else(|| {
-            InterpreterResult::runtime_error("Operand must be a boolean", self.line())
-          })?;
-          self.push(Value::Bool(!boolean));
-        }
-        OpCode::Equal => {
-          let b = self.pop();
-          let a = self.pop();
-          match (b, a) {
-            (Value::Bool(b), Value::Bool(a)) => self.push(Value::Bool(a == b)),
-            (Value::String(b), Value::String(a)) => self.push(Value::Bool(a == b)),
-            (Value::Number(b), Value::Number(a)) => {
-              self.push(Value::Bool((a - b).abs() < f64::EPSILON))
-            }
-            (Value::Null, Value::Null) => self.push(Value::Bool(true)),
-            (_, Value::Null) => self.push(Value::Bool(false)),
-            (Value::Null, _) => self.push(Value::Bool(false)),
-            (_, _) => {
-              return Err(
-                InterpreterResult::runtime_error(
-                  "Equality operator can only be used with 2 of the same type",
-                  self.line(),
-                )
-                .into(),
-              )
-            }
-          }
-        }
-        OpCode::NotEqual => {
-          let b = self.pop();
-          let a = self.pop();
-          match (b, a) {
-            (Value::Bool(b), Value::Bool(a)) => self.push(Value::Bool(a != b)),
-            (Value::String(b), Value::String(a)) => self.push(Value::Bool(a != b)),
-            (Value::Number(b), Value::Number(a)) => {
-              self.push(Value::Bool((a - b).abs() > f64::EPSILON))
-            }
-            (Value::Null, Value::Null) => self.push(Value::Bool(false)),
-            (_, _) => {
-              return Err(
-                InterpreterResult::runtime_error(
-                  "Not equal operator can only be used with 2 of the same type",
-                  self.line(),
-                )
-                .into(),
-              )
-            }
-          }
-        }
-        OpCode::Greater => {
-          let b = self.pop();
-          let a = self.pop();
-          match (b, a) {
-            (Value::Bool(b), Value::Bool(a)) => self.push(Value::Bool(a & !b)),
-            (Value::String(b), Value::String(a)) => self.push(Value::Bool(a > b)),
-            (Value::Number(b), Value::Number(a)) => self.push(Value::Bool(a > b)),
-            (Value::Null, Value::Null) => self.push(Value::Bool(false)),
-            (_, _) => {
-              return Err(
-                InterpreterResult::runtime_error(
-                  "Greater than operator can only be used with 2 of the same type",
-                  self.line(),
-                )
-                .into(),
-              )
-            }
-          }
-        }
-        OpCode::GreaterOrEqual => {
-          let b = self.pop();
-          let a = self.pop();
-          match (b, a) {
-            (Value::Bool(b), Value::Bool(a)) => self.push(Value::Bool(a >= b)),
-            (Value::String(b), Value::String(a)) => self.push(Value::Bool(a >= b)),
-            (Value::Number(b), Value::Number(a)) => self.push(Value::Bool(a >= b)),
-            (Value::Null, Value::Null) => self.push(Value::Bool(true)),
-            (_, _) => {
-              return Err(
-                InterpreterResult::runtime_error(
-                  "Greater than or equal operator can only be used with 2 of the same type",
-                  self.line(),
-                )
-                .into(),
-              )
-            }
-          }
-        }
-        OpCode::Less => {
-          let b = self.pop();
-          let a = self.pop();
-          match (b, a) {
-            (Value::Bool(b), Value::Bool(a)) => self.push(Value::Bool(!a & b)),
-            (Value::String(b), Value::String(a)) => self.push(Value::Bool(a < b)),
-            (Value::Number(b), Value::Number(a)) => self.push(Value::Bool(a < b)),
-            (Value::Null, Value::Null) => self.push(Value::Bool(false)),
-            (_, _) => {
-              return Err(
-                InterpreterResult::runtime_error(
-                  "Less than operator can only be used with 2 of the same type",
-                  self.line(),
-                )
-                .into(),
-              )
-            }
-          }
-        }
-        OpCode::LessOrEqual => {
-          let b = self.pop();
-          let a = self.pop();
-          match (b, a) {
-            (Value::Bool(b), Value::Bool(a)) => self.push(Value::Bool(a <= b)),
-            (Value::String(b), Value::String(a)) => self.push(Value::Bool(a <= b)),
-            (Value::Number(b), Value::Number(a)) => self.push(Value::Bool(a <= b)),
-            (Value::Null, Value::Null) => self.push(Value::Bool(true)),
-            (_, _) => {
-              return Err(
-                InterpreterResult::runtime_error(
-                  "Less than or equal operator can only be used with 2 of the same type",
-                  self.line(),
-                )
-                .into(),
-              )
-            }
-          }
+          self.push_within::<LIMITED>(Value::Null)?;
         }
         OpCode::Print => {
-          println!("{}", self.pop());
+          let value = self.pop()?;
+          if let Err(e) = writeln!(self.stdout, "{}", value) {
+            return Err(self.error(format!("Could not print: {}", e)));
+          }
         }
         OpCode::Pop => {
-          self.pop();
+          self.pop()?;
         }
         OpCode::DefineGlobal => {
-          let name = self.read_constant().into_string().ok_or_else(|| {
-            InterpreterResult::runtime_error(
-              "The identifier being used was not a string and is an internal runtime error",
-              self.line(),
-            )
-          })?;
-          let value = self.pop();
-          self.globals.insert(name, value);
+          let slot = self.read_u16()?;
+          let value = self.pop()?;
+          if !self.globals.define(slot, value) {
+            return Err(self.undefined_global(slot));
+          }
         }
         OpCode::GetGlobal => {
-          let name = self.read_constant().into_string().ok_or_else(|| {
-            InterpreterResult::runtime_error(
-              "The identifier being used was not a string and is an internal runtime error",
-              self.line(),
-            )
-          })?;
-          self.push(
-            self
-              .globals
-              .get(&name)
-              .ok_or_else(|| {
-                InterpreterResult::runtime_error(
-                  format!("Undefined variable '{}'", name),
-                  self.line(),
-                )
-              })?
-              .clone(),
-          );
+          let slot = self.read_u16()?;
+          let value = self
+            .globals
+            .get(slot)
+            .cloned()
+            .ok_or_else(|| self.undefined_global(slot))?;
+          self.push_within::<LIMITED>(value)?;
         }
         OpCode::SetGlobal => {
-          let name = self.read_constant().into_string().ok_or_else(|| {
-            InterpreterResult::runtime_error(
-              "The identifier being used was not a string and is an internal runtime error",
-              self.line(),
-            )
-          })?;
-          let value = self.peek();
-          if self.globals.insert(name.clone(), value).is_none() {
-            return Err(
-              InterpreterResult::runtime_error(
-                format!("Undefined variable '{}'", name),
-                self.line(),
-              )
-              .into(),
-            );
+          let slot = self.read_u16()?;
+          let value = self.peek()?;
+          if !self.globals.set(slot, value) {
+            return Err(self.undefined_global(slot));
           }
         }
         OpCode::GetLocal => {
-          let slot = self.read_byte() as usize;
-          self.push(self.stack[slot + self.slots()].clone());
+          let slot = self.read_byte()? as usize + self.slots()?;
+          let value = self.stack.get(slot).cloned().ok_or_else(|| {
+            InterpreterResult::runtime_error("Local variable is not on the stack", self.line())
+          })?;
+          self.push_within::<LIMITED>(value)?;
         }
         OpCode::SetLocal => {
-          let slot = self.read_byte() as usize + self.slots();
-          self.stack[slot] = self.peek();
+          let slot = self.read_byte()? as usize + self.slots()?;
+          let value = self.peek()?;
+          let line = self.line();
+          *self.stack.get_mut(slot).ok_or_else(|| {
+            InterpreterResult::runtime_error("Local variable is not on the stack", line)
+          })? = value;
         }
         OpCode::JumpIfFalse => {
-          let offset = self.read_u16();
-          if self.peek() == Value::Bool(false) {
-            *self.ip() += offset as usize;
+          let offset = self.read_u16()?;
+          if matches!(self.stack.last(), Some(Value::Bool(false))) {
+            self.frame_mut()?.ip += offset as usize;
           }
         }
         OpCode::Jump => {
-          *self.ip() += self.read_u16() as usize;
+          let offset = self.read_u16()?;
+          self.frame_mut()?.ip += offset as usize;
         }
         OpCode::Loop => {
-          *self.ip() -= self.read_u16() as usize;
+          let offset = self.read_u16()? as usize;
+          let line = self.line();
+          let frame = self.frame_mut()?;
+          frame.ip = frame.ip.checked_sub(offset).ok_or_else(|| {
+            InterpreterResult::runtime_error("Loop jumped before the start of the chunk", line)
+          })?;
         }
         OpCode::Call => {
-          let arg_count = self.read_byte();
-          let callee = self.peek_n(arg_count as usize);
+          let arg_count = self.read_byte()?;
+          let callee = self.peek_n(arg_count as usize)?;
           self.call_value(callee, arg_count)?;
         }
+        OpCode::Invoke => {
+          let name = self.read_constant()?;
+          let arg_count = self.read_byte()?;
+          self.invoke_method(name, arg_count)?;
+        }
+        OpCode::Index => self.binary(ops::index)?,
+        OpCode::BuildList => {
+          let count = self.read_byte()? as usize;
+          self.check_collection_length(Some(count))?;
+          let start = self.stack.len().checked_sub(count).ok_or_else(|| {
+            InterpreterResult::runtime_error("Not enough values on the stack for list", self.line())
+          })?;
+          let list = self.stack.split_off(start);
+          self.push_within::<LIMITED>(Value::from(list))?;
+        }
+        OpCode::IncrementLocal => {
+          let slot = self.read_byte()? as usize + self.slots()?;
+          let constant = self.read_constant()?;
+          let line = self.line();
+          let max = self.max_string_len();
+          let local = self.stack.get_mut(slot).ok_or_else(|| {
+            InterpreterResult::runtime_error("Local variable is not on the stack", line)
+          })?;
+          // Take the value out so a string can be appended to in place
+          let value = mem::replace(local, Value::Null);
+          *local = ops::add_within(value, constant, max)
+            .map_err(|e| InterpreterResult::runtime_error(e, line))?;
+        }
+        OpCode::AddConstant => {
+          let b = self.read_constant()?;
+          self.release_assignment_target(0, &b)?;
+          let a = self.pop()?;
+          let value = ops::add_within(a, b, self.max_string_len())
+            .map_err(|e| InterpreterResult::runtime_error(e, self.line()))?;
+          self.push_result(value);
+        }
+        OpCode::PopJumpIfFalse => {
+          let offset = self.read_u16()?;
+          if let Value::Bool(false) = self.pop()? {
+            self.frame_mut()?.ip += offset as usize;
+          }
+        }
+        OpCode::CompareJumpIfFalse => {
+          let byte = self.read_byte()?;
+          let compare = OpCode::try_from(byte)
+            .ok()
+            .and_then(ops::comparison)
+            .ok_or_else(|| {
+              InterpreterResult::runtime_error(
+                format!("Operand {} is not a comparison opcode", byte),
+                self.line(),
+              )
+            })?;
+          let offset = self.read_u16()?;
+          let b = self.pop()?;
+          let a = self.pop()?;
+          let value =
+            compare(a, b).map_err(|e| InterpreterResult::runtime_error(e, self.line()))?;
+          if let Value::Bool(false) = value {
+            self.frame_mut()?.ip += offset as usize;
+          }
+        }
       }
     }
   }
-  fn read_byte(&mut self) -> u8 {
-    let value = self.chunk().code[self.ip_()];
-    *self.ip() += 1;
-    value
+  fn undefined_global(&self, slot: u16) -> CedarError {
+    let message = match self.globals.name(slot) {
+      Some(name) => format!("Undefined variable '{}'", name),
+      None => format!("Global slot {} does not exist", slot),
+    };
+    InterpreterResult::runtime_error(message, self.line()).into()
+  }
+  /// When a string is appended to and the result is stored straight back into
+  /// the variable it came from, as in `s = s + "more"`, the variable still
+  /// holds a reference to the string so the append has to copy it. That makes
+  /// building up a string in a loop quadratic. Since the variable is about to
+  /// be overwritten anyway we can drop its reference early, which lets the
+  /// append happen in place when nothing else is holding on to the string.
+  ///
+  /// `depth` is how far down the stack the string being appended to is and
+  /// `appended` is what's being added to it. Nothing is released unless the
+  /// add is guaranteed to succeed, as the variable can't be put back if it
+  /// fails.
+  fn release_assignment_target(
+    &mut self,
+    depth: usize,
+    appended: &Value,
+  ) -> Result<(), CedarError> {
+    let string = match self.peek_n(depth)? {
+      Value::String(string) if appendable(&string, appended, self.max_string_len()) => string,
+      _ => return Ok(()),
+    };
+    let frame = self.frame()?;
+    let code = &frame.function.chunk.code;
+    let byte = |offset: usize| code.get(frame.ip + offset).copied();
+    let target = match (byte(0).map(OpCode::try_from), byte(1), byte(2)) {
+      (Some(Ok(OpCode::SetLocal)), Some(slot), _) => Target::Local(frame.slots + slot as usize),
+      (Some(Ok(OpCode::SetGlobal)), Some(high), Some(low)) => {
+        Target::Global(((high as u16) << 8) | low as u16)
+      }
+      _ => return Ok(()),
+    };
+    let current = match target {
+      Target::Local(slot) => self.stack.get(slot),
+      Target::Global(slot) => self.globals.get(slot),
+    };
+    match current {
+      Some(Value::String(current)) if Rc::ptr_eq(current, &string) => {}
+      _ => return Ok(()),
+    }
+    match target {
+      Target::Local(slot) => self.stack[slot] = Value::Null,
+      Target::Global(slot) => {
+        self.globals.set(slot, Value::Null);
+      }
+    }
+    Ok(())
+  }
+  fn add(&mut self) -> Result<(), CedarError> {
+    // Only appending to a string needs the variable released
+    if let Some(Value::String(_)) = self.stack.iter().rev().nth(1) {
+      let b = self.peek()?;
+      self.release_assignment_target(1, &b)?;
+    }
+    let b = self.pop()?;
+    let a = self.pop()?;
+    let value = ops::add_within(a, b, self.max_string_len())
+      .map_err(|e| InterpreterResult::runtime_error(e, self.line()))?;
+    self.push_result(value);
+    Ok(())
+  }
+  fn unary(&mut self, op: fn(Value) -> OpResult) -> Result<(), CedarError> {
+    let a = self.pop()?;
+    let value = op(a).map_err(|e| InterpreterResult::runtime_error(e, self.line()))?;
+    self.push_result(value);
+    Ok(())
   }
-  fn read_u16(&mut self) -> u16 {
-    *self.ip() += 2;
-    let high = self.chunk().code[self.ip_() - 2] as u16;
-    let low = self.chunk().code[self.ip_() - 1] as u16;
-    (high << 8) | low
+  fn binary(&mut self, op: fn(Value, Value) -> OpResult) -> Result<(), CedarError> {
+    let b = self.pop()?;
+    let a = self.pop()?;
+    let value = op(a, b).map_err(|e| InterpreterResult::runtime_error(e, self.line()))?;
+    self.push_result(value);
+    Ok(())
   }
-  fn call_value(&mut self, callee: Value, mut arg_count: u8) -> Result<(), CedarError> {
+  fn read_byte(&mut self) -> Result<u8, CedarError> {
+    let frame = self.frame_mut()?;
+    let byte = frame.function.chunk.code.get(frame.ip).copied();
+    frame.ip += 1;
+    byte.ok_or_else(|| {
+      InterpreterResult::runtime_error("Read past the end of the chunk", self.line()).into()
+    })
+  }
+  fn read_u16(&mut self) -> Result<u16, CedarError> {
+    let high = self.read_byte()? as u16;
+    let low = self.read_byte()? as u16;
+    Ok((high << 8) | low)
+  }
+  fn call_value(&mut self, callee: Value, arg_count: u8) -> Result<(), CedarError> {
     match callee {
       Value::Function(func) => self.call(func, arg_count),
       Value::NativeFn(func) => {
-        let mut args = Vec::with_capacity(arg_count as usize);
-        while arg_count != 0 {
-          args.push(self.pop());
-          arg_count -= 1;
+        if arg_count as usize != func.arity {
+          return Err(self.arity_error(func.arity, arg_count));
         }
-        let res = func.call(args).ok_or_else(|| {
-          InterpreterResult::runtime_error(
-            "Native function call returned an invalid value",
-            self.line(),
-          )
-        })?;
-        self.push(res);
+        // The arguments are on the stack in order with the native just below
+        let start = self
+          .stack
+          .len()
+          .checked_sub(arg_count as usize + 1)
+          .ok_or_else(|| {
+            InterpreterResult::runtime_error("Not enough values on the stack for call", self.line())
+          })?;
+        let args = self.stack.split_off(start + 1);
+        self.pop()?;
+        let res = func.call(&mut Context::new(self, &func.name), args)?;
+        self.push_result(res);
         Ok(())
       }
       _ => Err(
@@ -404,139 +772,177 @@ impl VM {
       ),
     }
   }
-  fn call(&mut self, function: Function, arg_count: u8) -> Result<(), CedarError> {
-    if arg_count as usize > function.arity {
-      return Err(
-        InterpreterResult::runtime_error(
-          format!(
-            "Expected {} arguments but got {}",
-            arg_count, function.arity
-          ),
-          self.line(),
-        )
-        .into(),
-      );
+  /// A runtime error at the line currently being executed
+  pub(crate) fn error<M: Into<Cow<'static, str>>>(&self, message: M) -> CedarError {
+    InterpreterResult::runtime_error(message, self.line()).into()
+  }
+  // The most bytes a string made by the script can hold
+  pub(crate) fn max_string_len(&self) -> usize {
+    self
+      .limits
+      .max_string_len
+      .map_or(ops::MAX_STRING_LENGTH, |max| {
+        max.min(ops::MAX_STRING_LENGTH)
+      })
+  }
+  pub(crate) fn check_string_length(&self, bytes: Option<usize>) -> Result<(), CedarError> {
+    ops::check_length(bytes, self.max_string_len()).map_err(|e| self.error(e))
+  }
+  pub(crate) fn check_collection_length(&self, length: Option<usize>) -> Result<(), CedarError> {
+    match self.limits.max_collection_len {
+      Some(max) if length.is_none_or(|length| length > max) => Err(self.error(format!(
+        "Can't make a list or map with more than {} entries",
+        max
+      ))),
+      _ => Ok(()),
+    }
+  }
+  pub(crate) fn intern(&mut self, s: &str) -> Rc<String> {
+    self.strings.intern(s)
+  }
+  fn invoke_method(&mut self, name: Value, arg_count: u8) -> Result<(), CedarError> {
+    let name = name
+      .into_string()
+      .ok_or_else(|| self.error("Method name is not a string"))?;
+    let method = match self.peek_n(arg_count as usize)? {
+      Value::UserData(data) => data.user_type().get_method(&name).cloned().ok_or_else(|| {
+        self.error(format!(
+          "Undefined method '{}' on {}",
+          name,
+          data.type_name()
+        ))
+      })?,
+      other => {
+        return Err(self.error(format!(
+          "Can only call methods on userdata, not {}",
+          other.type_name()
+        )))
+      }
+    };
+    // The receiver is passed as the method's first argument
+    if arg_count as usize + 1 != method.arity {
+      return Err(self.arity_error(method.arity - 1, arg_count));
+    }
+    let start = self.stack.len() - arg_count as usize - 1;
+    let args = self.stack.split_off(start);
+    let res = method.call(&mut Context::new(self, &method.name), args)?;
+    self.push_result(res);
+    Ok(())
+  }
+  fn arity_error(&self, arity: usize, arg_count: u8) -> CedarError {
+    InterpreterResult::runtime_error(
+      format!("Expected {} arguments but got {}", arity, arg_count),
+      self.line(),
+    )
+    .into()
+  }
+  fn call(&mut self, function: Rc<Function>, arg_count: u8) -> Result<(), CedarError> {
+    if arg_count as usize != function.arity {
+      return Err(self.arity_error(function.arity, arg_count));
+    }
+    // The function being called sits just below its arguments in slot 0
+    let slots = self
+      .stack
+      .len()
+      .checked_sub(arg_count as usize + 1)
+      .ok_or_else(|| {
+        InterpreterResult::runtime_error("Not enough values on the stack for call", self.line())
+      })?;
+    let too_deep = matches!(self.limits.max_call_depth, Some(max) if self.frames.len() >= max);
+    let too_big = matches!(self.limits.max_stack, Some(max) if self.stack.len() > max);
+    if too_deep || too_big {
+      return Err(self.error("Stack overflow"));
     }
     self.frame_count += 1;
     self.frames.push(CallFrame {
       ip: 0,
-      slots: if self.stack.is_empty() {
-        0
-      } else {
-        self.stack.len() - arg_count as usize
-      },
+      slots,
       function,
     });
 
     Ok(())
   }
 
-  // To do make this not hot garbage
-  #[allow(clippy::while_immutable_condition)]
-  fn collect_garbage(&mut self) {
-    for i in &self.stack {
-      if let Value::Heap(h) = i {
-        let mut pointer = *h;
-        while {
-          self.heap[pointer].1 = true;
-          if let Value::Heap(inner) = self.heap[pointer].0 {
-            pointer = inner;
-            true
-          } else {
-            false
-          }
-        } {}
-      }
-    }
-    // There's nothing to collect so return early
-    if self.heap.is_empty() {
-      return;
-    }
-    let old_max_index = self.heap.len() - 1;
-    self.heap.retain(|(_, alive)| *alive);
-    let offset = old_max_index - (self.heap.len().saturating_sub(1));
-    self.heap.iter_mut().for_each(|(h, alive)| {
-      if let Value::Heap(inner) = h {
-        *inner -= offset;
-      }
-      *alive = false
-    });
-    self.stack.iter_mut().for_each(|h| {
-      if let Value::Heap(inner) = h {
-        *inner -= offset;
-      }
-    });
+  fn chunk(&self) -> Result<&Chunk, CedarError> {
+    Ok(&self.frame()?.function.chunk)
   }
-  fn chunk(&self) -> &Chunk {
-    &self.frames[self.frame_count - 1].function.chunk
+  fn read_instruction(&mut self) -> Result<OpCode, CedarError> {
+    let byte = self.read_byte()?;
+    OpCode::try_from(byte)
+      .map_err(|e| InterpreterResult::runtime_error(e.to_string(), self.line()).into())
   }
-  fn read_instruction(&self) -> OpCode {
-    self.chunk().code[self.ip_()].into()
+  fn read_constant(&mut self) -> Result<Value, CedarError> {
+    let index = self.read_byte()? as usize;
+    self.chunk()?.constants.get(index).cloned().ok_or_else(|| {
+      InterpreterResult::runtime_error(format!("Constant {} does not exist", index), self.line())
+        .into()
+    })
   }
-  fn read_constant(&mut self) -> Value {
-    let value = self.chunk().constants[self.chunk().code[self.ip_()] as usize].clone();
-    *self.ip() += 1;
-    value
+  fn push(&mut self, value: Value) -> Result<(), CedarError> {
+    self.push_within::<true>(value)
   }
-  fn push(&mut self, value: Value) {
+  // Push a value, only checking it fits under the stack limit if there
+  // might be one
+  #[inline]
+  fn push_within<const LIMITED: bool>(&mut self, value: Value) -> Result<(), CedarError> {
+    if LIMITED && matches!(self.limits.max_stack, Some(max) if self.stack.len() >= max) {
+      return Err(self.stack_overflow());
+    }
     self.stack.push(value);
+    Ok(())
   }
-  fn pop(&mut self) -> Value {
-    let value = self.stack.pop().expect("Popped empty stack value");
-
-    // This could be so so much better but I'm not really stuck
-    // on a gc design or inneficiencies yet. Maybe I could use
-    // Cow here idk
-    if let Value::Heap(h) = value {
-      self.heap[h].0.clone()
-    } else {
-      value
-    }
+  // Put a result on the stack in place of the values it was made from, which
+  // were just popped, so it can't take the stack over the limit
+  fn push_result(&mut self, value: Value) {
+    self.stack.push(value);
   }
-  fn peek(&mut self) -> Value {
-    self
-      .stack
-      .iter()
-      .rev()
-      .next()
-      .cloned()
-      .expect("No value to peek on stack")
+  // Kept out of line so that the check in push stays small
+  #[cold]
+  fn stack_overflow(&self) -> CedarError {
+    self.error("Stack overflow")
   }
-  fn peek_n(&mut self, n: usize) -> Value {
-    self
-      .stack
-      .iter()
-      .rev()
-      .nth(n)
-      .cloned()
-      .expect("No value to peek on stack")
+  fn pop(&mut self) -> Result<Value, CedarError> {
+    self.stack.pop().ok_or_else(|| {
+      InterpreterResult::runtime_error("Popped a value from an empty stack", self.line()).into()
+    })
+  }
+  fn peek(&self) -> Result<Value, CedarError> {
+    self.peek_n(0)
+  }
+  fn peek_n(&self, n: usize) -> Result<Value, CedarError> {
+    self.stack.iter().rev().nth(n).cloned().ok_or_else(|| {
+      InterpreterResult::runtime_error("No value to peek on stack", self.line()).into()
+    })
   }
   fn line(&self) -> usize {
-    self.chunk().lines[self.ip_() - 1]
+    self
+      .frames
+      .last()
+      .and_then(|frame| frame.function.chunk.lines.get(frame.ip.checked_sub(1)?))
+      .copied()
+      .unwrap_or(0)
   }
   #[allow(dead_code)]
   pub fn debug(&self) {
     self.print_globals();
     self.print_stack();
-    self.print_heap();
   }
   #[allow(dead_code)]
   pub fn print_globals(&self) {
-    println!("--- Globals ---\n{:#?}", self.globals);
+    println!("--- Globals ---");
+    for (name, value) in self.globals.iter() {
+      println!("{} = {:?}", name, value);
+    }
   }
   #[allow(dead_code)]
   pub fn print_stack(&self) {
     println!("--- Stack ---\n{:#?}", self.stack);
   }
-  #[allow(dead_code)]
-  pub fn print_heap(&self) {
-    println!("--- Heap ---\n{:#?}", self.heap);
-  }
 }
 
 #[derive(Debug)]
 struct CallFrame {
-  function: Function,
+  function: Rc<Function>,
   ip: usize,
   // first index in stack it can point too.
   slots: usize,
@@ -568,3 +974,26 @@ impl fmt::Display for InterpreterResult {
 }
 
 impl std::error::Error for InterpreterResult {}
+
+// Where the next instruction is going to store a value
+#[derive(Clone, Copy)]
+enum Target {
+  Local(usize),
+  Global(u16),
+}
+
+// Whether adding a value to a string always succeeds. No number is written
+// out with more than a few hundred characters.
+fn appendable(string: &str, value: &Value, max_length: usize) -> bool {
+  let added = match value {
+    Value::String(s) => s.len(),
+    Value::Number(_) | Value::Bool(_) | Value::Null => 512,
+    _ => return false,
+  };
+  matches!(string.len().checked_add(added), Some(length) if length <= max_length)
+}
+
+fn convert<T: FromValue>(name: &str, value: Value) -> Result<T, CedarError> {
+  T::try_from_value(value)
+    .map_err(|e| CedarError::HostError(e.describe(&format!("'{}'", name)).into()))
+}
@@ -0,0 +1,131 @@
+use cedar::{
+  assembler::assemble,
+  chunk::OpCode,
+  compiler::compile,
+  globals::Globals,
+  value::{Function, Value},
+  CedarError, VM,
+};
+use pretty_assertions::assert_eq;
+use std::{fs, path::PathBuf};
+
+#[test]
+fn round_trip() -> Result<(), CedarError> {
+  for script in &["control-flow", "functions", "native", "scopes"] {
+    let path = PathBuf::from("tests")
+      .join("cedar-scripts")
+      .join(format!("{}.cdr", script));
+    let function = compile(fs::read_to_string(path)?, &mut Globals::new())?;
+    let listing = function.chunk.disassemble("MAIN");
+    let chunk = assemble(&listing)?;
+    assert_eq!(chunk, function.chunk);
+    assert_eq!(chunk.disassemble("MAIN"), listing);
+  }
+  Ok(())
+}
+
+#[test]
+fn hand_written() -> Result<(), CedarError> {
+  let chunk = assemble(
+    r#"
+.chunk MAIN
+.const 0 number 3
+.const 1 function "double" 1
+  .code
+  [line 2] GetLocal 1
+  GetLocal 1
+  Add
+  Return
+.end
+.code
+; Count down from 3 calling double each time
+[line 1] Constant 0
+top:
+GetLocal 1
+Constant 0
+Greater
+JumpIfFalse done
+Pop
+Constant 1
+GetLocal 1
+Call 1
+Pop
+[line 3] Loop top
+done:
+Pop
+Null
+Return
+.end
+"#,
+  )?;
+  assert_eq!(chunk.constants[0], Value::Number(3.0));
+  match &chunk.constants[1] {
+    Value::Function(f) => {
+      assert_eq!(f.name, "double");
+      assert_eq!(f.arity, 1);
+      assert_eq!(f.chunk.lines, vec![2; 6]);
+    }
+    other => panic!("Expected a function constant, got {}", other),
+  }
+  // The Loop instruction jumps back 19 bytes to `top`
+  assert_eq!(&chunk.code[18..21], &[OpCode::Loop.into(), 0, 19]);
+
+  let mut function = Function::new();
+  function.chunk = chunk;
+  // The loop never terminates as the counter is never decremented, so we
+  // only check the chunk passes verification
+  cedar::verifier::verify(&function)?;
+  Ok(())
+}
+
+#[test]
+fn executes_assembled_chunks() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  let slot = vm.globals_mut().resolve("a").unwrap();
+  let mut function = Function::new();
+  function.chunk = assemble(&format!(
+    r#"
+.chunk MAIN
+.const 0 string "tab\tand \"quotes\""
+.code
+Constant 0
+DefineGlobal {slot}
+GetGlobal {slot}
+Pop
+Null
+Return
+.end
+"#,
+    slot = slot
+  ))?;
+  assert_eq!(
+    function.chunk.constants[0],
+    Value::from("tab\tand \"quotes\"")
+  );
+  vm.execute(function)?;
+  assert_eq!(
+    vm.globals().get(slot),
+    Some(&Value::from("tab\tand \"quotes\""))
+  );
+  Ok(())
+}
+
+#[test]
+fn errors() {
+  let error = assemble(".chunk MAIN\n.code\nJump nowhere\n.end\n").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 4] Error in assembly: Label 'nowhere' is not defined"
+  );
+  let error = assemble(".chunk MAIN\n.code\nFrobnicate\n.end\n").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 3] Error in assembly: Unknown instruction: Frobnicate"
+  );
+  let error =
+    assemble(".chunk MAIN\n.const 0 number 1\n.code\nConstant 0 junk\nReturn\n.end\n").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 4] Error in assembly: Unexpected input at the end of the line"
+  );
+}
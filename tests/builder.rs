@@ -0,0 +1,146 @@
+use cedar::{libstd::Module, value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+
+#[test]
+fn sandboxed_vms_have_no_standard_library() {
+  let mut vm = VM::sandboxed();
+  let error = vm
+    .interpret(r#"let text = read-file("Cargo.toml");"#.into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Undefined variable 'read-file'"
+  );
+  assert!(VM::builder()
+    .build()
+    .unwrap()
+    .get_global("read-file")
+    .is_none());
+}
+
+#[test]
+fn modules_and_natives_are_opted_into() -> Result<(), CedarError> {
+  let mut vm = VM::builder()
+    .module(Module::Io)
+    .register_fn("double", |n: f64| n * 2.0)
+    .register_native("apply", 2, |cx, args| cx.call(&args[0], &args[1..]))
+    .global("answer", 21.0)
+    .build()?;
+  assert!(matches!(
+    vm.get_global("read-file"),
+    Some(Value::NativeFn(_))
+  ));
+  vm.interpret("let n = double(answer); let f = apply(double, n);".into())?;
+  assert_eq!(vm.get_global_as::<f64>("f")?, 84.0);
+
+  let vm = VM::builder().modules(Module::ALL).build()?;
+  assert!(vm.get_global("write-file").is_some());
+  Ok(())
+}
+
+#[test]
+fn limits_stop_runaway_scripts() -> Result<(), CedarError> {
+  let mut vm = VM::builder().max_call_depth(64).build()?;
+  let error = vm
+    .interpret("fn forever(n) { return forever(n + 1); } forever(0);".into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Stack overflow"
+  );
+  vm.interpret("fn three() { return 3; } let n = three();".into())?;
+  assert_eq!(vm.get_global_as::<f64>("n")?, 3.0);
+
+  let mut vm = VM::builder().max_instructions(1_000).build()?;
+  let error = vm
+    .interpret("let i = 0;\nwhile true {\n  i = i + 1;\n}".into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 3] Error in script: Instruction limit exceeded"
+  );
+  // Every call from the host gets the full budget again
+  vm.interpret("let j = 0; while j < 10 { j = j + 1; }".into())?;
+  assert_eq!(vm.get_global_as::<f64>("j")?, 10.0);
+
+  // Values pushed without any calls count towards the stack limit too
+  let mut vm = VM::builder().max_stack(16).build()?;
+  let nested = format!(
+    "let one = 1; let deep = {}one{};",
+    "(one + ".repeat(20),
+    ")".repeat(20)
+  );
+  let error = vm.interpret(nested).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Stack overflow"
+  );
+  let nested = format!(
+    "let shallow = {}one{};",
+    "(one + ".repeat(10),
+    ")".repeat(10)
+  );
+  vm.interpret(nested)?;
+  assert_eq!(vm.get_global_as::<f64>("shallow")?, 11.0);
+
+  // Arguments from the host are pushed the same as a script's
+  let mut vm = VM::builder()
+    .max_stack(4)
+    .register_native("count", 6, |_, args| Ok(Value::Number(args.len() as f64)))
+    .build()?;
+  let error = vm
+    .call_function("count", &vec![Value::Null; 6])
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 0] Error in script: Stack overflow"
+  );
+  Ok(())
+}
+
+#[test]
+fn limits_stop_scripts_using_too_much_memory() -> Result<(), CedarError> {
+  let mut vm = VM::builder()
+    .module(Module::String)
+    .module(Module::Collections)
+    .module(Module::Json)
+    .max_string_len(1024)
+    .max_collection_len(4)
+    .build()?;
+  let error = vm
+    .interpret(r#"let s = "x"; let i = 0; while i < 40 { s = s + s; i = i + 1; }"#.into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Can't make a string longer than 1024 bytes"
+  );
+  // The string that was being appended to is left as it was
+  assert_eq!(vm.get_global_as::<String>("s")?.len(), 1024);
+
+  let too_long = "Can't make a string longer than 1024 bytes";
+  let too_many = "Can't make a list or map with more than 4 entries";
+  for (script, message) in [
+    (r#"repeat("ab", 513);"#, too_long),
+    (
+      r#"let l = [1]; for let i = 0; i < 12; i = i + 1 { l = [l, l]; } json-stringify(l, null);"#,
+      too_long,
+    ),
+    ("[1, 2, 3, 4, 5];", too_many),
+    ("push([1, 2, 3, 4], 5);", too_many),
+    (
+      r#"insert(insert(insert(insert(insert(new-map(), "a", 1), "b", 2), "c", 3), "d", 4), "e", 5);"#,
+      too_many,
+    ),
+    (r#"split("a,b,c,d,e", ",");"#, too_many),
+    (r#"chars("abcde");"#, too_many),
+  ] {
+    let error = vm.interpret(script.into()).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      format!("[line 1] Error in script: {}", message)
+    );
+  }
+  vm.interpret(r#"let four = split("a,b,c,d", ",");"#.into())?;
+  assert_eq!(vm.get_global_as::<Vec<String>>("four")?.len(), 4);
+  Ok(())
+}
@@ -0,0 +1,111 @@
+use cedar::{CedarError, VM};
+use pretty_assertions::assert_eq;
+use std::collections::BTreeMap;
+
+#[test]
+fn lists_can_be_built_and_indexed() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(
+    r#"
+let list = [1, "two", [3, 4]];
+let first = list[0];
+let second = list[1];
+let nested = list[2][1];
+let sum = 0;
+for let i = 0; i < length(list[2]); i = i + 1 {
+  sum = sum + list[2][i];
+}
+let empty = length([]);
+let letter = "cedar"[2];
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<f64>("first")?, 1.0);
+  assert_eq!(vm.get_global_as::<String>("second")?, "two");
+  assert_eq!(vm.get_global_as::<f64>("nested")?, 4.0);
+  assert_eq!(vm.get_global_as::<f64>("sum")?, 7.0);
+  assert_eq!(vm.get_global_as::<f64>("empty")?, 0.0);
+  assert_eq!(vm.get_global_as::<String>("letter")?, "d");
+  Ok(())
+}
+
+#[test]
+fn maps_are_indexed_by_key() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  let mut map = BTreeMap::new();
+  map.insert("name".to_string(), "cedar".to_string());
+  map.insert("kind".to_string(), "tree".to_string());
+  vm.set_global("map", map)?;
+  vm.interpret(
+    r#"
+let name = map["name"];
+let missing = map["height"] == null;
+let keys = keys(map);
+let joined = "";
+for let i = 0; i < length(keys); i = i + 1 {
+  joined = joined + keys[i] + "=" + map[keys[i]] + ";";
+}
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<String>("name")?, "cedar");
+  assert!(vm.get_global_as::<bool>("missing")?);
+  assert_eq!(
+    vm.get_global_as::<String>("joined")?,
+    "kind=tree;name=cedar;"
+  );
+  Ok(())
+}
+
+#[test]
+fn changing_a_collection_leaves_the_original_alone() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(
+    r#"
+let list = [1, 2];
+let longer = push(list, 3);
+let map = insert(new-map(), "a", 1);
+let bigger = insert(map, "b", 2);
+let values = values(bigger);
+let found = contains(longer, 3) and !contains(list, 3) and contains(bigger, "b");
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<Vec<f64>>("list")?, vec![1.0, 2.0]);
+  assert_eq!(vm.get_global_as::<Vec<f64>>("longer")?, vec![1.0, 2.0, 3.0]);
+  assert_eq!(vm.get_global_as::<BTreeMap<String, f64>>("map")?.len(), 1);
+  assert_eq!(vm.get_global_as::<Vec<f64>>("values")?, vec![1.0, 2.0]);
+  assert!(vm.get_global_as::<bool>("found")?);
+  Ok(())
+}
+
+#[test]
+fn bad_indexes_are_errors() {
+  let mut vm = VM::new();
+  let errors = [
+    (
+      "[1, 2][2];",
+      "Index 2 is out of range for a list of length 2",
+    ),
+    ("[1, 2][0.5];", "Index 0.5 is not a whole number"),
+    (
+      r#"[1, 2]["a"];"#,
+      "Can't index a list or string with a string",
+    ),
+    (
+      r#""abc"[-1];"#,
+      "Index -1 is out of range for a string of length 3",
+    ),
+    (
+      "insert(new-map(), \"a\", 1)[0];",
+      "Can't index a map with a number",
+    ),
+    ("let n = 1; n[0];", "Can't index a number"),
+  ];
+  for (script, error) in errors.iter() {
+    assert_eq!(
+      vm.interpret(script.to_string()).unwrap_err().to_string(),
+      format!("[line 1] Error in script: {}", error)
+    );
+  }
+}
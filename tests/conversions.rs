@@ -0,0 +1,123 @@
+use cedar::{
+  native::{FromValue, IntoValue},
+  value::Value,
+  CedarError, VM,
+};
+use pretty_assertions::assert_eq;
+use std::{collections::HashMap, rc::Rc};
+
+#[test]
+fn integers_are_range_checked() {
+  assert_eq!(u8::from_value(Value::Number(255.0)), Some(255));
+  assert_eq!(u8::from_value(Value::Number(256.0)), None);
+  assert_eq!(u8::from_value(Value::Number(-1.0)), None);
+  assert_eq!(i8::from_value(Value::Number(-128.0)), Some(-128));
+  assert_eq!(i32::from_value(Value::Number(1.5)), None);
+  assert_eq!(u64::from_value(Value::Number(f64::NAN)), None);
+  assert_eq!(u64::from_value(Value::Number(f64::INFINITY)), None);
+  assert_eq!(u64::from_value(Value::Number(18446744073709551616.0)), None);
+  assert_eq!(usize::from_value(Value::Number(3.0)), Some(3));
+  assert_eq!(i128::from_value(Value::Number(1e40)), None);
+  assert_eq!(i128::from_value(Value::Number(-1e40)), None);
+  assert_eq!(u128::from_value(Value::Number(1e39)), None);
+  assert_eq!(u128::from_value(Value::Number(-1.0)), None);
+  assert_eq!(
+    i128::from_value(Value::Number(-(2f64.powi(100)))),
+    Some(-(1i128 << 100))
+  );
+  assert_eq!(
+    i64::from_value(Value::Number(-9223372036854775808.0)),
+    Some(i64::MIN)
+  );
+  assert_eq!(i64::from_value(Value::Number(9223372036854775808.0)), None);
+  assert_eq!(f32::from_value(Value::Number(1.5)), Some(1.5));
+  assert_eq!(f32::from_value(Value::Number(1e39)), None);
+  assert_eq!(f32::from_value(Value::Number(-1e39)), None);
+  assert_eq!(
+    f32::from_value(Value::Number(f64::INFINITY)),
+    Some(f32::INFINITY)
+  );
+  assert_eq!(f32::expected(), "number from -3.4028235e38 to 3.4028235e38");
+  assert_eq!(7u16.to_value(), Value::Number(7.0));
+  assert_eq!(i16::expected(), "integer from -32768 to 32767");
+}
+
+#[test]
+fn collections_convert_both_ways() {
+  let list = vec![1.0, 2.0].to_value();
+  assert_eq!(
+    list,
+    Value::from(vec![Value::Number(1.0), Value::Number(2.0)])
+  );
+  assert_eq!(Vec::<u32>::from_value(list.clone()), Some(vec![1, 2]));
+  assert_eq!(Vec::<String>::from_value(list), None);
+
+  let mut map = HashMap::new();
+  map.insert("a".to_string(), Some("x"));
+  map.insert("b".to_string(), None);
+  let value = map.to_value();
+  assert_eq!(value.to_string(), r#"{"a": "x", "b": null}"#);
+  let back = HashMap::<String, Option<String>>::from_value(value).unwrap();
+  assert_eq!(back["a"], Some("x".to_string()));
+  assert_eq!(back["b"], None);
+
+  let pair = (1u8, "one").to_value();
+  assert_eq!(pair.to_string(), r#"[1, "one"]"#);
+  assert_eq!(
+    <(u8, String)>::from_value(pair.clone()),
+    Some((1, "one".to_string()))
+  );
+  assert_eq!(<(u8, String, bool)>::from_value(pair), None);
+  assert_eq!(
+    <(u8, Rc<String>)>::expected(),
+    "list of [integer from 0 to 255, string]"
+  );
+}
+
+#[test]
+fn natives_take_and_return_rust_types() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.register_fn("repeat", |s: String, n: u32| s.repeat(n as usize))?;
+  vm.register_fn("first", |l: Vec<Value>| l.into_iter().next())?;
+  vm.register_fn("greeting", || "hello")?;
+  vm.register_fn("split", |s: String| {
+    s.split(',').map(str::to_string).collect::<Vec<_>>()
+  })?;
+  vm.interpret(
+    r#"
+let r = repeat("ab", 3);
+let f = first(split("x,y"));
+let g = greeting();
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<String>("r")?, "ababab");
+  assert_eq!(vm.get_global_as::<String>("f")?, "x");
+  assert_eq!(vm.get_global_as::<String>("g")?, "hello");
+  Ok(())
+}
+
+#[test]
+fn errors_name_the_argument_and_type() {
+  let mut vm = VM::new();
+  vm.register_fn("repeat", |s: String, n: u32| s.repeat(n as usize))
+    .unwrap();
+  vm.register_fn("maybe", |n: Option<f64>| n.unwrap_or(0.0))
+    .unwrap();
+  let error = vm.interpret(r#"repeat("a", -1);"#.into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Argument 2 of native function 'repeat' should be integer from 0 to 4294967295 but got number"
+  );
+  let error = vm.interpret("maybe(true);".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Argument 1 of native function 'maybe' should be number or null but got bool"
+  );
+  vm.set_global("list", vec![true]).unwrap();
+  let error = vm.get_global_as::<Vec<f64>>("list").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[host] Error: 'list' at [0] should be number but got bool"
+  );
+}
@@ -0,0 +1,135 @@
+use cedar::{
+  native::{FromValue, IntoValue},
+  value::Value,
+  CedarError, VM,
+};
+use cedar_derive::CedarValue;
+use pretty_assertions::assert_eq;
+
+#[derive(CedarValue, Debug, Clone, PartialEq)]
+struct Server {
+  host: String,
+  port: u16,
+  #[cedar(rename = "max-connections")]
+  max_connections: Option<u32>,
+}
+
+#[derive(CedarValue, Debug, Clone, PartialEq)]
+enum Mode {
+  Fast,
+  #[cedar(rename = "slow")]
+  Slow,
+  Limited {
+    per_second: f64,
+  },
+  Custom(String, u8),
+}
+
+#[derive(CedarValue, Debug, Clone, PartialEq)]
+struct Config {
+  name: String,
+  servers: Vec<Server>,
+  mode: Mode,
+  tags: Wrapper<Vec<String>>,
+}
+
+#[derive(CedarValue, Debug, Clone, PartialEq)]
+struct Wrapper<T>(T);
+
+fn config() -> Config {
+  Config {
+    name: "prod".into(),
+    servers: vec![Server {
+      host: "localhost".into(),
+      port: 8080,
+      max_connections: None,
+    }],
+    mode: Mode::Limited { per_second: 2.5 },
+    tags: Wrapper(vec!["a".into()]),
+  }
+}
+
+#[test]
+fn structs_and_enums_round_trip() {
+  let value = config().to_value();
+  assert_eq!(
+    value.to_string(),
+    r#"{"mode": {"Limited": {"per_second": 2.5}}, "name": "prod", "servers": [{"host": "localhost", "max-connections": null, "port": 8080}], "tags": ["a"]}"#
+  );
+  assert_eq!(Config::from_value(value), Some(config()));
+
+  for mode in [
+    Mode::Fast,
+    Mode::Slow,
+    Mode::Custom("x".into(), 3),
+    Mode::Limited { per_second: 1.0 },
+  ] {
+    assert_eq!(Mode::from_value(mode.clone().to_value()), Some(mode));
+  }
+  assert_eq!(Mode::Slow.to_value(), Value::from("slow"));
+}
+
+#[test]
+fn scripts_build_values_through_natives() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.register_fn("default-server", || Server {
+    host: "example.com".into(),
+    port: 443,
+    max_connections: Some(10),
+  })?;
+  vm.register_fn("port", |server: Server| server.port)?;
+  vm.interpret("let s = default-server(); let p = port(s);".into())?;
+  assert_eq!(vm.get_global_as::<f64>("p")?, 443.0);
+  assert_eq!(vm.get_global_as::<Server>("s")?.max_connections, Some(10));
+  Ok(())
+}
+
+#[test]
+fn errors_name_the_field_path() {
+  let mut vm = VM::new();
+  let mut bad = config();
+  bad.servers.push(bad.servers[0].clone());
+  let mut value = bad.to_value();
+  if let Value::Map(map) = &mut value {
+    let map = std::rc::Rc::make_mut(map);
+    if let Some(Value::List(servers)) = map.get_mut("servers") {
+      if let Value::Map(server) = &mut std::rc::Rc::make_mut(servers)[1] {
+        std::rc::Rc::make_mut(server).insert("port".into(), Value::from("80"));
+      }
+    }
+  }
+  vm.set_global("config", value).unwrap();
+  let error = vm.get_global_as::<Config>("config").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[host] Error: 'config' at servers[1].port should be integer from 0 to 65535 but got string"
+  );
+
+  vm.set_global("mode", "medium").unwrap();
+  let error = vm.get_global_as::<Mode>("mode").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    r#"[host] Error: 'mode' should be one of "Fast", "slow", "Limited", "Custom" but got "medium""#
+  );
+
+  let mode = Mode::Custom("x".into(), 3).to_value();
+  let mut custom = std::collections::BTreeMap::new();
+  custom.insert("Custom".to_string(), ("x", 300).to_value());
+  let error = Mode::try_from_value(Value::from(custom)).unwrap_err();
+  assert_eq!(error.path(), "Custom[1]");
+  assert_eq!(error.expected, "integer from 0 to 255");
+  vm.set_global("mode", mode).unwrap();
+  let error = vm.get_global_as::<Server>("mode").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[host] Error: 'mode' at host should be string but got null"
+  );
+
+  vm.register_fn("port", |server: Server| server.port)
+    .unwrap();
+  let error = vm.interpret(r#"port(config);"#.into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Argument 1 of native function 'port' at host should be string but got null"
+  );
+}
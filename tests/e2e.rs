@@ -1,6 +1,31 @@
 use assert_cmd::Command;
 use pretty_assertions::assert_eq;
-use std::{error::Error, path::PathBuf};
+use std::{
+  env,
+  error::Error,
+  fs,
+  path::{Path, PathBuf},
+};
+
+// Tests that write files run in a directory of their own so that they can't
+// see each other's files while running in parallel
+fn scratch_dir(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+  let dir = env::temp_dir().join(name);
+  if dir.exists() {
+    fs::remove_dir_all(&dir)?;
+  }
+  fs::create_dir_all(&dir)?;
+  Ok(dir)
+}
+
+// A script in tests/cedar-scripts, which works from any directory
+fn script(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+  Ok(
+    env::current_dir()?
+      .join(Path::new("tests").join("cedar-scripts"))
+      .join(name),
+  )
+}
 
 #[test]
 fn control_flow() -> Result<(), Box<dyn Error>> {
@@ -46,15 +71,126 @@ fn functions() -> Result<(), Box<dyn Error>> {
 
 #[test]
 fn native_functions() -> Result<(), Box<dyn Error>> {
-  let path = PathBuf::from("tests")
-    .join("cedar-scripts")
-    .join("native.cdr");
+  let path = script("native.cdr")?;
+  let dir = scratch_dir("cedar-e2e-native-functions")?;
   let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd.current_dir(&dir);
+  cmd.args(["--allow-read=test-file", "--allow-write=test-file"]);
   cmd.arg(path);
   cmd.assert().success();
   let stdout = String::from_utf8(cmd.output()?.stdout)?;
   assert_eq!(stdout, NATIVE);
-  std::fs::remove_file("test-file")?;
+  fs::remove_dir_all(dir)?;
+  Ok(())
+}
+
+#[test]
+fn natives_need_permission() -> Result<(), Box<dyn Error>> {
+  let path = script("native.cdr")?;
+  let dir = scratch_dir("cedar-e2e-natives-need-permission")?;
+  let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd.current_dir(&dir);
+  cmd.arg(&path);
+  let output = cmd.output()?;
+  assert_eq!(output.status.code(), Some(70));
+  assert_eq!(
+    String::from_utf8(output.stderr)?,
+    "[line 1] Error in script: Permission denied: 'write-file' needs write access to 'test-file'\n"
+  );
+
+  let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd.current_dir(&dir);
+  cmd.args(["--allow-read", "--allow-write=target"]);
+  cmd.arg(&path);
+  let output = cmd.output()?;
+  assert_eq!(output.status.code(), Some(70));
+  assert!(!dir.join("test-file").exists());
+  fs::remove_dir_all(dir)?;
+
+  let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd.args(["--allow-everything", "script.cdr"]);
+  cmd.assert().code(64);
+
+  // An empty path would grant the whole current directory
+  for flag in ["--allow-read=", "--allow-write=a,,b", "--allow-read=a,"] {
+    let mut cmd = Command::cargo_bin("cedarc")?;
+    cmd.args([flag, "-e", "print 1;"]);
+    cmd.assert().code(64);
+  }
+  Ok(())
+}
+
+#[test]
+fn main_gets_arguments_and_returns_the_exit_code() -> Result<(), Box<dyn Error>> {
+  let path = PathBuf::from("tests")
+    .join("cedar-scripts")
+    .join("main.cdr");
+  let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd.arg("-O").arg(path).args(["one", "-O", "--allow-run"]);
+  let output = cmd.output()?;
+  assert_eq!(output.status.code(), Some(3));
+  assert_eq!(
+    String::from_utf8(output.stdout)?,
+    "top level runs first\none,-O,--allow-run\nfirst: one\nlast: --allow-run\n"
+  );
+  Ok(())
+}
+
+#[test]
+fn exit_codes_must_fit_in_a_byte() -> Result<(), Box<dyn Error>> {
+  let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd.args(["-e", "fn main() { return 256; }"]);
+  let output = cmd.output()?;
+  assert_eq!(output.status.code(), Some(70));
+  assert_eq!(
+    String::from_utf8(output.stderr)?,
+    "[host] Error: The value main returned should be integer from 0 to 255 or null but got number\n"
+  );
+
+  let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd.args(["-e", "fn main() { return 255; }"]);
+  assert_eq!(cmd.output()?.status.code(), Some(255));
+  Ok(())
+}
+
+#[test]
+fn exit_stops_the_script() -> Result<(), Box<dyn Error>> {
+  let path = PathBuf::from("tests")
+    .join("cedar-scripts")
+    .join("exit.cdr");
+  let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd.arg(path);
+  let output = cmd.output()?;
+  assert_eq!(output.status.code(), Some(4));
+  assert_eq!(String::from_utf8(output.stdout)?, "leaving\n");
+  assert_eq!(String::from_utf8(output.stderr)?, "");
+  Ok(())
+}
+
+#[test]
+fn scripts_from_stdin_and_the_command_line() -> Result<(), Box<dyn Error>> {
+  let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd
+    .arg("-")
+    .arg("first")
+    .write_stdin("print join(args(), \" \");\nprint read-line();");
+  let output = cmd.output()?;
+  assert_eq!(output.status.code(), Some(0));
+  assert_eq!(String::from_utf8(output.stdout)?, "first\nnull\n");
+
+  let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd
+    .arg("-e")
+    .arg("let lines = stdin-lines(); for let l = lines.next(); l != null; l = lines.next() { print length(l); }")
+    .arg("-e")
+    .write_stdin("cedar\nvm\n");
+  let output = cmd.output()?;
+  assert_eq!(output.status.code(), Some(0));
+  assert_eq!(String::from_utf8(output.stdout)?, "5\n2\n");
+
+  let mut cmd = Command::cargo_bin("cedarc")?;
+  cmd.arg("-e");
+  assert_eq!(cmd.output()?.status.code(), Some(64));
   Ok(())
 }
 
@@ -0,0 +1,73 @@
+use cedar::{value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+use std::borrow::Cow;
+
+const PLUGIN: &str = r#"
+let handled = 0;
+fn on-event(kind, amount) {
+  handled = handled + amount;
+  return kind + " handled";
+}
+fn broken() {
+  return 1 + true;
+}
+"#;
+
+#[test]
+fn calls_script_functions() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(PLUGIN.into())?;
+  for _ in 0..1000 {
+    let result = vm.call_function("on-event", &[Value::from("click"), Value::Number(2.0)])?;
+    assert_eq!(result, Value::from("click handled"));
+  }
+  assert_eq!(vm.get_global_as::<f64>("handled")?, 2000.0);
+  let result: Cow<'static, str> =
+    vm.call_function_as("on-event", &[Value::from("key"), Value::Number(1.0)])?;
+  assert_eq!(result, "key handled");
+  Ok(())
+}
+
+#[test]
+fn globals_can_be_read_and_written() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.set_global("limit", 10.0)?;
+  vm.interpret("let doubled = limit * 2;".into())?;
+  assert_eq!(vm.get_global("doubled"), Some(Value::Number(20.0)));
+  vm.set_global("limit", Value::from("changed"))?;
+  vm.interpret("let copy = limit;".into())?;
+  assert_eq!(vm.get_global("copy"), Some(Value::from("changed")));
+  assert_eq!(vm.get_global("missing"), None);
+  Ok(())
+}
+
+#[test]
+fn errors_leave_the_vm_usable() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(PLUGIN.into())?;
+
+  let error = vm.call_function("on-missing", &[]).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[host] Error: Undefined function 'on-missing'"
+  );
+  let error = vm.get_global_as::<bool>("handled").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[host] Error: 'handled' should be bool but got number"
+  );
+  let error = vm.call_function("on-event", &[]).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 0] Error in script: Expected 2 arguments but got 0"
+  );
+  let error = vm.call_function("broken", &[]).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 8] Error in script: Second operand is not a number"
+  );
+
+  let result = vm.call_function("on-event", &[Value::from("after"), Value::Number(1.0)])?;
+  assert_eq!(result, Value::from("after handled"));
+  Ok(())
+}
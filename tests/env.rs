@@ -0,0 +1,88 @@
+use cedar::{libstd::Module, permissions::Permissions, value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+use std::env;
+
+#[test]
+fn scripts_read_their_arguments_and_environment() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.set_args(vec!["--verbose", "input.txt"]);
+  env::set_var("CEDAR_ENV_TEST_READ", "from the host");
+  vm.interpret(
+    r#"
+let arguments = args();
+let read = env-get("CEDAR_ENV_TEST_READ");
+let missing = env-get("CEDAR_ENV_TEST_MISSING");
+env-set("CEDAR_ENV_TEST_WRITE", "from the script");
+let dir = cwd();
+let host = hostname();
+"#
+    .into(),
+  )?;
+  assert_eq!(
+    vm.get_global_as::<Vec<String>>("arguments")?,
+    vec!["--verbose", "input.txt"]
+  );
+  assert_eq!(vm.get_global_as::<String>("read")?, "from the host");
+  assert_eq!(vm.get_global("missing"), Some(Value::Null));
+  assert_eq!(
+    env::var("CEDAR_ENV_TEST_WRITE").as_deref(),
+    Ok("from the script")
+  );
+  assert_eq!(
+    vm.get_global_as::<String>("dir")?,
+    env::current_dir()?.display().to_string()
+  );
+  assert_eq!(
+    vm.get_global_as::<String>("host")?,
+    gethostname::gethostname().to_string_lossy()
+  );
+  Ok(())
+}
+
+#[test]
+fn the_environment_needs_permission() -> Result<(), CedarError> {
+  let mut vm = VM::builder()
+    .module(Module::Env)
+    .permissions(Permissions::none().allow_read_paths(vec!["src"]))
+    .build()?;
+  let error = vm.interpret(r#"env-get("HOME");"#.into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Permission denied: 'env-get' needs access to environment variables"
+  );
+  let error = vm.interpret("cwd();".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Permission denied: 'cwd' needs read access to '.'"
+  );
+  // Arguments are the script's own so reading them is always allowed
+  vm.interpret("let none = args();".into())?;
+  assert_eq!(
+    vm.get_global_as::<Vec<String>>("none")?,
+    Vec::<String>::new()
+  );
+  Ok(())
+}
+
+#[test]
+fn exit_cannot_be_caught() {
+  let mut vm = VM::new();
+  let output = vm.capture_output();
+  let result = vm.interpret(
+    r#"
+fn leave() { exit(2); }
+fn caught(message) { print "caught"; }
+print "before";
+try(leave, caught);
+print "after";
+"#
+    .into(),
+  );
+  assert!(matches!(result, Err(CedarError::Exit(2))));
+  assert_eq!(output.stdout(), "before\n");
+  let error = vm.interpret("exit(256);".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Argument 1 of native function 'exit' should be integer from 0 to 255 but got number"
+  );
+}
@@ -0,0 +1,75 @@
+use cedar::{chunk::OpCode, compiler::compile, globals::Globals, value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+
+fn compiled(source: &str) -> Result<(Vec<u8>, Vec<Value>), CedarError> {
+  let function = compile(source.into(), &mut Globals::new())?;
+  Ok((function.chunk.code, function.chunk.constants))
+}
+
+#[test]
+fn arithmetic() -> Result<(), CedarError> {
+  let (code, constants) = compiled("print 60 * 60 * 24 - (10 / 2);")?;
+  assert_eq!(constants, vec![Value::Number(86395.0)]);
+  assert_eq!(
+    code,
+    vec![
+      OpCode::Constant.into(),
+      0,
+      OpCode::Print.into(),
+      OpCode::Null.into(),
+      OpCode::Return.into()
+    ]
+  );
+  let (_, constants) = compiled("print -1;")?;
+  assert_eq!(constants, vec![Value::Number(-1.0)]);
+  Ok(())
+}
+
+#[test]
+fn strings_comparisons_and_negation() -> Result<(), CedarError> {
+  let (_, constants) = compiled(r#"print "answer: " + 40 + 2;"#)?;
+  assert_eq!(constants, vec![Value::from("answer: 402")]);
+  let (code, constants) = compiled("print !(1 < 2);")?;
+  assert!(constants.is_empty());
+  assert_eq!(code[0], OpCode::False.into());
+  Ok(())
+}
+
+#[test]
+fn only_literals_are_folded() -> Result<(), CedarError> {
+  let (code, _) = compiled("let x = 2; print x * 3 * 4;")?;
+  assert_eq!(
+    code
+      .iter()
+      .filter(|b| **b == u8::from(OpCode::Multiply))
+      .count(),
+    2
+  );
+  Ok(())
+}
+
+#[test]
+fn ill_typed_operands_still_fail_at_runtime() -> Result<(), CedarError> {
+  let (code, _) = compiled(r#"print "a" - 1;"#)?;
+  assert!(code.contains(&OpCode::Subtract.into()));
+  match VM::new().interpret(r#"print "a" - 1;"#.into()) {
+    Err(e) => assert_eq!(
+      e.to_string(),
+      "[line 1] Error in script: Operand must be a number"
+    ),
+    Ok(_) => panic!("Subtracting from a string should fail"),
+  }
+  Ok(())
+}
+
+#[test]
+fn constant_if_statements_are_pruned() -> Result<(), CedarError> {
+  let (code, constants) = compiled(r#"if true { print "yes"; } else { print "no"; }"#)?;
+  assert_eq!(constants, vec![Value::from("yes")]);
+  assert!(!code.contains(&OpCode::JumpIfFalse.into()));
+  let (_, constants) = compiled(r#"if 1 > 2 { print "yes"; } else { print "no"; }"#)?;
+  assert_eq!(constants, vec![Value::from("no")]);
+  let (code, _) = compiled(r#"if false { print "never"; }"#)?;
+  assert_eq!(code, vec![OpCode::Null.into(), OpCode::Return.into()]);
+  Ok(())
+}
@@ -0,0 +1,250 @@
+use cedar::{
+  value::Value,
+  vfs::{MemoryFs, OsFs, OverlayFs},
+  CedarError, VM,
+};
+use pretty_assertions::assert_eq;
+use std::{collections::BTreeMap, env, fs, path::Path};
+
+fn vm(fs: MemoryFs) -> VM {
+  let mut vm = VM::new();
+  vm.set_fs(fs);
+  vm
+}
+
+#[test]
+fn directories_are_created_listed_and_removed() -> Result<(), CedarError> {
+  let files = MemoryFs::new().with_file("notes/a.txt", "a");
+  let mut vm = vm(files.clone());
+  vm.interpret(
+    r#"
+mkdir("out/logs/today");
+append-file("out/logs/today/run.log", "one ");
+append-file("out/logs/today/run.log", "two");
+copy("notes/a.txt", "out/a.txt");
+rename("notes/a.txt", "out/b.txt");
+let listed = list-dir("out");
+let notes-left = exists("notes/a.txt");
+let logs-is-dir = is-dir("out/logs");
+remove("notes");
+remove-all("out/logs");
+let logs-left = exists("out/logs");
+"#
+    .into(),
+  )?;
+  assert_eq!(
+    vm.get_global_as::<Vec<String>>("listed")?,
+    vec!["a.txt", "b.txt", "logs"]
+  );
+  assert!(!vm.get_global_as::<bool>("notes-left")?);
+  assert!(vm.get_global_as::<bool>("logs-is-dir")?);
+  assert!(!vm.get_global_as::<bool>("logs-left")?);
+  assert_eq!(files.contents("out/b.txt").as_deref(), Some("a"));
+  assert_eq!(files.contents("out/a.txt").as_deref(), Some("a"));
+  Ok(())
+}
+
+#[test]
+fn failures_are_runtime_errors() {
+  let mut vm = vm(MemoryFs::new().with_file("full/file", ""));
+  for (script, message) in &[
+    (
+      r#"remove("full");"#,
+      "Could not remove 'full': Directory not empty",
+    ),
+    (
+      r#"list-dir("full/file");"#,
+      "Could not list 'full/file': Not a directory",
+    ),
+    (
+      r#"rename("full", "full/inside");"#,
+      "Could not rename 'full': Cannot move a directory inside of itself",
+    ),
+    (
+      r#"metadata("missing");"#,
+      "Could not read metadata of 'missing': No such file or directory",
+    ),
+    (
+      r#"walk("missing");"#,
+      "Could not walk 'missing': No such file or directory",
+    ),
+  ] {
+    let error = vm.interpret(script.to_string()).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      format!("[line 1] Error in script: {}", message)
+    );
+  }
+}
+
+#[test]
+fn walking_and_globbing() -> Result<(), CedarError> {
+  let mut vm = vm(
+    MemoryFs::new()
+      .with_file("src/main.cdr", "")
+      .with_file("src/lib/util.cdr", "")
+      .with_file("src/lib/notes.md", "")
+      .with_file("src/lib/deep/er.cdr", "")
+      .with_file("README.md", ""),
+  );
+  vm.interpret(
+    r#"
+let everything = walk("src");
+let scripts = glob("src/**/*.cdr");
+let top = glob("*.md");
+let one = glob("src/lib/?otes.md");
+let exact = glob("README.md");
+let none = glob("missing/*.cdr");
+"#
+    .into(),
+  )?;
+  let list = |name| vm.get_global_as::<Vec<String>>(name);
+  assert_eq!(
+    list("everything")?,
+    vec![
+      "src/lib/deep/er.cdr",
+      "src/lib/notes.md",
+      "src/lib/util.cdr",
+      "src/main.cdr"
+    ]
+  );
+  assert_eq!(
+    list("scripts")?,
+    vec!["src/lib/deep/er.cdr", "src/lib/util.cdr", "src/main.cdr"]
+  );
+  assert_eq!(list("top")?, vec!["README.md"]);
+  assert_eq!(list("one")?, vec!["src/lib/notes.md"]);
+  assert_eq!(list("exact")?, vec!["README.md"]);
+  assert_eq!(list("none")?, Vec::<String>::new());
+  Ok(())
+}
+
+#[test]
+fn scripts_can_go_through_what_they_find() -> Result<(), CedarError> {
+  let mut vm = vm(
+    MemoryFs::new()
+      .with_file("src/a.cdr", "print 1;")
+      .with_file("src/b.cdr", "print 22;")
+      .with_file("src/c.md", ""),
+  );
+  vm.interpret(
+    r#"
+let names = "";
+let listed = list-dir("src");
+for let i = 0; i < length(listed); i = i + 1 {
+  names = names + listed[i] + " ";
+}
+let size = 0;
+let scripts = glob("src/*.cdr");
+for let i = 0; i < length(scripts); i = i + 1 {
+  size = size + length(read-file(scripts[i]));
+}
+let last = walk("src")[2];
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<String>("names")?, "a.cdr b.cdr c.md ");
+  assert_eq!(vm.get_global_as::<f64>("size")?, 17.0);
+  assert_eq!(vm.get_global_as::<String>("last")?, "src/c.md");
+  Ok(())
+}
+
+#[test]
+fn globs_with_many_stars_finish_quickly() -> Result<(), CedarError> {
+  let name = "a".repeat(40);
+  let mut vm = vm(MemoryFs::new().with_file(format!("{}/{}", name, name), ""));
+  vm.interpret(
+    r#"
+let stars = glob("*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*b/*");
+let deep = glob("**/**/**/**/**/**/**/**/**/**/**/**/missing");
+let found = glob("**/*a*a*a*");
+"#
+    .into(),
+  )?;
+  let list = |name| vm.get_global_as::<Vec<String>>(name);
+  assert_eq!(list("stars")?, Vec::<String>::new());
+  assert_eq!(list("deep")?, Vec::<String>::new());
+  assert_eq!(list("found")?, vec![format!("{}/{}", name, name)]);
+  Ok(())
+}
+
+#[test]
+fn metadata_comes_from_the_os() -> Result<(), CedarError> {
+  let dir = env::temp_dir().join("cedar-fs-metadata");
+  fs::create_dir_all(&dir)?;
+  fs::write(dir.join("file"), "12345")?;
+  let mut vm = VM::new();
+  vm.set_global("file", dir.join("file").display().to_string())?;
+  vm.interpret("let info = metadata(file);".into())?;
+  let info = vm.get_global_as::<BTreeMap<String, Value>>("info")?;
+  assert_eq!(info["size"], Value::Number(5.0));
+  assert_eq!(info["is-dir"], Value::Bool(false));
+  assert!(matches!(info["modified"], Value::Number(n) if n > 0.0));
+  fs::remove_dir_all(dir)?;
+  Ok(())
+}
+
+#[test]
+fn overlays_hide_what_is_removed() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.set_fs(OverlayFs::new(OsFs));
+  vm.interpret(
+    r#"
+remove-all("src/libstd");
+rename("Cargo.toml", "Manifest.toml");
+let gone = exists("src/libstd/fs.rs") or exists("Cargo.toml");
+let moved = read-file("Manifest.toml");
+mkdir("src/libstd");
+let recreated = list-dir("src/libstd");
+"#
+    .into(),
+  )?;
+  assert!(!vm.get_global_as::<bool>("gone")?);
+  assert!(vm.get_global_as::<String>("moved")?.contains("[package]"));
+  assert_eq!(
+    vm.get_global_as::<Vec<String>>("recreated")?,
+    Vec::<String>::new()
+  );
+  assert!(Path::new("src/libstd/fs.rs").exists());
+  assert!(Path::new("Cargo.toml").exists());
+  Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn walking_never_follows_links_out_of_a_directory() -> Result<(), CedarError> {
+  use cedar::permissions::Permissions;
+  use std::os::unix::fs::symlink;
+
+  let dir = env::temp_dir().join("cedar-fs-walk-links");
+  if dir.exists() {
+    fs::remove_dir_all(&dir)?;
+  }
+  fs::create_dir_all(dir.join("allowed/nested"))?;
+  fs::create_dir_all(dir.join("secret"))?;
+  fs::write(dir.join("allowed/nested/a.txt"), "a")?;
+  fs::write(dir.join("secret/s.txt"), "s")?;
+  symlink(dir.join("secret"), dir.join("allowed/secret"))?;
+  symlink(dir.join("allowed"), dir.join("allowed/nested/loop"))?;
+  symlink(dir.join("allowed/nested/a.txt"), dir.join("allowed/b.txt"))?;
+
+  let allowed = dir.join("allowed");
+  let mut vm = VM::new();
+  vm.set_permissions(Permissions::none().allow_read_paths(vec![&allowed]));
+  vm.set_global("dir", allowed.to_string_lossy().into_owned())?;
+  vm.interpret(
+    r#"
+let walked = walk(dir);
+let globbed = glob(dir + "/**/*.txt");
+"#
+    .into(),
+  )?;
+  let expected = vec![
+    allowed.join("b.txt").to_string_lossy().into_owned(),
+    allowed.join("nested/a.txt").to_string_lossy().into_owned(),
+  ];
+  assert_eq!(vm.get_global_as::<Vec<String>>("walked")?, expected);
+  assert_eq!(vm.get_global_as::<Vec<String>>("globbed")?, expected);
+  fs::remove_dir_all(dir)?;
+  Ok(())
+}
@@ -0,0 +1,58 @@
+use cedar::{chunk::OpCode, compiler::compile, globals::Globals, value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+
+#[test]
+fn natives_are_bound_to_slots() {
+  let vm = VM::new();
+  let globals = vm.globals();
+  for name in &["read-file", "write-file"] {
+    let slot = globals.slot(name).unwrap();
+    assert_eq!(globals.name(slot), Some(*name));
+    match globals.get(slot) {
+      Some(Value::NativeFn(_)) => {}
+      other => panic!("Expected {} to be a native, got {:?}", name, other),
+    }
+  }
+}
+
+#[test]
+fn names_resolve_to_the_same_slot() -> Result<(), CedarError> {
+  let mut globals = Globals::new();
+  let function = compile("let a = 1; let b = a; a = b;".into(), &mut globals)?;
+  let a = globals.slot("a").unwrap();
+  let b = globals.slot("b").unwrap();
+  assert_eq!(globals.len(), 2);
+  let code = function.chunk.code;
+  assert_eq!(&code[2..5], &[OpCode::DefineGlobal.into(), 0, a as u8]);
+  assert_eq!(&code[5..8], &[OpCode::GetGlobal.into(), 0, a as u8]);
+  assert_eq!(&code[8..11], &[OpCode::DefineGlobal.into(), 0, b as u8]);
+  Ok(())
+}
+
+#[test]
+fn globals_persist_between_scripts() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret("let counter = 1;".into())?;
+  vm.interpret("counter = counter + 1;".into())?;
+  let slot = vm.globals().slot("counter").unwrap();
+  assert_eq!(vm.globals().get(slot), Some(&Value::Number(2.0)));
+  Ok(())
+}
+
+#[test]
+fn undefined_globals_are_named_in_errors() {
+  let mut vm = VM::new();
+  let error = vm.interpret("print missing;".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Undefined variable 'missing'"
+  );
+  let error = vm.interpret("missing = 1;".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Undefined variable 'missing'"
+  );
+  // A failed assignment doesn't define the variable
+  let slot = vm.globals().slot("missing").unwrap();
+  assert_eq!(vm.globals().get(slot), None);
+}
@@ -0,0 +1,137 @@
+use cedar::{value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+use std::collections::BTreeMap;
+
+#[test]
+fn parsing_builds_collections() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.set_global(
+    "text",
+    r#" {"name": "cedar", "tags": ["vm", null, true, -1.5e2], "nested": {"emoji": "\ud83c\udf32 \u00e9\n"}} "#,
+  )?;
+  vm.interpret("let doc = json-parse(text);".into())?;
+  let doc = vm.get_global_as::<BTreeMap<String, Value>>("doc")?;
+  assert_eq!(doc["name"], Value::from("cedar"));
+  assert_eq!(
+    doc["tags"],
+    Value::from(vec![
+      Value::from("vm"),
+      Value::Null,
+      Value::Bool(true),
+      Value::Number(-150.0)
+    ])
+  );
+  let nested = doc["nested"].clone().into_map().unwrap();
+  assert_eq!(nested["emoji"], Value::from("🌲 é\n"));
+  Ok(())
+}
+
+#[test]
+fn scripts_can_read_parsed_fields() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.set_global(
+    "text",
+    r#"{"package": {"name": "cedar", "authors": ["a", "b"]}, "version": 2}"#,
+  )?;
+  vm.interpret(
+    r#"
+let doc = json-parse(text);
+let name = doc["package"]["name"];
+let author = doc["package"]["authors"][1];
+let authors = length(doc["package"]["authors"]);
+let version = doc["version"];
+let missing = doc["package"]["license"];
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<String>("name")?, "cedar");
+  assert_eq!(vm.get_global_as::<String>("author")?, "b");
+  assert_eq!(vm.get_global_as::<f64>("authors")?, 2.0);
+  assert_eq!(vm.get_global_as::<f64>("version")?, 2.0);
+  assert_eq!(vm.get_global("missing"), Some(Value::Null));
+  Ok(())
+}
+
+#[test]
+fn stringify_sorts_keys_and_indents() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.set_global(
+    "text",
+    r#"{"b": [1, 2.5, {}], "a": "quote \" tab \t", "c": []}"#,
+  )?;
+  vm.interpret(
+    r#"
+let doc = json-parse(text);
+let compact = json-stringify(doc, null);
+let pretty = json-stringify(doc, 2);
+let round-trip = json-stringify(json-parse(pretty), 0);
+"#
+    .into(),
+  )?;
+  let compact = vm.get_global_as::<String>("compact")?;
+  assert_eq!(compact, r#"{"a":"quote \" tab \t","b":[1,2.5,{}],"c":[]}"#);
+  assert_eq!(
+    vm.get_global_as::<String>("pretty")?,
+    r#"{
+  "a": "quote \" tab \t",
+  "b": [
+    1,
+    2.5,
+    {}
+  ],
+  "c": []
+}"#
+  );
+  assert_eq!(vm.get_global_as::<String>("round-trip")?, compact);
+  Ok(())
+}
+
+#[test]
+fn errors_say_where_they_are() {
+  let mut vm = VM::new();
+  for (text, message) in &[
+    (
+      "{\"a\": 1,\n  \"b\" 2}",
+      "Invalid JSON at line 2, column 7: Expected ':' but found '2'",
+    ),
+    (
+      "[1, 2",
+      "Invalid JSON at line 1, column 6: Expected ',' or ']' after a value in an array",
+    ),
+    (
+      "\"héllo",
+      "Invalid JSON at line 1, column 7: Unterminated string",
+    ),
+    (
+      "01",
+      "Invalid JSON at line 1, column 2: Unexpected '1' after the end of the JSON",
+    ),
+    ("nul", "Invalid JSON at line 1, column 1: Expected null"),
+    (
+      "",
+      "Invalid JSON at line 1, column 1: Expected a value but the text ended",
+    ),
+  ] {
+    vm.set_global("text", *text).unwrap();
+    let error = vm.interpret("json-parse(text);".into()).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      format!("[line 1] Error in script: {}", message)
+    );
+  }
+
+  let error = vm
+    .interpret("fn f() {} json-stringify(f, 0);".into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Can't convert <fn f> to JSON because it's a function"
+  );
+  let error = vm
+    .interpret("json-stringify(1 / 0, 0);".into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Can't convert inf to JSON"
+  );
+}
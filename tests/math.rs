@@ -0,0 +1,79 @@
+use cedar::{CedarError, VM};
+use pretty_assertions::assert_eq;
+
+fn number(vm: &VM, name: &str) -> f64 {
+  vm.get_global_as(name).unwrap()
+}
+
+#[test]
+fn math_functions() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(
+    r#"
+let rounded = floor(2.7) + ceil(2.2) + round(2.5) + round(-2.5) + trunc(-2.7);
+let roots = sqrt(16) + pow(2, 10) + abs(-3);
+let logs = log(exp(2)) + log2(8) + log10(1000);
+let trig = sin(pi / 2) + cos(0) + tan(0) + atan2(1, 1) * 4 / pi;
+let inverse = asin(1) + acos(1) + atan(0);
+let bounds = min(3, -1) + max(3, -1) + clamp(15, 0, 10) + clamp(-5, 0, 10);
+let checks = is-integer(4) and !is-integer(4.5) and is-nan(sqrt(-1)) and !is-finite(1 / 0);
+let two-places = format-number(pi, 2);
+let none = format-number(e, 0);
+"#
+    .into(),
+  )?;
+  assert_eq!(number(&vm, "rounded"), 2.0 + 3.0 + 3.0 - 3.0 - 2.0);
+  assert_eq!(number(&vm, "roots"), 4.0 + 1024.0 + 3.0);
+  assert!((number(&vm, "logs") - 8.0).abs() < 1e-9);
+  assert!((number(&vm, "trig") - 3.0).abs() < 1e-9);
+  assert_eq!(number(&vm, "inverse"), std::f64::consts::FRAC_PI_2);
+  assert_eq!(number(&vm, "bounds"), -1.0 + 3.0 + 10.0);
+  assert!(vm.get_global_as::<bool>("checks")?);
+  assert_eq!(vm.get_global_as::<String>("two-places")?, "3.14");
+  assert_eq!(vm.get_global_as::<String>("none")?, "3");
+
+  let error = vm.interpret("clamp(1, 10, 0);".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Can't clamp between 10 and 0"
+  );
+  Ok(())
+}
+
+const ROLLS: &str = r#"
+seed-random(42);
+let a = random();
+let b = random();
+let roll = random-int(1, 6);
+let all-in-range = true;
+let i = 0;
+while i < 200 {
+  let n = random-int(-2, 2);
+  let f = random();
+  if n < -2 or n > 2 or !is-integer(n) or f < 0 or f >= 1 {
+    all-in-range = false;
+  }
+  i = i + 1;
+}
+"#;
+
+#[test]
+fn seeded_random_numbers_repeat() -> Result<(), CedarError> {
+  let mut first = VM::new();
+  first.interpret(ROLLS.into())?;
+  let mut second = VM::new();
+  second.interpret(ROLLS.into())?;
+  for name in &["a", "b", "roll"] {
+    assert_eq!(number(&first, name), number(&second, name));
+  }
+  assert_ne!(number(&first, "a"), number(&first, "b"));
+  assert!(first.get_global_as::<bool>("all-in-range")?);
+  assert!((1.0..=6.0).contains(&number(&first, "roll")));
+
+  let error = first.interpret("random-int(3, 1);".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Can't pick a random integer from 3 to 1"
+  );
+  Ok(())
+}
@@ -0,0 +1,141 @@
+use cedar::{value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+use std::{borrow::Cow, cell::Cell, rc::Rc};
+
+#[test]
+fn closures_capture_state() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  let count = Rc::new(Cell::new(0.0));
+  let counter = count.clone();
+  vm.register_fn("tick", move || {
+    counter.set(counter.get() + 1.0);
+    counter.get()
+  })?;
+  vm.interpret("tick(); tick(); let last = tick();".into())?;
+  assert_eq!(count.get(), 3.0);
+  assert_eq!(vm.get_global("last"), Some(Value::Number(3.0)));
+  Ok(())
+}
+
+#[test]
+fn arguments_arrive_in_order() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.register_fn("minus", |a: f64, b: f64| a - b)?;
+  vm.register_fn("join", |a: Cow<'static, str>, b: Cow<'static, str>| {
+    Cow::Owned(format!("{}/{}", a, b))
+  })?;
+  vm.interpret(r#"let d = minus(10, 3); let path = join("a", "b");"#.into())?;
+  assert_eq!(vm.get_global("d"), Some(Value::Number(7.0)));
+  assert_eq!(vm.get_global("path"), Some(Value::from("a/b")));
+  Ok(())
+}
+
+#[test]
+fn up_to_twelve_arguments() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.register_fn(
+    "sum",
+    |a: f64,
+     b: f64,
+     c: f64,
+     d: f64,
+     e: f64,
+     f: f64,
+     g: f64,
+     h: f64,
+     i: f64,
+     j: f64,
+     k: f64,
+     l: f64| a + b + c + d + e + f + g + h + i + j + k + l,
+  )?;
+  vm.interpret("let total = sum(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12);".into())?;
+  assert_eq!(vm.get_global("total"), Some(Value::Number(78.0)));
+  match vm.get_global("sum") {
+    Some(Value::NativeFn(native)) => {
+      assert_eq!(native.name, "sum");
+      assert_eq!(native.arity, 12);
+    }
+    other => panic!("Expected a native function, got {:?}", other),
+  }
+  Ok(())
+}
+
+#[test]
+fn arity_is_checked_before_calling() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  let called = Rc::new(Cell::new(false));
+  let flag = called.clone();
+  vm.register_fn("one", move |_: f64| flag.set(true))?;
+  let error = vm.interpret("one(1, 2);".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Expected 1 arguments but got 2"
+  );
+  assert!(!called.get());
+  let error = vm.interpret(r#"one("1");"#.into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Argument 1 of native function 'one' should be number but got string"
+  );
+  assert!(!called.get());
+  Ok(())
+}
+
+#[test]
+fn natives_call_back_into_scripts() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.register_native("fold", 3, |cx, args| {
+    let n: f64 = cx.arg(&args, 0)?;
+    let mut acc = args[1].clone();
+    for i in 0..n as usize {
+      acc = cx.call(&args[2], &[acc, Value::Number(i as f64)])?;
+    }
+    Ok(acc)
+  })?;
+  vm.interpret(
+    r#"
+fn add(acc, i) { return acc + i; }
+fn sum-to(n) { return fold(n, 0, add); }
+fn nested(acc, i) { return acc + sum-to(i); }
+let sum = sum-to(5);
+let total = fold(4, 0, nested);
+let text = fold(3, "", add);
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global("sum"), Some(Value::Number(10.0)));
+  assert_eq!(vm.get_global("total"), Some(Value::Number(4.0)));
+  assert_eq!(vm.get_global("text"), Some(Value::from("012")));
+  Ok(())
+}
+
+#[test]
+fn natives_raise_errors() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.register_native("apply", 2, |cx, args| cx.call(&args[0], &args[1..]))?;
+  vm.register_native("check", 1, |cx, args| {
+    if cx.arg::<bool>(&args, 0)? {
+      Ok(cx.intern("ok"))
+    } else {
+      Err(cx.error(format!("{} failed", cx.name())))
+    }
+  })?;
+  vm.interpret("fn broken(x) {\n  return x + true;\n}".into())?;
+
+  let error = vm.interpret("\n\ncheck(false);".into()).unwrap_err();
+  assert_eq!(error.to_string(), "[line 3] Error in script: check failed");
+  let error = vm.interpret("check(1);".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Argument 1 of native function 'check' should be bool but got number"
+  );
+  let error = vm.interpret("apply(broken, 1);".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 2] Error in script: Second operand is not a number"
+  );
+
+  vm.interpret("let result = apply(check, true);".into())?;
+  assert_eq!(vm.get_global("result"), Some(Value::from("ok")));
+  Ok(())
+}
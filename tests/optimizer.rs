@@ -0,0 +1,160 @@
+use assert_cmd::Command;
+use cedar::{
+  assembler::assemble,
+  chunk::OpCode,
+  compiler::compile,
+  globals::Globals,
+  optimizer::optimize,
+  value::{Function, Value},
+  verifier::verify,
+  CedarError,
+};
+use pretty_assertions::assert_eq;
+use std::{convert::TryFrom, error::Error, path::PathBuf};
+
+const COUNT: &str = "
+fn count(n) {
+  let i = 0;
+  while i < n {
+    i = i + 1;
+  }
+  return i;
+}
+print count(10);
+";
+
+const BRANCHES: &str = r#"
+let a = 1;
+let b = 2;
+if a < b {
+  if b > 1 {
+    print "yes";
+  } else {
+    print "no";
+  }
+} else {
+  print "other";
+}
+{
+  let x = 1;
+  print x + 2;
+}
+"#;
+
+fn optimized(source: &str) -> Result<Function, CedarError> {
+  let mut function = compile(source.into(), &mut Globals::new())?;
+  optimize(&mut function);
+  verify(&function)?;
+  Ok(function)
+}
+
+fn opcodes(function: &Function) -> Vec<OpCode> {
+  let code = &function.chunk.code;
+  let mut ops = Vec::new();
+  let mut offset = 0;
+  while offset < code.len() {
+    let op = OpCode::try_from(code[offset]).unwrap();
+    ops.push(op);
+    offset += 1 + op.operand_len();
+  }
+  ops
+}
+
+fn inner(function: &Function) -> &Function {
+  function
+    .chunk
+    .constants
+    .iter()
+    .find_map(|c| match c {
+      Value::Function(f) => Some(&**f),
+      _ => None,
+    })
+    .unwrap()
+}
+
+#[test]
+fn loops_use_superinstructions() -> Result<(), CedarError> {
+  let main = optimized(COUNT)?;
+  assert_eq!(
+    opcodes(inner(&main)),
+    vec![
+      OpCode::Constant,
+      OpCode::GetLocal,
+      OpCode::GetLocal,
+      OpCode::CompareJumpIfFalse,
+      OpCode::IncrementLocal,
+      OpCode::Loop,
+      OpCode::GetLocal,
+      OpCode::Return,
+      OpCode::Null,
+      OpCode::Return,
+    ]
+  );
+  Ok(())
+}
+
+#[test]
+fn branches_and_add_constant() -> Result<(), CedarError> {
+  let main = optimized(BRANCHES)?;
+  let ops = opcodes(&main);
+  assert!(ops.contains(&OpCode::AddConstant));
+  assert!(!ops.contains(&OpCode::JumpIfFalse));
+  assert!(!ops.contains(&OpCode::Add));
+  Ok(())
+}
+
+#[test]
+fn jumps_are_threaded() -> Result<(), CedarError> {
+  let main = optimized(BRANCHES)?;
+  let chunk = &main.chunk;
+  let mut offset = 0;
+  while offset < chunk.code.len() {
+    let op = OpCode::try_from(chunk.code[offset]).unwrap();
+    if let Some(target) = chunk.jump_target(offset) {
+      let landing = OpCode::try_from(chunk.code[target]).unwrap();
+      assert!(landing != OpCode::Jump && landing != OpCode::Loop);
+    }
+    offset += 1 + op.operand_len();
+  }
+  Ok(())
+}
+
+#[test]
+fn line_table_matches_code() -> Result<(), CedarError> {
+  let main = optimized(COUNT)?;
+  let count = inner(&main);
+  assert_eq!(count.chunk.lines.len(), count.chunk.code.len());
+  // The increment keeps the line of the statement it came from
+  assert_eq!(&count.chunk.lines[10..13], &[5, 5, 5]);
+  Ok(())
+}
+
+#[test]
+fn optimized_listings_round_trip() -> Result<(), CedarError> {
+  for source in &[COUNT, BRANCHES] {
+    let main = optimized(source)?;
+    let listing = main.chunk.disassemble("MAIN");
+    let chunk = assemble(&listing)?;
+    assert_eq!(chunk.code, main.chunk.code);
+    assert_eq!(chunk.lines, main.chunk.lines);
+  }
+  Ok(())
+}
+
+#[test]
+fn scripts_behave_the_same() -> Result<(), Box<dyn Error>> {
+  for script in &["control-flow.cdr", "functions.cdr", "scopes.cdr"] {
+    let path = PathBuf::from("tests").join("cedar-scripts").join(script);
+    let plain = Command::cargo_bin("cedarc")?.arg(&path).output()?;
+    let optimized = Command::cargo_bin("cedarc")?
+      .arg("-O")
+      .arg(&path)
+      .output()?;
+    assert!(optimized.status.success());
+    assert_eq!(
+      String::from_utf8(optimized.stdout)?,
+      String::from_utf8(plain.stdout)?
+    );
+  }
+  Ok(())
+}
@@ -0,0 +1,74 @@
+use cedar::{output::Buffer, value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+
+#[test]
+fn prints_are_captured() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  let output = vm.capture_output();
+  vm.interpret(
+    r#"
+let i = 0;
+while i < 3 {
+  print i;
+  i = i + 1;
+}
+print "done";
+"#
+    .into(),
+  )?;
+  assert_eq!(output.stdout(), "0\n1\n2\ndone\n");
+  assert_eq!(output.stderr(), "");
+  assert_eq!(output.stdout.take(), "0\n1\n2\ndone\n");
+  vm.interpret("print true;".into())?;
+  assert_eq!(output.stdout(), "true\n");
+  Ok(())
+}
+
+#[test]
+fn compile_errors_go_to_stderr() {
+  let mut vm = VM::new();
+  let output = vm.capture_output();
+  vm.interpret("let = 1;\nprint ;".into()).unwrap_err();
+  assert_eq!(
+    output.stderr.take(),
+    "[line 1] Error at '=': Expect variable name.\n[line 2] Error at ';': Expected expression\n"
+  );
+  let error = vm.interpret("print 1 @ 2;".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "Error: could not compile program due to invalid input while scanning"
+  );
+  assert!(output
+    .stderr()
+    .starts_with("[line 1] Error: Unexpected character"));
+  assert_eq!(output.stdout(), "");
+}
+
+#[test]
+fn output_before_a_runtime_error_is_kept() {
+  let mut vm = VM::new();
+  let output = vm.capture_output();
+  let error = vm
+    .interpret("print \"before\";\nprint 1 + true;".into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 2] Error in script: Second operand is not a number"
+  );
+  assert_eq!(output.stdout(), "before\n");
+}
+
+#[test]
+fn natives_write_to_the_same_sink() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  let stdout = Buffer::new();
+  vm.set_stdout(stdout.clone());
+  vm.register_native("shout", 1, |cx, args| {
+    let text: String = cx.arg(&args, 0)?;
+    writeln!(cx.stdout(), "{}!", text.to_uppercase())?;
+    Ok(Value::Null)
+  })?;
+  vm.interpret(r#"print "a"; shout("b"); print "c";"#.into())?;
+  assert_eq!(stdout.contents(), "a\nB!\nc\n");
+  Ok(())
+}
@@ -0,0 +1,101 @@
+use cedar::{libstd::Module, permissions::Permissions, vfs::MemoryFs, CedarError, VM};
+use pretty_assertions::assert_eq;
+use std::{env, fs};
+
+fn vm(permissions: Permissions) -> Result<VM, CedarError> {
+  VM::builder()
+    .module(Module::Io)
+    .permissions(permissions)
+    .build()
+}
+
+#[test]
+fn nothing_is_allowed_by_default() -> Result<(), CedarError> {
+  let mut vm = vm(Permissions::default())?;
+  let error = vm
+    .interpret(r#"let toml = read-file("Cargo.toml");"#.into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Permission denied: 'read-file' needs read access to 'Cargo.toml'"
+  );
+  Ok(())
+}
+
+#[test]
+fn paths_are_allowed_with_what_is_inside_them() -> Result<(), CedarError> {
+  let dir = env::temp_dir().join("cedar-permissions");
+  fs::create_dir_all(dir.join("out"))?;
+  let mut vm = vm(
+    Permissions::none()
+      .allow_read_paths(vec!["src"])
+      .allow_write_paths(vec![dir.join("out")]),
+  )?;
+  vm.interpret(r#"let lib = read-file("src/../src/lib.rs");"#.into())?;
+  assert!(vm.get_global_as::<String>("lib")?.contains("pub mod vm;"));
+
+  let error = vm
+    .interpret(r#"read-file("src/../Cargo.toml");"#.into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Permission denied: 'read-file' needs read access to 'src/../Cargo.toml'"
+  );
+
+  let out = dir.join("out").join("file");
+  vm.set_global("out", out.display().to_string())?;
+  vm.set_global("outside", dir.join("file").display().to_string())?;
+  vm.interpret(r#"write-file(out, "written");"#.into())?;
+  assert_eq!(fs::read_to_string(&out)?, "written");
+  assert!(vm
+    .interpret(r#"write-file(outside, "written");"#.into())
+    .is_err());
+  assert!(!dir.join("file").exists());
+
+  fs::remove_dir_all(dir)?;
+  Ok(())
+}
+
+#[test]
+fn paths_are_checked_against_the_vms_filesystem() -> Result<(), CedarError> {
+  let files = MemoryFs::new()
+    .with_file("data/in.txt", "input")
+    .with_file("secret.txt", "hidden")
+    .with_dir("data/out");
+  let mut vm = VM::builder()
+    .module(Module::Io)
+    .fs(files.clone())
+    .permissions(
+      Permissions::none()
+        .allow_read_paths(vec!["/data"])
+        .allow_write_paths(vec!["data/out"]),
+    )
+    .build()?;
+  vm.interpret(
+    r#"
+let input = read-file("data/in.txt");
+write-file("/data/out/copy.txt", input);
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<String>("input")?, "input");
+  assert_eq!(
+    files.contents("data/out/copy.txt").as_deref(),
+    Some("input")
+  );
+
+  for script in [
+    r#"read-file("data/../secret.txt");"#,
+    r#"write-file("data/out/../in.txt", "changed");"#,
+  ] {
+    assert!(vm.interpret(script.into()).is_err());
+  }
+  assert_eq!(files.contents("data/in.txt").as_deref(), Some("input"));
+  Ok(())
+}
+
+#[test]
+fn new_vms_allow_everything() {
+  assert_eq!(VM::new().permissions(), &Permissions::all());
+  assert_eq!(VM::sandboxed().permissions(), &Permissions::none());
+}
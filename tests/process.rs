@@ -0,0 +1,120 @@
+use cedar::{libstd::Module, CedarError, VM};
+use pretty_assertions::assert_eq;
+use std::env;
+
+#[test]
+fn output_is_captured() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(
+    r#"
+let out = command("sh").arg("-c").arg("echo out; echo err >&2").run();
+let stdout = out.stdout();
+let stderr = out.stderr();
+let status = out.status();
+let success = out.success();
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<String>("stdout")?, "out\n");
+  assert_eq!(vm.get_global_as::<String>("stderr")?, "err\n");
+  assert_eq!(vm.get_global_as::<f64>("status")?, 0.0);
+  assert!(vm.get_global_as::<bool>("success")?);
+  Ok(())
+}
+
+#[test]
+fn commands_take_a_directory_env_and_stdin() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(
+    r#"
+let out = command("sh")
+  .args(split("-c|cat; echo $GREETING; pwd", "|"))
+  .env("GREETING", "hello")
+  .cwd("src")
+  .stdin("piped in, ")
+  .run()
+  .stdout();
+"#
+    .into(),
+  )?;
+  let src = env::current_dir()?.join("src").canonicalize()?;
+  assert_eq!(
+    vm.get_global_as::<String>("out")?,
+    format!("piped in, hello\n{}\n", src.display())
+  );
+  Ok(())
+}
+
+#[test]
+fn failures_can_be_caught() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(
+    r#"
+fn fail() {
+  return command("sh").arg("-c").arg("echo oops >&2; exit 3").run();
+}
+fn caught(message) {
+  return "caught: " + message;
+}
+let handled = try(fail, caught);
+let unchecked = command("sh").arg("-c").arg("exit 3").output();
+let status = unchecked.status();
+let success = unchecked.success();
+fn fine() { return "fine"; }
+let untouched = try(fine, caught);
+fn broken() { return missing; }
+let script-error = try(broken, caught);
+"#
+    .into(),
+  )?;
+  assert_eq!(
+    vm.get_global_as::<String>("handled")?,
+    "caught: Command 'sh' exited with status 3: oops"
+  );
+  assert_eq!(vm.get_global_as::<f64>("status")?, 3.0);
+  assert!(!vm.get_global_as::<bool>("success")?);
+  assert_eq!(vm.get_global_as::<String>("untouched")?, "fine");
+  assert_eq!(
+    vm.get_global_as::<String>("script-error")?,
+    "caught: Undefined variable 'missing'"
+  );
+
+  let error = vm
+    .interpret(r#"command("no-such-program-for-cedar").run();"#.into())
+    .unwrap_err();
+  assert!(error
+    .to_string()
+    .starts_with("[line 1] Error in script: Could not run 'no-such-program-for-cedar': "));
+  Ok(())
+}
+
+#[test]
+fn streamed_output_goes_through_the_vm() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  let output = vm.capture_output();
+  vm.interpret(
+    r#"
+print "before";
+let out = command("sh").arg("-c").arg("echo streamed").stream().run();
+print "after";
+let kept = out.stdout();
+"#
+    .into(),
+  )?;
+  assert_eq!(output.stdout(), "before\nstreamed\nafter\n");
+  assert_eq!(vm.get_global_as::<String>("kept")?, "streamed\n");
+  Ok(())
+}
+
+#[test]
+fn running_needs_permission() -> Result<(), CedarError> {
+  let mut vm = VM::builder().modules(Module::ALL).build()?;
+  let error = vm
+    .interpret(r#"command("true").run();"#.into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Permission denied: 'Command.run' needs permission to run processes"
+  );
+  Ok(())
+}
@@ -0,0 +1,112 @@
+use cedar::{serde_value::from_value, value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Config {
+  name: String,
+  port: u16,
+  ratio: f32,
+  retries: Option<u8>,
+  hosts: Vec<String>,
+  mode: Mode,
+  limits: Limits,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Limits(u32, i64);
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum Mode {
+  Fast,
+  RateLimited { per_second: u32 },
+}
+
+fn config_value() -> Value {
+  let mut mode = BTreeMap::new();
+  let mut limited = BTreeMap::new();
+  limited.insert("per_second".to_string(), Value::Number(20.0));
+  mode.insert("rate-limited".to_string(), Value::from(limited));
+  let mut config = BTreeMap::new();
+  config.insert("name".to_string(), Value::from("prod"));
+  config.insert("port".to_string(), Value::Number(8080.0));
+  config.insert("ratio".to_string(), Value::Number(0.5));
+  config.insert("retries".to_string(), Value::Null);
+  config.insert(
+    "hosts".to_string(),
+    Value::from(vec![Value::from("a"), Value::from("b")]),
+  );
+  config.insert("mode".to_string(), Value::from(mode));
+  config.insert(
+    "limits".to_string(),
+    Value::from(vec![Value::Number(10.0), Value::Number(-3.0)]),
+  );
+  Value::from(config)
+}
+
+#[test]
+fn deserializes_typed_configs() -> Result<(), CedarError> {
+  let config: Config = from_value(config_value()).unwrap();
+  assert_eq!(
+    config,
+    Config {
+      name: "prod".into(),
+      port: 8080,
+      ratio: 0.5,
+      retries: None,
+      hosts: vec!["a".into(), "b".into()],
+      mode: Mode::RateLimited { per_second: 20 },
+      limits: Limits(10, -3),
+    }
+  );
+
+  let mut vm = VM::new();
+  vm.interpret(r#"let mode = "fast"; let port = 70000; let half = 1.5;"#.into())?;
+  assert_eq!(vm.deserialize_global::<Mode>("mode")?, Mode::Fast);
+  assert_eq!(vm.deserialize_global::<u32>("port")?, 70000);
+  assert_eq!(vm.deserialize_global::<f64>("half")?, 1.5);
+  Ok(())
+}
+
+#[test]
+fn numbers_only_become_integers_that_fit() {
+  let mut vm = VM::new();
+  vm.interpret(r#"let port = 70000; let half = 1.5; fn f() {}"#.into())
+    .unwrap();
+  let error = vm.deserialize_global::<u16>("port").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[host] Error: 'port' could not be deserialized: invalid value: integer `70000`, expected u16"
+  );
+  let error = vm.deserialize_global::<i32>("half").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[host] Error: 'half' could not be deserialized: invalid type: floating point `1.5`, expected i32"
+  );
+  let error = vm.deserialize_global::<String>("f").unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[host] Error: 'f' could not be deserialized: <fn f> can't be converted with serde because it's a function"
+  );
+}
+
+#[test]
+fn values_serialize() {
+  let json = serde_json::to_string(&config_value()).unwrap();
+  assert_eq!(
+    json,
+    r#"{"hosts":["a","b"],"limits":[10,-3],"mode":{"rate-limited":{"per_second":20}},"name":"prod","port":8080,"ratio":0.5,"retries":null}"#
+  );
+  let vm = VM::new();
+  let list = Value::from(vec![
+    Value::Number(1.0),
+    vm.get_global("read-file").unwrap(),
+  ]);
+  let error = serde_json::to_string(&list).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "<native read-file> can't be converted with serde because it's a native function"
+  );
+}
@@ -0,0 +1,68 @@
+use cedar::{libstd::Module, CedarError, VM};
+use pretty_assertions::assert_eq;
+
+#[test]
+fn read_line_strips_line_endings_and_gives_null_at_the_end() -> Result<(), CedarError> {
+  let mut vm = VM::builder()
+    .module(Module::Io)
+    .stdin("first\r\nsecond\n\nlast".as_bytes())
+    .build()?;
+  let output = vm.capture_output();
+  vm.interpret(
+    r#"
+print read-line();
+print read-line();
+print read-line() == "";
+print read-line();
+print read-line();
+"#
+    .into(),
+  )?;
+  assert_eq!(output.stdout(), "first\nsecond\ntrue\nlast\nnull\n");
+  Ok(())
+}
+
+#[test]
+fn read_stdin_reads_whatever_is_left() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.set_stdin("header\nrow 1\nrow 2\n".as_bytes());
+  vm.interpret(
+    "let header = read-line(); let rest = read-stdin(); let empty = read-stdin();".into(),
+  )?;
+  assert_eq!(vm.get_global_as::<String>("header")?, "header");
+  assert_eq!(vm.get_global_as::<String>("rest")?, "row 1\nrow 2\n");
+  assert_eq!(vm.get_global_as::<String>("empty")?, "");
+  Ok(())
+}
+
+#[test]
+fn stdin_lines_are_read_as_they_are_asked_for() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.set_stdin("one\ntwo\nthree\n".as_bytes());
+  let output = vm.capture_output();
+  vm.interpret(
+    r#"
+let lines = stdin-lines();
+print lines.next();
+print read-line();
+for let line = lines.next(); line != null; line = lines.next() {
+  print upper(line);
+}
+print lines.next();
+"#
+    .into(),
+  )?;
+  assert_eq!(output.stdout(), "one\ntwo\nTHREE\nnull\n");
+  Ok(())
+}
+
+#[test]
+fn stdin_must_be_utf8() {
+  let mut vm = VM::new();
+  vm.set_stdin(&[0xff, 0xfe, b'\n'][..]);
+  let error = vm.interpret("read-line();".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Could not read stdin: stream did not contain valid UTF-8"
+  );
+}
@@ -0,0 +1,237 @@
+use cedar::{interner::Interner, value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+use std::rc::Rc;
+
+fn global(vm: &VM, name: &str) -> Value {
+  let globals = vm.globals();
+  globals.get(globals.slot(name).unwrap()).unwrap().clone()
+}
+
+fn string(value: Value) -> Rc<String> {
+  match value {
+    Value::String(s) => s,
+    other => panic!("Expected a string, got {:?}", other),
+  }
+}
+
+#[test]
+fn interner_shares_storage() {
+  let mut strings = Interner::new();
+  let a = strings.intern("cedar");
+  let b = strings.intern(&String::from("cedar"));
+  let c = strings.intern("tree");
+  assert!(Rc::ptr_eq(&a, &b));
+  assert!(!Rc::ptr_eq(&a, &c));
+  assert_eq!(strings.len(), 2);
+}
+
+#[test]
+fn literals_are_interned_across_scripts() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(r#"let a = "hello"; fn f() { return "hello"; }"#.into())?;
+  vm.interpret(r#"let b = "hello"; let c = f();"#.into())?;
+  let a = string(global(&vm, "a"));
+  assert!(Rc::ptr_eq(&a, &string(global(&vm, "b"))));
+  assert!(Rc::ptr_eq(&a, &string(global(&vm, "c"))));
+  Ok(())
+}
+
+#[test]
+fn appending_does_not_change_other_references() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(
+    r#"
+let a = "x";
+let b = a;
+a = a + "y";
+fn local() {
+  let s = "p";
+  let t = s;
+  s = s + "q";
+  return t + s;
+}
+let c = local();
+"#
+    .into(),
+  )?;
+  assert_eq!(global(&vm, "a"), Value::from("xy"));
+  assert_eq!(global(&vm, "b"), Value::from("x"));
+  assert_eq!(global(&vm, "c"), Value::from("ppq"));
+  Ok(())
+}
+
+#[test]
+fn building_strings_in_a_loop() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(
+    r#"
+let global = "";
+let i = 0;
+while i < 20000 {
+  global = global + "ab";
+  i = i + 1;
+}
+fn build(n) {
+  let s = "";
+  let i = 0;
+  while i < n {
+    s = s + "ab";
+    i = i + 1;
+  }
+  return s;
+}
+let local = build(20000);
+"#
+    .into(),
+  )?;
+  assert_eq!(string(global(&vm, "global")).len(), 40000);
+  assert_eq!(global(&vm, "local"), global(&vm, "global"));
+  Ok(())
+}
+
+#[test]
+fn failed_appends_keep_the_variable() {
+  let mut vm = VM::new();
+  vm.interpret(r#"let s = "keep"; fn f() {}"#.into()).unwrap();
+  let error = vm.interpret("s = s + f;".into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Addition operator can only be used with 2 number values or a String and another value"
+  );
+  assert_eq!(global(&vm, "s"), Value::from("keep"));
+}
+
+#[test]
+fn string_library_counts_characters() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(
+    r#"
+let word = "naïve café";
+let len = length(word);
+let middle = substring(word, 2, 7);
+let at = find(word, "café");
+let missing = find(word, "tea");
+let letters = chars("añb");
+let shout = upper("straße");
+let padded = pad-start("7", 3, "0");
+let ragged = pad-end("ab", 7, "xyz");
+let fits = pad-start("long", 2, " ");
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<f64>("len")?, 10.0);
+  assert_eq!(vm.get_global_as::<String>("middle")?, "ïve c");
+  assert_eq!(vm.get_global_as::<f64>("at")?, 6.0);
+  assert_eq!(global(&vm, "missing"), Value::Null);
+  assert_eq!(
+    vm.get_global_as::<Vec<String>>("letters")?,
+    vec!["a", "ñ", "b"]
+  );
+  assert_eq!(vm.get_global_as::<String>("shout")?, "STRASSE");
+  assert_eq!(vm.get_global_as::<String>("padded")?, "007");
+  assert_eq!(vm.get_global_as::<String>("ragged")?, "abxyzxy");
+  assert_eq!(vm.get_global_as::<String>("fits")?, "long");
+  Ok(())
+}
+
+#[test]
+fn string_library_splits_and_searches() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(
+    r#"
+let parts = split("a,b,,c", ",");
+let joined = join(split(" x y ", " "), "-");
+let trimmed = trim("  both  ") + "|" + trim-start("  start") + "|" + trim-end("end  ");
+let replaced = replace("one two one", "one", "1");
+let checks = starts-with("cedar", "ced") and ends-with("cedar", "dar") and !starts-with("cedar", "dar");
+let small = lower("ÀB");
+let twice = repeat("ab", 2);
+let number = parse-number(" -12.5e1 ");
+let not-number = parse-number("12px");
+let infinite = parse-number("inf");
+"#
+    .into(),
+  )?;
+  assert_eq!(
+    vm.get_global_as::<Vec<String>>("parts")?,
+    vec!["a", "b", "", "c"]
+  );
+  assert_eq!(vm.get_global_as::<String>("joined")?, "-x-y-");
+  assert_eq!(vm.get_global_as::<String>("trimmed")?, "both|start|end");
+  assert_eq!(vm.get_global_as::<String>("replaced")?, "1 two 1");
+  assert!(vm.get_global_as::<bool>("checks")?);
+  assert_eq!(vm.get_global_as::<String>("small")?, "àb");
+  assert_eq!(vm.get_global_as::<String>("twice")?, "abab");
+  assert_eq!(vm.get_global_as::<f64>("number")?, -125.0);
+  assert_eq!(global(&vm, "not-number"), Value::Null);
+  assert_eq!(global(&vm, "infinite"), Value::Null);
+  Ok(())
+}
+
+#[test]
+fn string_library_errors() {
+  let mut vm = VM::new();
+  for (script, message) in &[
+    (
+      r#"substring("héllo", 2, 9);"#,
+      "Index 9 is out of range for a string of length 5",
+    ),
+    (
+      r#"substring("héllo", 3, 1);"#,
+      "Start index 3 is after end index 1",
+    ),
+    (
+      r#"substring("héllo", 1.5, 2);"#,
+      "Argument 2 of native function 'substring' should be integer from 0 to 18446744073709551615 but got number",
+    ),
+    (r#"length(12);"#, "Can't get the length of a number"),
+    (
+      r#"join(split("a b", " "), 1);"#,
+      "Argument 2 of native function 'join' should be string but got number",
+    ),
+  ] {
+    let error = vm.interpret(script.to_string()).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      format!("[line 1] Error in script: {}", message)
+    );
+  }
+}
+
+#[test]
+fn huge_strings_are_errors_rather_than_crashes() {
+  let mut vm = VM::new();
+  for script in [
+    r#"repeat("ab", 10000000000000000000);"#,
+    r#"repeat("ab", 1000000000000000);"#,
+    r#"pad-start("x", 1000000000000000, "-");"#,
+    r#"pad-end("x", 4294967296, "é");"#,
+  ] {
+    let e = vm.interpret(script.into()).unwrap_err();
+    assert_eq!(
+      e.to_string(),
+      "[line 1] Error in script: Can't make a string longer than 268435456 bytes"
+    );
+  }
+}
+
+#[test]
+fn every_way_of_building_a_string_is_capped() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(r#"let s = repeat("x", 150000000);"#.into())?;
+  for script in [
+    "s = s + s;",
+    r#"join([s, s], "");"#,
+    r#"replace(s, "x", "xx");"#,
+  ] {
+    let e = vm.interpret(script.into()).unwrap_err();
+    assert_eq!(
+      e.to_string(),
+      "[line 1] Error in script: Can't make a string longer than 268435456 bytes"
+    );
+  }
+  // Failing to append leaves the variable as it was
+  vm.interpret(r#"let s = s + "";"#.into())?;
+  assert_eq!(vm.get_global_as::<String>("s")?.len(), 150000000);
+  Ok(())
+}
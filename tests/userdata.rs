@@ -0,0 +1,138 @@
+use cedar::{
+  native::FromValue,
+  userdata::{UserRef, UserType},
+  value::Value,
+  CedarError, VM,
+};
+use pretty_assertions::assert_eq;
+use std::{cell::Cell, rc::Rc};
+
+struct Connection {
+  queries: Vec<String>,
+  closed: Rc<Cell<bool>>,
+}
+
+impl Drop for Connection {
+  fn drop(&mut self) {
+    self.closed.set(true);
+  }
+}
+
+struct Wrapper<T>(T);
+
+fn connection_type() -> UserType {
+  UserType::new::<Connection>("Connection")
+    .method("query", |conn: UserRef<Connection>, sql: String| {
+      conn.borrow_mut().queries.push(sql.clone());
+      vec![sql.to_uppercase()]
+    })
+    .method("count", |conn: UserRef<Connection>| {
+      conn.borrow().queries.len() as u32
+    })
+    .native_method("each-query", 2, |cx, args| {
+      let conn: UserRef<Connection> = cx.arg(&args, 0)?;
+      let queries = conn.borrow().queries.clone();
+      for query in queries {
+        cx.call(&args[1], &[Value::from(query)])?;
+      }
+      Ok(Value::Null)
+    })
+}
+
+fn connect(vm: &mut VM) -> Result<Rc<Cell<bool>>, CedarError> {
+  let closed = Rc::new(Cell::new(false));
+  vm.register_type(connection_type());
+  let conn = vm.userdata(Connection {
+    queries: Vec::new(),
+    closed: closed.clone(),
+  })?;
+  vm.set_global("conn", conn)?;
+  Ok(closed)
+}
+
+#[test]
+fn scripts_call_methods() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  connect(&mut vm)?;
+  vm.interpret(
+    r#"
+let rows = conn.query("select 1");
+conn.query("select " + "2");
+let seen = "";
+fn record(query) { seen = seen + query + ";"; }
+conn.each-query(record);
+let count = conn.count();
+let same = conn == conn;
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<Vec<String>>("rows")?, vec!["SELECT 1"]);
+  assert_eq!(vm.get_global_as::<String>("seen")?, "select 1;select 2;");
+  assert_eq!(vm.get_global_as::<f64>("count")?, 2.0);
+  assert_eq!(vm.get_global_as::<bool>("same")?, true);
+  match vm.get_global("conn") {
+    Some(value) => assert_eq!(value.to_string(), "<Connection>"),
+    None => panic!("conn should be defined"),
+  }
+  let conn: UserRef<Connection> = vm.get_global_as("conn")?;
+  assert_eq!(conn.borrow().queries, vec!["select 1", "select 2"]);
+  Ok(())
+}
+
+#[test]
+fn method_errors() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  connect(&mut vm)?;
+  let cases = [
+    (
+      "conn.missing();",
+      "[line 1] Error in script: Undefined method 'missing' on Connection",
+    ),
+    (
+      "conn.count(1);",
+      "[line 1] Error in script: Expected 0 arguments but got 1",
+    ),
+    (
+      "let n = 1; n.count();",
+      "[line 1] Error in script: Can only call methods on userdata, not number",
+    ),
+    (
+      "conn.query(1);",
+      "[line 1] Error in script: Argument 2 of native function 'Connection.query' should be string but got number",
+    ),
+  ];
+  for (source, message) in cases.iter() {
+    let error = vm.interpret(source.to_string()).unwrap_err();
+    assert_eq!(error.to_string(), *message);
+  }
+  let error = vm.get_global_as::<UserRef<String>>("conn").err().unwrap();
+  assert_eq!(
+    error.to_string(),
+    "[host] Error: 'conn' should be String but got userdata"
+  );
+  assert_eq!(
+    UserRef::<Wrapper<Connection>>::expected(),
+    "Wrapper<Connection>"
+  );
+  assert_eq!(
+    UserRef::<Wrapper<(Vec<String>, Rc<[u8]>)>>::expected(),
+    "Wrapper<(Vec<String>, Rc<[u8]>)>"
+  );
+  let error = vm.userdata(5u8).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[host] Error: u8 has not been registered as a type"
+  );
+  Ok(())
+}
+
+#[test]
+fn userdata_is_dropped_with_its_last_reference() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  let closed = connect(&mut vm)?;
+  vm.interpret("let other = conn; conn = null;".into())?;
+  assert!(!closed.get());
+  vm.interpret("other = null;".into())?;
+  assert!(closed.get());
+  Ok(())
+}
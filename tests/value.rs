@@ -0,0 +1,39 @@
+use cedar::{value::Value, CedarError, VM};
+use pretty_assertions::assert_eq;
+use std::mem;
+
+#[test]
+fn values_are_two_words() {
+  assert_eq!(mem::size_of::<Value>(), 16);
+}
+
+#[test]
+fn concatenation_does_not_change_shared_strings() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret(r#"let a = "left"; let b = a + " right";"#.into())?;
+  let globals = vm.globals();
+  assert_eq!(
+    globals.get(globals.slot("a").unwrap()),
+    Some(&Value::from("left"))
+  );
+  assert_eq!(
+    globals.get(globals.slot("b").unwrap()),
+    Some(&Value::from("left right"))
+  );
+  Ok(())
+}
+
+#[test]
+fn functions_share_their_chunk() -> Result<(), CedarError> {
+  let mut vm = VM::new();
+  vm.interpret("fn f() { return 1; } let g = f;".into())?;
+  let globals = vm.globals();
+  match (
+    globals.get(globals.slot("f").unwrap()),
+    globals.get(globals.slot("g").unwrap()),
+  ) {
+    (Some(Value::Function(f)), Some(Value::Function(g))) => assert!(std::rc::Rc::ptr_eq(f, g)),
+    other => panic!("Expected two functions, got {:?}", other),
+  }
+  Ok(())
+}
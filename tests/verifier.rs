@@ -0,0 +1,171 @@
+use cedar::{
+  chunk::{Chunk, OpCode},
+  compiler::compile,
+  globals::Globals,
+  value::{Function, Value},
+  verifier::{verify, VerifierErrorKind},
+  CedarError, VM,
+};
+use pretty_assertions::assert_eq;
+use std::{fs, path::PathBuf};
+
+fn script(code: Vec<u8>, constants: Vec<Value>) -> Function {
+  let mut function = Function::new();
+  function.chunk = Chunk {
+    lines: vec![1; code.len()],
+    code,
+    constants,
+  };
+  function
+}
+
+fn kind(function: &Function) -> VerifierErrorKind {
+  verify(function).unwrap_err().kind
+}
+
+#[test]
+fn compiled_scripts_verify() -> Result<(), CedarError> {
+  for script in &["control-flow", "functions", "native", "scopes"] {
+    let path = PathBuf::from("tests")
+      .join("cedar-scripts")
+      .join(format!("{}.cdr", script));
+    verify(&compile(fs::read_to_string(path)?, &mut Globals::new())?)?;
+  }
+  Ok(())
+}
+
+#[test]
+fn invalid_opcode() {
+  let function = script(vec![OpCode::Null.into(), 200], vec![]);
+  assert_eq!(kind(&function), VerifierErrorKind::InvalidOpCode(200));
+}
+
+#[test]
+fn truncated_operand() {
+  let function = script(vec![OpCode::Null.into(), OpCode::Constant.into()], vec![]);
+  assert_eq!(
+    kind(&function),
+    VerifierErrorKind::TruncatedOperand(OpCode::Constant)
+  );
+}
+
+#[test]
+fn constant_out_of_range() {
+  let function = script(
+    vec![OpCode::Constant.into(), 3, OpCode::Return.into()],
+    vec![Value::Number(1.0)],
+  );
+  assert_eq!(kind(&function), VerifierErrorKind::ConstantOutOfRange(3));
+}
+
+#[test]
+fn jump_into_operand() {
+  let function = script(
+    vec![
+      OpCode::Jump.into(),
+      0,
+      1,
+      OpCode::Constant.into(),
+      0,
+      OpCode::Return.into(),
+    ],
+    vec![Value::Number(1.0)],
+  );
+  assert_eq!(kind(&function), VerifierErrorKind::InvalidJumpTarget(4));
+}
+
+#[test]
+fn stack_underflow() {
+  let function = script(vec![OpCode::Pop.into(), OpCode::Add.into()], vec![]);
+  assert_eq!(
+    kind(&function),
+    VerifierErrorKind::StackUnderflow(OpCode::Add)
+  );
+}
+
+#[test]
+fn inconsistent_stack_depth() {
+  // One branch pushes an extra value before both paths meet at Return
+  let function = script(
+    vec![
+      OpCode::True.into(),
+      OpCode::JumpIfFalse.into(),
+      0,
+      1,
+      OpCode::Null.into(),
+      OpCode::Return.into(),
+    ],
+    vec![],
+  );
+  assert_eq!(
+    kind(&function),
+    VerifierErrorKind::InconsistentStackDepth {
+      expected: 3,
+      found: 2
+    }
+  );
+}
+
+#[test]
+fn falls_off_end() {
+  let function = script(vec![OpCode::Null.into()], vec![]);
+  assert_eq!(kind(&function), VerifierErrorKind::FallsOffEnd);
+}
+
+#[test]
+fn nested_functions_are_verified() {
+  let mut inner = script(
+    vec![OpCode::GetLocal.into(), 5, OpCode::Return.into()],
+    vec![],
+  );
+  inner.name = "inner".into();
+  let function = script(
+    vec![OpCode::Null.into(), OpCode::Return.into()],
+    vec![Value::from(inner)],
+  );
+  let error = verify(&function).unwrap_err();
+  assert_eq!(error.function, "<fn inner>");
+  assert_eq!(error.kind, VerifierErrorKind::InvalidLocal(5));
+}
+
+#[test]
+fn vm_rejects_malformed_chunks() {
+  let mut vm = VM::new();
+  let function = script(vec![OpCode::Add.into(), OpCode::Return.into()], vec![]);
+  match vm.execute(function) {
+    Err(CedarError::VerifierError(e)) => {
+      assert_eq!(e.kind, VerifierErrorKind::StackUnderflow(OpCode::Add))
+    }
+    other => panic!("Expected a verifier error, got {:?}", other),
+  }
+  // The VM is still usable afterwards
+  let function = script(vec![OpCode::Null.into(), OpCode::Return.into()], vec![]);
+  assert!(vm.execute(function).is_ok());
+}
+
+#[test]
+fn functions_from_the_host_are_verified() {
+  let mut vm = VM::new();
+  let function = script(vec![OpCode::Add.into(), OpCode::Return.into()], vec![]);
+  match vm.invoke(Value::from(function), &[]) {
+    Err(CedarError::VerifierError(e)) => {
+      assert_eq!(e.kind, VerifierErrorKind::StackUnderflow(OpCode::Add))
+    }
+    other => panic!("Expected a verifier error, got {:?}", other),
+  }
+}
+
+#[test]
+fn method_names_must_be_strings() {
+  let function = script(
+    vec![
+      OpCode::Null.into(),
+      OpCode::Invoke.into(),
+      0,
+      0,
+      OpCode::Return.into(),
+    ],
+    vec![Value::Number(1.0)],
+  );
+  assert_eq!(kind(&function), VerifierErrorKind::NonStringMethodName(0));
+}
@@ -0,0 +1,120 @@
+use cedar::{
+  vfs::{FileSystem, MemoryFs, OsFs, OverlayFs},
+  CedarError, VM,
+};
+use pretty_assertions::assert_eq;
+use std::{fs, path::Path};
+
+#[test]
+fn scripts_can_write_files_in_memory() -> Result<(), CedarError> {
+  let files = MemoryFs::new().with_file("notes/monday.txt", "Standup");
+  let mut vm = VM::new();
+  vm.set_fs(files.clone());
+  vm.interpret(
+    r#"
+let monday = read-file("notes/monday.txt");
+write-file("/notes/tuesday.txt", monday + " and planning");
+let tuesday = read-file("./notes/../notes/tuesday.txt");
+"#
+    .into(),
+  )?;
+  assert_eq!(
+    vm.get_global_as::<String>("tuesday")?,
+    "Standup and planning"
+  );
+  assert_eq!(
+    files.contents("notes/tuesday.txt").as_deref(),
+    Some("Standup and planning")
+  );
+  assert!(!Path::new("notes").exists());
+  Ok(())
+}
+
+#[test]
+fn memory_errors_match_the_os() {
+  let mut vm = VM::new();
+  vm.set_fs(MemoryFs::new().with_dir("empty"));
+  let error = vm.interpret(r#"read-file("missing");"#.into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Could not read 'missing': No such file or directory"
+  );
+  let error = vm.interpret(r#"read-file("empty");"#.into()).unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Could not read 'empty': Is a directory"
+  );
+  let error = vm
+    .interpret(r#"write-file("nowhere/file", "");"#.into())
+    .unwrap_err();
+  assert_eq!(
+    error.to_string(),
+    "[line 1] Error in script: Could not write 'nowhere/file': No such file or directory"
+  );
+}
+
+#[test]
+fn overlays_never_change_the_lower_layer() -> Result<(), CedarError> {
+  let overlay = OverlayFs::new(OsFs);
+  let upper = overlay.upper().clone();
+  let scratch = overlay.resolve(Path::new("src/scratch.cdr"));
+  let mut vm = VM::new();
+  vm.set_fs(overlay);
+  vm.interpret(
+    r#"
+let manifest = read-file("Cargo.toml");
+write-file("Cargo.toml", "[package]");
+write-file("src/scratch.cdr", "print 1;");
+let changed = read-file("Cargo.toml");
+"#
+    .into(),
+  )?;
+  let on_disk = fs::read_to_string("Cargo.toml")?;
+  assert_eq!(vm.get_global_as::<String>("manifest")?, on_disk);
+  assert_eq!(vm.get_global_as::<String>("changed")?, "[package]");
+  assert_eq!(upper.contents(&scratch).as_deref(), Some("print 1;"));
+  assert!(!Path::new("src/scratch.cdr").exists());
+  assert!(vm.fs().metadata(Path::new("src"))?.is_dir);
+
+  let error = vm
+    .interpret(r#"write-file("missing/file", "");"#.into())
+    .unwrap_err();
+  assert!(error.to_string().starts_with(
+    "[line 1] Error in script: Could not write 'missing/file': No such file or directory"
+  ));
+  Ok(())
+}
+
+#[test]
+fn overlays_resolve_relative_paths_against_their_root() -> Result<(), CedarError> {
+  let lower = MemoryFs::new()
+    .with_file("/work/notes.txt", "in work")
+    .with_file("/notes.txt", "at the root");
+  let mut vm = VM::new();
+  vm.set_fs(OverlayFs::new(lower).with_root("/work"));
+  vm.interpret(
+    r#"
+write-file("/notes.txt", "changed at the root");
+let relative = read-file("notes.txt");
+write-file("./notes.txt", "changed in work");
+let absolute = read-file("/work/notes.txt");
+let root = read-file("../notes.txt");
+remove("/work/notes.txt");
+let removed = exists("notes.txt");
+let kept = exists("/notes.txt");
+"#
+    .into(),
+  )?;
+  assert_eq!(vm.get_global_as::<String>("relative")?, "in work");
+  assert_eq!(vm.get_global_as::<String>("absolute")?, "changed in work");
+  assert_eq!(vm.get_global_as::<String>("root")?, "changed at the root");
+  assert!(!vm.get_global_as::<bool>("removed")?);
+  assert!(vm.get_global_as::<bool>("kept")?);
+
+  // Over the real filesystem the root is the current directory
+  let overlay = OverlayFs::new(OsFs);
+  let manifest = std::env::current_dir()?.join("Cargo.toml");
+  overlay.write(Path::new("Cargo.toml"), b"[package]")?;
+  assert_eq!(overlay.read_to_string(&manifest)?, "[package]");
+  Ok(())
+}